@@ -0,0 +1,159 @@
+//! # BlueR derive macros
+//!
+//! This crate provides the `#[derive(GattService)]` macro.
+//! Do not use it directly; instead enable the `derive` feature of [BlueR](https://docs.rs/bluer)
+//! and refer to the documentation of `bluer::gatt::local::GattService`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, Lit, LitStr, Result};
+
+/// Characteristic flags that can be specified by name.
+const CHARACTERISTIC_FLAGS: &[&str] = &[
+    "broadcast",
+    "read",
+    "write_without_response",
+    "write",
+    "notify",
+    "indicate",
+    "authenticated_signed_writes",
+    "reliable_write",
+    "writable_auxiliaries",
+    "encrypt_read",
+    "encrypt_write",
+    "encrypt_authenticated_read",
+    "encrypt_authenticated_write",
+    "secure_read",
+    "secure_write",
+    "authorize",
+];
+
+/// Bluetooth base UUID used to expand 16-bit and 32-bit UUIDs.
+const BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805f9b34fb;
+
+/// Derives `bluer::gatt::local::GattService` for a struct.
+///
+/// Refer to the documentation of the trait for the supported attributes.
+#[proc_macro_derive(GattService, attributes(gatt, characteristic))]
+pub fn derive_gatt_service(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "GattService requires a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "GattService can only be derived for structs")),
+    };
+
+    let mut uuid = None;
+    let mut primary = true;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("gatt")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("uuid") {
+                uuid = Some(parse_uuid(&meta.value()?.parse()?)?);
+                Ok(())
+            } else if meta.path.is_ident("secondary") {
+                primary = false;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported gatt attribute"))
+            }
+        })?;
+    }
+    let uuid = uuid.ok_or_else(|| {
+        Error::new_spanned(&input.ident, "missing service UUID, specify it using #[gatt(uuid = \"...\")]")
+    })?;
+
+    let mut characteristics = Vec::new();
+    for field in fields {
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("characteristic")) {
+            let ident = field.ident.as_ref().unwrap();
+            characteristics.push(characteristic(ident, attr)?);
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bluer::gatt::local::GattService for #name #ty_generics #where_clause {
+            fn service(&self) -> ::bluer::gatt::local::Service {
+                ::bluer::gatt::local::Service {
+                    uuid: #uuid,
+                    primary: #primary,
+                    characteristics: ::std::vec![#(#characteristics),*],
+                    ..::std::default::Default::default()
+                }
+            }
+        }
+    })
+}
+
+/// Generates the characteristic definition for a field.
+fn characteristic(field: &Ident, attr: &syn::Attribute) -> Result<TokenStream2> {
+    let mut uuid = None;
+    let mut flags = Vec::new();
+    let mut descriptors = Vec::new();
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("uuid") {
+            uuid = Some(parse_uuid(&meta.value()?.parse()?)?);
+        } else if meta.path.is_ident("descriptor") {
+            let mut desc_uuid = None;
+            let mut value = None;
+            meta.parse_nested_meta(|meta| {
+                if meta.path.is_ident("uuid") {
+                    desc_uuid = Some(parse_uuid(&meta.value()?.parse()?)?);
+                } else if meta.path.is_ident("value") {
+                    value = Some(match meta.value()?.parse()? {
+                        Lit::Str(s) => Literal::byte_string(s.value().as_bytes()),
+                        Lit::ByteStr(s) => Literal::byte_string(&s.value()),
+                        lit => return Err(Error::new_spanned(lit, "expected string or byte string")),
+                    });
+                } else {
+                    return Err(meta.error("unsupported descriptor attribute"));
+                }
+                Ok(())
+            })?;
+            let desc_uuid = desc_uuid.ok_or_else(|| meta.error("missing descriptor UUID"))?;
+            let value = value.ok_or_else(|| meta.error("missing descriptor value"))?;
+            descriptors.push(quote! {
+                ::bluer::gatt::local::Descriptor::with_value(#desc_uuid, &#value[..])
+            });
+        } else if let Some(flag) = CHARACTERISTIC_FLAGS.iter().find(|flag| meta.path.is_ident(flag)) {
+            let flag = Ident::new(flag, meta.path.get_ident().unwrap().span());
+            flags.push(quote! { #flag: true });
+        } else {
+            return Err(meta.error("unsupported characteristic attribute"));
+        }
+        Ok(())
+    })?;
+    let uuid = uuid.ok_or_else(|| Error::new_spanned(attr, "missing characteristic UUID"))?;
+
+    Ok(quote! {
+        ::bluer::gatt::local::CharacteristicValue::characteristic(
+            &self.#field,
+            #uuid,
+            ::bluer::gatt::CharacteristicFlags { #(#flags,)* ..::std::default::Default::default() },
+            ::std::vec![#(#descriptors),*],
+        )
+    })
+}
+
+/// Parses a UUID in full or in 16-bit or 32-bit short form.
+fn parse_uuid(lit: &LitStr) -> Result<TokenStream2> {
+    let value = lit.value();
+    let hex: String = value.chars().filter(|&c| c != '-').collect();
+    let parsed = match hex.len() {
+        _ if !hex.chars().all(|c| c.is_ascii_hexdigit()) => None,
+        4 | 8 => u32::from_str_radix(&hex, 16).ok().map(|short| (u128::from(short) << 96) | BASE_UUID),
+        32 => u128::from_str_radix(&hex, 16).ok(),
+        _ => None,
+    };
+    let uuid = parsed.ok_or_else(|| Error::new_spanned(lit, "invalid UUID"))?;
+    let uuid = Literal::u128_unsuffixed(uuid);
+    Ok(quote! { ::bluer::Uuid::from_u128(#uuid) })
+}
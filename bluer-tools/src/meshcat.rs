@@ -0,0 +1,655 @@
+//! Bluetooth mesh nodes and access messages.
+
+use bluer::{
+    mesh::{
+        agent::{Capabilities, DisplayNumeric, DisplayString, PromptNumeric, PromptStatic, ProvisionAgent},
+        application::{Application, ApplicationHandle},
+        models::{
+            config::ConfigMessage,
+            generic::{LevelMessage, OnOffMessage, GENERIC_LEVEL_CLIENT, GENERIC_ONOFF_CLIENT},
+            health::{HealthMessage, HEALTH_CLIENT},
+            sensor::{SensorMessage, SENSOR_CLIENT},
+        },
+        network::Network,
+        node::{Node, SendOptions},
+        provisioner::{Provisioner, ProvisionerControlHandle, UnicastAllocator},
+        AccessPayload, CompanyIdentifier, Destination, Element, ElementControl, ElementEvent, Message, Model,
+        ModelIdentifier, Opcode, ParseError, ReqError,
+    },
+    ErrorKind, Uuid,
+};
+use clap::Parser;
+use dbus::Path;
+use futures::{pin_mut, StreamExt};
+use std::{fmt::Write as _, fs, path::PathBuf, process::exit, sync::Arc};
+use tokio::{
+    io::{stdin, AsyncBufReadExt, AsyncReadExt, BufReader, Lines, Stdin},
+    select, signal,
+    sync::mpsc,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// D-Bus path of the application.
+const ROOT_PATH: &str = "/meshcat";
+
+/// Index of the network key used for the network created by meshcat.
+const NET_INDEX: u16 = 0;
+
+#[derive(Parser)]
+#[clap(
+    name = "meshcat",
+    about = "Bluetooth mesh nodes and access messages.",
+    author = "Sebastian Urban <surban@surban.net>",
+    version = env!("CARGO_PKG_VERSION"),
+)]
+struct Opts {
+    /// File storing the token and configuration of the node.
+    ///
+    /// Defaults to meshcat/state in the user's configuration directory.
+    #[clap(long, short)]
+    state: Option<PathBuf>,
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Parser)]
+enum Cmd {
+    /// Join a mesh network as an unprovisioned device and wait for provisioning.
+    Join(JoinOpts),
+    /// Create a new mesh network with the local node as provisioner.
+    Create(JoinOpts),
+    /// Attach to the mesh network and show the configuration of the node.
+    Attach,
+    /// Leave the mesh network, removing the node.
+    Leave,
+    /// Scan for unprovisioned devices.
+    Scan(ScanOpts),
+    /// Provision an unprovisioned device into the network.
+    Provision(ProvisionOpts),
+    /// Send access messages to a destination.
+    Send(SendOpts),
+    /// Publish access messages from a model of the node.
+    Publish(PublishOpts),
+    /// Print received access messages.
+    Monitor,
+}
+
+#[derive(Parser)]
+struct JoinOpts {
+    /// Device UUID of the node. Random if not specified.
+    #[clap(long, short)]
+    uuid: Option<Uuid>,
+    /// Model of the node, either a 16-bit SIG model identifier or
+    /// a company identifier and 16-bit vendor model identifier separated by a colon,
+    /// all in hexadecimal.
+    /// Defaults to the Generic OnOff, Generic Level, Health and Sensor client models.
+    #[clap(long, short, parse(try_from_str = parse_model))]
+    model: Vec<ModelIdentifier>,
+}
+
+#[derive(Parser)]
+struct ScanOpts {
+    /// Scan duration in seconds.
+    #[clap(long, short, default_value = "10")]
+    seconds: u16,
+}
+
+#[derive(Parser)]
+struct ProvisionOpts {
+    /// Device UUID of the unprovisioned device.
+    uuid: Uuid,
+}
+
+#[derive(Parser)]
+struct MessageOpts {
+    /// Read a single message as raw binary data from standard input.
+    #[clap(long, short)]
+    raw: bool,
+    /// Access message consisting of opcode and parameters in hexadecimal.
+    ///
+    /// If not specified, messages are read line by line from standard input.
+    message: Option<String>,
+}
+
+#[derive(Parser)]
+struct SendOpts {
+    /// Index of the application key to encrypt the message with.
+    #[clap(long, short, default_value = "0")]
+    app_key: u16,
+    /// Encrypt the message with the device key of the remote node instead.
+    #[clap(long, short)]
+    dev_key: bool,
+    /// Force segmentation of the message.
+    #[clap(long)]
+    segmented: bool,
+    /// Unicast or group destination address in hexadecimal.
+    #[clap(parse(try_from_str = parse_destination))]
+    destination: Destination,
+    #[clap(flatten)]
+    message: MessageOpts,
+}
+
+#[derive(Parser)]
+struct PublishOpts {
+    /// Force segmentation of the message.
+    #[clap(long)]
+    segmented: bool,
+    /// Publishing model of the node.
+    #[clap(parse(try_from_str = parse_model))]
+    model: ModelIdentifier,
+    #[clap(flatten)]
+    message: MessageOpts,
+}
+
+/// Persistent state of the node.
+#[derive(Default)]
+struct State {
+    path: PathBuf,
+    token: Option<u64>,
+    uuid: Option<Uuid>,
+    models: Vec<ModelIdentifier>,
+    /// Nodes provisioned by this node as primary address and element count.
+    nodes: Vec<(u16, u8)>,
+}
+
+impl State {
+    fn load(path: Option<PathBuf>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let config = match std::env::var_os("XDG_CONFIG_HOME") {
+                    Some(config) => PathBuf::from(config),
+                    None => PathBuf::from(std::env::var_os("HOME").ok_or("HOME is not set")?).join(".config"),
+                };
+                config.join("meshcat").join("state")
+            }
+        };
+
+        let mut state = Self { path, ..Default::default() };
+        let content = match fs::read_to_string(&state.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(state),
+            Err(err) => return Err(err.into()),
+        };
+
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (key, value) = line.split_once('=').ok_or_else(|| format!("invalid state line: {}", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "token" => state.token = Some(u64::from_str_radix(value, 16)?),
+                "uuid" => state.uuid = Some(value.parse()?),
+                "model" => state.models.push(parse_model(value)?),
+                "node" => {
+                    let (unicast, count) =
+                        value.split_once(' ').ok_or_else(|| format!("invalid node in state: {}", value))?;
+                    state.nodes.push((u16::from_str_radix(unicast, 16)?, count.trim().parse()?));
+                }
+                other => return Err(format!("unknown state key: {}", other).into()),
+            }
+        }
+        Ok(state)
+    }
+
+    fn save(&self) -> Result<()> {
+        let mut content = String::new();
+        if let Some(token) = self.token {
+            writeln!(content, "token = {:016x}", token)?;
+        }
+        if let Some(uuid) = self.uuid {
+            writeln!(content, "uuid = {}", uuid)?;
+        }
+        for model in &self.models {
+            writeln!(content, "model = {}", format_model(model))?;
+        }
+        for (unicast, count) in &self.nodes {
+            writeln!(content, "node = {:04x} {}", unicast, count)?;
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+
+    fn remove(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn token(&self) -> Result<u64> {
+        self.token.ok_or_else(|| "not joined to a mesh network, use join or create first".into())
+    }
+}
+
+fn parse_model(s: &str) -> std::result::Result<ModelIdentifier, String> {
+    let parse = |s: &str| u16::from_str_radix(s, 16).map_err(|err| format!("invalid model {}: {}", s, err));
+    match s.split_once(':') {
+        Some((company, model)) => Ok(ModelIdentifier::Vendor(CompanyIdentifier(parse(company)?), parse(model)?)),
+        None => Ok(ModelIdentifier::SIG(parse(s)?)),
+    }
+}
+
+fn format_model(model: &ModelIdentifier) -> String {
+    match model {
+        ModelIdentifier::SIG(id) => format!("{:04x}", id),
+        ModelIdentifier::Vendor(company, id) => format!("{:04x}:{:04x}", company.0, id),
+    }
+}
+
+fn parse_destination(s: &str) -> std::result::Result<Destination, String> {
+    if Uuid::parse_str(s).is_ok() {
+        return Err("sending to a virtual address is not supported, publish to it instead".into());
+    }
+    let address = u16::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|err| err.to_string())?;
+    Destination::from_address(address).ok_or_else(|| format!("invalid destination address {:04x}", address))
+}
+
+/// Model whose messages are not handled by meshcat itself.
+#[derive(Clone, Debug)]
+struct RawModel(ModelIdentifier);
+
+impl Model for RawModel {
+    fn identifier(&self) -> ModelIdentifier {
+        self.0
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(
+        _opcode: Opcode, _parameters: &'m [u8],
+    ) -> std::result::Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(None)
+    }
+}
+
+/// Access message sent as is.
+struct RawMessage(AccessPayload);
+
+impl Message for RawMessage {
+    fn opcode(&self) -> Opcode {
+        self.0.opcode
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        xmit.extend_from_slice(&self.0.parameters);
+    }
+}
+
+/// Registered mesh application of meshcat.
+struct App {
+    network: Network,
+    element_control: ElementControl,
+    _handle: ApplicationHandle,
+}
+
+impl App {
+    fn root_path() -> Path<'static> {
+        Path::from(ROOT_PATH)
+    }
+
+    fn element_path() -> Path<'static> {
+        Path::from(format!("{}/ele00", ROOT_PATH))
+    }
+
+    async fn register(state: &State) -> Result<Self> {
+        let session = bluer::Session::new().await?;
+        let network = session.mesh().await?;
+
+        let models = if state.models.is_empty() {
+            vec![GENERIC_ONOFF_CLIENT, GENERIC_LEVEL_CLIENT, HEALTH_CLIENT, SENSOR_CLIENT]
+        } else {
+            state.models.clone()
+        };
+        let (element_control, element_handle) = bluer::mesh::element_control();
+        let (provisioner_tx, _) = mpsc::channel(1);
+
+        let app = Application {
+            path: Path::from(format!("{}/application", ROOT_PATH)),
+            elements: vec![Element {
+                path: Self::element_path(),
+                models: models.into_iter().map(|model| Arc::new(RawModel(model)) as Arc<dyn Model>).collect(),
+                control_handle: Some(element_handle),
+                location: None,
+            }],
+            provisioner: Some(Provisioner {
+                control_handle: ProvisionerControlHandle { messages_tx: provisioner_tx },
+                address_allocator: Arc::new(UnicastAllocator::with_nodes(NET_INDEX, state.nodes.iter().copied())),
+            }),
+            agent: agent(),
+            ..Default::default()
+        };
+        let handle = network.application(Self::root_path(), app).await?;
+
+        Ok(Self { network, element_control, _handle: handle })
+    }
+
+    async fn attach(&self, state: &State) -> Result<Node> {
+        Ok(self.network.attach(Self::root_path(), state.token()?).await?)
+    }
+}
+
+/// Provisioning agent interacting through the terminal.
+fn agent() -> ProvisionAgent {
+    ProvisionAgent {
+        capabilities: Capabilities { out_numeric: true, out_alpha: true, static_oob: true, ..Default::default() },
+        display_numeric: Some(Box::new(|req: DisplayNumeric| {
+            Box::pin(async move {
+                eprintln!("Authenticate using {}: {}", req.display_type, req.number);
+                Ok(())
+            })
+        })),
+        display_string: Some(Box::new(|req: DisplayString| {
+            Box::pin(async move {
+                eprintln!("Authenticate using: {}", req.value);
+                Ok(())
+            })
+        })),
+        prompt_numeric: Some(Box::new(|req: PromptNumeric| {
+            Box::pin(async move {
+                eprintln!("Enter {} value:", req.prompt_type);
+                read_line().await?.parse().map_err(|_| ReqError::Failed)
+            })
+        })),
+        prompt_static: Some(Box::new(|req: PromptStatic| {
+            Box::pin(async move {
+                eprintln!("Enter {} value as 32 hex digits:", req.prompt_type);
+                let mut value = [0; 16];
+                hex::decode_to_slice(read_line().await?, &mut value).map_err(|_| ReqError::Failed)?;
+                Ok(value)
+            })
+        })),
+        ..Default::default()
+    }
+}
+
+async fn read_line() -> std::result::Result<String, ReqError> {
+    let mut line = String::new();
+    BufReader::new(stdin()).read_line(&mut line).await.map_err(|_| ReqError::Failed)?;
+    Ok(line.trim().to_string())
+}
+
+impl JoinOpts {
+    async fn perform(self, mut state: State, create: bool) -> Result<()> {
+        if state.token.is_some() {
+            return Err(format!(
+                "already joined, leave first or use another state file than {}",
+                state.path.display()
+            )
+            .into());
+        }
+
+        let uuid = self.uuid.unwrap_or_else(Uuid::new_v4);
+        state.uuid = Some(uuid);
+        state.models = self.model;
+        let app = App::register(&state).await?;
+
+        let token = if create {
+            let token = app.network.create_network(App::root_path(), uuid).await?;
+            state.nodes.push((0x0001, 1));
+            token
+        } else {
+            eprintln!("Waiting to be provisioned as device {}", uuid);
+            app.network.join(App::root_path(), uuid).await?
+        };
+        state.token = Some(token);
+        state.save()?;
+        println!("Joined network with token {:016x}", token);
+
+        if create {
+            let node = app.attach(&state).await?;
+            if let Some(management) = &node.management {
+                match management.create_app_key(NET_INDEX, 0).await {
+                    Err(err) if err.kind != ErrorKind::AlreadyExists => return Err(err.into()),
+                    _ => (),
+                }
+            }
+            println!("Created application key 0");
+        }
+
+        Ok(())
+    }
+}
+
+async fn attach(state: State) -> Result<()> {
+    let app = App::register(&state).await?;
+    let _node = app.attach(&state).await?;
+
+    let config = app.element_control.configuration();
+    for (model, config) in &config.models {
+        println!("Model {}", format_model(model));
+        println!("  Bindings      {:?}", config.bindings);
+        if let Some(period) = config.publication_period {
+            println!("  Publication   every {:?}", period);
+        }
+        println!("  Subscriptions {:?}", config.subscriptions);
+    }
+    Ok(())
+}
+
+async fn leave(state: State) -> Result<()> {
+    let session = bluer::Session::new().await?;
+    let network = session.mesh().await?;
+    network.leave(state.token()?).await?;
+    state.remove()?;
+    println!("Left network");
+    Ok(())
+}
+
+impl ScanOpts {
+    async fn perform(self, state: State) -> Result<()> {
+        let app = App::register(&state).await?;
+        let node = app.attach(&state).await?;
+        let management = node.management.as_ref().ok_or("node is not a provisioner")?;
+
+        let scan = management.unprovisioned_scan(Some(self.seconds)).await?;
+        pin_mut!(scan);
+        loop {
+            select! {
+                result = scan.next() => match result {
+                    Some(result) => println!("{} RSSI {} dBm OOB {:?}", result.uuid, result.rssi, result.oob_info),
+                    None => break,
+                },
+                _ = signal::ctrl_c() => {
+                    management.unprovisioned_scan_cancel().await?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProvisionOpts {
+    async fn perform(self, mut state: State) -> Result<()> {
+        let app = App::register(&state).await?;
+        let node = app.attach(&state).await?;
+        let management = node.management.as_ref().ok_or("node is not a provisioner")?;
+
+        eprintln!("Provisioning device {}", self.uuid);
+        let added = management.add_node(self.uuid).await?;
+        state.nodes.push((added.unicast, added.count));
+        state.save()?;
+        println!("Added node {} with {} elements at {:04x}", added.uuid, added.count, added.unicast);
+        Ok(())
+    }
+}
+
+/// Source of access messages to send.
+enum Messages {
+    Argument(Option<AccessPayload>),
+    Lines(Lines<BufReader<Stdin>>),
+    Raw(bool),
+}
+
+impl Messages {
+    fn new(opts: MessageOpts) -> Result<Self> {
+        Ok(match opts.message {
+            Some(message) => Self::Argument(Some(AccessPayload::parse(&hex::decode(message.trim())?)?)),
+            None if opts.raw => Self::Raw(false),
+            None => Self::Lines(BufReader::new(stdin()).lines()),
+        })
+    }
+
+    async fn next(&mut self) -> Result<Option<AccessPayload>> {
+        match self {
+            Self::Argument(message) => Ok(message.take()),
+            Self::Lines(lines) => loop {
+                match lines.next_line().await? {
+                    Some(line) if line.trim().is_empty() => continue,
+                    Some(line) => return Ok(Some(AccessPayload::parse(&hex::decode(line.trim())?)?)),
+                    None => return Ok(None),
+                }
+            },
+            Self::Raw(done) if *done => Ok(None),
+            Self::Raw(done) => {
+                *done = true;
+                let mut data = Vec::new();
+                stdin().read_to_end(&mut data).await?;
+                Ok(Some(AccessPayload::parse(&data)?))
+            }
+        }
+    }
+}
+
+impl SendOpts {
+    async fn perform(self, state: State) -> Result<()> {
+        let app = App::register(&state).await?;
+        let node = app.attach(&state).await?;
+        let options = SendOptions { force_segmented: self.segmented, ..Default::default() };
+
+        let mut messages = Messages::new(self.message)?;
+        while let Some(payload) = messages.next().await? {
+            let message = RawMessage(payload);
+            if self.dev_key {
+                let dest = match self.destination {
+                    Destination::Unicast(dest) => dest,
+                    _ => return Err("device key messages must be sent to a unicast address".into()),
+                };
+                node.dev_key_send(App::element_path(), dest, true, NET_INDEX, options.clone(), &message).await?;
+            } else {
+                node.send(App::element_path(), self.destination, self.app_key, options.clone(), &message).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PublishOpts {
+    async fn perform(self, state: State) -> Result<()> {
+        let app = App::register(&state).await?;
+        let node = app.attach(&state).await?;
+        let options = SendOptions { force_segmented: self.segmented, ..Default::default() };
+
+        let mut messages = Messages::new(self.message)?;
+        while let Some(payload) = messages.next().await? {
+            node.publish(App::element_path(), self.model, options.clone(), &RawMessage(payload)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a message of a model known to BlueR.
+fn decode(payload: &AccessPayload) -> Option<String> {
+    let (opcode, parameters) = (&payload.opcode, payload.parameters.as_slice());
+    if let Ok(Some(msg)) = OnOffMessage::parse(opcode, parameters) {
+        Some(format!("{:?}", msg))
+    } else if let Ok(Some(msg)) = LevelMessage::parse(opcode, parameters) {
+        Some(format!("{:?}", msg))
+    } else if let Ok(Some(msg)) = HealthMessage::parse(opcode, parameters) {
+        Some(format!("{:?}", msg))
+    } else if let Ok(Some(SensorMessage::Status(values))) = SensorMessage::parse(opcode, parameters) {
+        let values: Vec<_> = values
+            .iter()
+            .map(|value| match (value.value(), value.unit()) {
+                (Ok(Some(v)), Some(unit)) => format!("{} = {} {}", value.property, v, unit),
+                (Ok(None), _) => format!("{} unknown", value.property),
+                _ => format!("{} = {}", value.property, hex::encode(&value.raw)),
+            })
+            .collect();
+        Some(format!("SensorStatus [{}]", values.join(", ")))
+    } else if let Ok(Some(msg)) = SensorMessage::parse(opcode, parameters) {
+        Some(format!("{:?}", msg))
+    } else if let Ok(Some(msg)) = ConfigMessage::parse(opcode, parameters) {
+        Some(format!("{:?}", msg))
+    } else {
+        None
+    }
+}
+
+async fn monitor(state: State) -> Result<()> {
+    let app = App::register(&state).await?;
+    let _node = app.attach(&state).await?;
+    let mut events = app.element_control;
+
+    loop {
+        let event = select! {
+            event = events.next() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = signal::ctrl_c() => break,
+        };
+
+        let (header, payload) = match event {
+            ElementEvent::Message(msg) => {
+                (format!("{:04x} -> {:?} app key {}", msg.src, msg.dest, msg.key), msg.payload)
+            }
+            ElementEvent::DevKeyMessage(msg) => (
+                format!(
+                    "{:04x} -> {} device key, net key {}",
+                    msg.src,
+                    if msg.remote { "remote" } else { "local" },
+                    msg.net_index
+                ),
+                msg.payload,
+            ),
+        };
+        match decode(&payload) {
+            Some(decoded) => println!("{}: {}", header, decoded),
+            None => println!("{}: {:?} {}", header, payload.opcode, hex::encode(&payload.parameters)),
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let opts: Opts = Opts::parse();
+
+    let result = match State::load(opts.state) {
+        Ok(state) => match opts.cmd {
+            Cmd::Join(j) => j.perform(state, false).await,
+            Cmd::Create(c) => c.perform(state, true).await,
+            Cmd::Attach => attach(state).await,
+            Cmd::Leave => leave(state).await,
+            Cmd::Scan(s) => s.perform(state).await,
+            Cmd::Provision(p) => p.perform(state).await,
+            Cmd::Send(s) => s.perform(state).await,
+            Cmd::Publish(p) => p.perform(state).await,
+            Cmd::Monitor => monitor(state).await,
+        },
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(_) => exit(0),
+        Err(err) => {
+            eprintln!("Error: {}", &err);
+            exit(2);
+        }
+    }
+}
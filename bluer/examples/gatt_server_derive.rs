@@ -0,0 +1,81 @@
+//! Serves a Bluetooth GATT application defined using `#[derive(GattService)]`.
+
+use bluer::{
+    adv::Advertisement,
+    gatt::local::{CharacteristicValue, GattService},
+};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    time::sleep,
+};
+
+include!("gatt.inc");
+
+/// Example service; UUIDs match those of the other GATT examples.
+#[derive(Clone, GattService)]
+#[gatt(uuid = "00000000-0000-0000-0000-0000feedc0de")]
+struct Example {
+    #[characteristic(
+        uuid = "00000000-0000-0000-000f-00dc0de00001",
+        read,
+        write,
+        write_without_response,
+        notify,
+        descriptor(uuid = "2901", value = "Example value")
+    )]
+    value: CharacteristicValue<Vec<u8>>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> bluer::Result<()> {
+    env_logger::init();
+    let session = bluer::Session::new().await?;
+    let adapter = session.default_adapter().await?;
+    adapter.set_powered(true).await?;
+
+    println!("Advertising on Bluetooth adapter {} with address {}", adapter.name(), adapter.address().await?);
+    let mut manufacturer_data = BTreeMap::new();
+    manufacturer_data.insert(MANUFACTURER_ID, vec![0x21, 0x22, 0x23, 0x24]);
+    let le_advertisement = Advertisement {
+        service_uuids: vec![SERVICE_UUID].into_iter().collect(),
+        manufacturer_data,
+        discoverable: Some(true),
+        local_name: Some("gatt_server".to_string()),
+        ..Default::default()
+    };
+    let adv_handle = adapter.advertise(le_advertisement).await?;
+
+    println!("Serving GATT service on Bluetooth adapter {}", adapter.name());
+    let example = Example { value: CharacteristicValue::new(vec![0x10, 0x01, 0x01, 0x10]) };
+    assert_eq!(example.service().characteristics[0].uuid, CHARACTERISTIC_UUID);
+    let app_handle = adapter.serve_gatt_application(example.application()).await?;
+
+    let mut changes = example.value.watch();
+    tokio::spawn(async move {
+        while changes.changed().await.is_ok() {
+            println!("Value changed to {:x?}", &*changes.borrow_and_update());
+        }
+    });
+
+    let value = example.value.clone();
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(5)).await;
+            println!("Decrementing each element by one");
+            value.set(value.get().into_iter().map(|v| v.saturating_sub(1)).collect());
+        }
+    });
+
+    println!("Service ready. Press enter to quit.");
+    let stdin = BufReader::new(tokio::io::stdin());
+    let mut lines = stdin.lines();
+    let _ = lines.next_line().await;
+
+    println!("Removing service and advertisement");
+    drop(app_handle);
+    drop(adv_handle);
+    sleep(Duration::from_secs(1)).await;
+
+    Ok(())
+}
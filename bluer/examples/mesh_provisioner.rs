@@ -1,4 +1,3 @@
-#![feature(generic_associated_types)]
 //! Attach and send/receive BT Mesh messages
 //!
 //! Example meshd
@@ -7,21 +6,32 @@
 //! Example receive
 //! [bluer]$ RUST_LOG=TRACE cargo run --example mesh_sensor_client -- --token 7eb48c91911361da
 //!
-//! Example send
-//! [burrboard/gateway]$ TOKEN=dae519a06e504bd3 ./app/temp-device.py
+//! Example unprovisioned device to provision
+//! [bluer-tools]$ cargo run --bin meshcat -- --state /tmp/meshcat-device join
 
-use bluer::{mesh::{application::Application, *, provisioner::{ProvisionerControlHandle, Provisioner}}, Uuid};
+use bluer::{
+    mesh::{
+        agent::{Capabilities, DisplayNumeric, PromptStatic, ProvisionAgent},
+        application::Application,
+        cdb::MeshCdb,
+        dispatcher::Dispatcher,
+        models::config::{ConfigClient, CONFIGURATION_CLIENT, CONFIGURATION_SERVER},
+        node::Node,
+        provisioner::{Provisioner, ProvisionerControlHandle, ProvisionerMessage, UnicastAllocator},
+        *,
+    },
+    ErrorKind, Uuid,
+};
 use clap::Parser;
 use dbus::Path;
-use drogue_device::drivers::ble::mesh::{
-    model::{
-        foundation::configuration::{ConfigurationServer, ConfigurationClient},
-    },
+use futures::{pin_mut, StreamExt};
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+    io::{stdin, AsyncBufReadExt, BufReader},
+    signal,
+    sync::mpsc,
 };
-use futures::{StreamExt};
-use tokio::{signal, sync::mpsc};
 use tokio_stream::wrappers::ReceiverStream;
-use std::sync::Arc;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -29,7 +39,10 @@ struct Args {
     #[clap(short, long)]
     token: String,
     #[clap(short, long)]
-    uuid: String,
+    uuid: Option<String>,
+    /// Mesh configuration database file, created if it does not exist.
+    #[clap(long)]
+    cdb: Option<PathBuf>,
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -40,7 +53,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mesh = session.mesh().await?;
 
-    let (_, element_handle) = element_control();
+    let mut cdb = match &args.cdb {
+        Some(path) if path.exists() => Some(MeshCdb::load(path)?),
+        Some(_) => Some(MeshCdb::new("bluer", Uuid::new_v4())),
+        None => None,
+    };
+    let address_allocator = match &cdb {
+        Some(cdb) => cdb.unicast_allocator(NET_INDEX),
+        None => UnicastAllocator::with_range(NET_INDEX, 0x00bd..=0x7fff),
+    };
+
+    let (element_control, element_handle) = element_control();
+    let config_client = ConfigClient::new();
 
     let root_path = Path::from("/mesh/cfgclient");
     let app_path = Path::from(format!("{}/{}", root_path.clone(), "application"));
@@ -48,48 +72,177 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let (prov_tx, prov_rx) = mpsc::channel(1);
 
+    let element = Element {
+        path: element_path.clone(),
+        models: vec![Arc::new(FoundationModel(CONFIGURATION_SERVER)), Arc::new(config_client.clone())],
+        control_handle: Some(element_handle),
+        location: None,
+    };
     let sim = Application {
         path: app_path,
-        elements: vec![Element {
-            path: element_path,
-            models: vec![
-                Arc::new(FromDrogue::new(ConfigurationServer::default())),
-                Arc::new(FromDrogue::new(ConfigurationClient::default())),
-            ],
-            control_handle: Some(element_handle),
-        }],
+        elements: vec![element.clone()],
         provisioner: Some(Provisioner {
-            control_handle: ProvisionerControlHandle {
-                messages_tx: prov_tx,
-            }
+            control_handle: ProvisionerControlHandle { messages_tx: prov_tx },
+            address_allocator: Arc::new(address_allocator),
         }),
+        agent: ProvisionAgent {
+            capabilities: Capabilities { out_numeric: true, static_oob: true, ..Default::default() },
+            display_numeric: Some(Box::new(|req: DisplayNumeric| {
+                Box::pin(async move {
+                    println!("{}: {}", req.display_type, req.number);
+                    Ok(())
+                })
+            })),
+            prompt_static: Some(Box::new(|req: PromptStatic| {
+                Box::pin(async move {
+                    println!("Enter {} value as 32 hex digits:", req.prompt_type);
+                    let mut line = String::new();
+                    BufReader::new(stdin()).read_line(&mut line).await.map_err(|_| ReqError::Failed)?;
+                    let mut value = [0; 16];
+                    hex::decode_to_slice(line.trim(), &mut value).map_err(|_| ReqError::Failed)?;
+                    Ok(value)
+                })
+            })),
+            ..Default::default()
+        },
+        ..Default::default()
     };
 
     let _registered = mesh.application(root_path.clone(), sim).await?;
 
-    let node = mesh.attach(root_path.clone(), &args.token).await?;
+    let node = mesh.attach(root_path.clone(), u64::from_str_radix(&args.token, 16)?).await?;
+
+    let dispatcher = Dispatcher::new(node.clone(), &element).handle(&config_client, config_client.handler());
+
+    if let Some(management) = &node.management {
+        match management.create_app_key(NET_INDEX, APP_INDEX).await {
+            Err(err) if err.kind != ErrorKind::AlreadyExists => return Err(err.into()),
+            _ => (),
+        }
+
+        let uuid = match &args.uuid {
+            Some(uuid) => Some(Uuid::parse_str(uuid)?),
+            None => {
+                println!("Scanning for unprovisioned devices");
+                let scan = management.unprovisioned_scan(Some(10)).await?;
+                pin_mut!(scan);
+                let found = scan.next().await;
+                management.unprovisioned_scan_cancel().await?;
+                found.map(|result| {
+                    println!("Found device {} with RSSI {} dBm", result.uuid, result.rssi);
+                    result.uuid
+                })
+            }
+        };
 
-    if let Some(management) = node.management {
-        management.add_node(Uuid::parse_str(&args.uuid)?).await?;
+        match uuid {
+            Some(uuid) => {
+                let added = management.add_node(uuid).await?;
+                println!("Added node {} with {} elements at {:04x}", added.uuid, added.count, added.unicast);
+            }
+            None => println!("No unprovisioned device found"),
+        }
     }
 
     let mut prov_stream = ReceiverStream::new(prov_rx);
-
-    loop {
-        tokio::select! {
-            _ = signal::ctrl_c() => break,
-            evt = prov_stream.next() => {
-                match evt {
-                    Some(msg) => {
-                        println!("msg {:?}", msg);
-                    },
-                    None => break,
+    let provisioning = async {
+        while let Some(msg) = prov_stream.next().await {
+            match msg {
+                ProvisionerMessage::AddNodeComplete { uuid, unicast, count } => {
+                    if let Some(cdb) = &mut cdb {
+                        cdb.add_node(uuid, unicast, count, NET_INDEX);
+                    }
+                    if let Err(err) =
+                        configure(&node, &config_client, element_path.clone(), unicast, cdb.as_mut()).await
+                    {
+                        println!("Configuring node {:04x} failed: {}", unicast, err);
+                    }
+                    if let (Some(cdb), Some(path), Some(management)) = (&mut cdb, &args.cdb, &node.management) {
+                        match management.export_keys().await {
+                            Ok(keys) => cdb.update_keys(&keys),
+                            Err(err) => println!("Exporting keys failed: {}", err),
+                        }
+                        if let Err(err) = cdb.save(path) {
+                            println!("Saving configuration database failed: {}", err);
+                        }
+                    }
                 }
-            },
+                msg => println!("msg {:?}", msg),
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = signal::ctrl_c() => (),
+        res = dispatcher.run(element_control) => res?,
+        () = provisioning => (),
+    }
+
+    Ok(())
+}
+
+const NET_INDEX: u16 = 0;
+const APP_INDEX: u16 = 0;
+
+/// Adds the application key to a newly provisioned node and binds it to all its models.
+async fn configure(
+    node: &Node, config: &ConfigClient, path: Path<'_>, unicast: u16, mut cdb: Option<&mut MeshCdb>,
+) -> bluer::Result<()> {
+    let composition = config.composition_data(node, path.clone(), unicast, NET_INDEX).await?;
+    if let Some(cdb) = cdb.as_deref_mut() {
+        cdb.set_composition(unicast, &composition);
+    }
+    println!(
+        "Node {:04x}: company {:04x}, product {:04x}, {} elements",
+        unicast,
+        composition.company_id.0,
+        composition.product_id,
+        composition.elements.len()
+    );
+
+    config.app_key_add(node, path.clone(), unicast, NET_INDEX, APP_INDEX).await?;
+    if let Some(cdb) = cdb.as_deref_mut() {
+        cdb.add_node_app_key(unicast, APP_INDEX);
+    }
+
+    for (element, address) in composition.elements.iter().zip(unicast..) {
+        for model in &element.models {
+            if *model == CONFIGURATION_SERVER || *model == CONFIGURATION_CLIENT {
+                continue;
+            }
+            config.model_app_bind(node, path.clone(), unicast, NET_INDEX, address, APP_INDEX, *model).await?;
+            println!("Bound application key {} to {:?} on {:04x}", APP_INDEX, model, address);
+            if let Some(cdb) = cdb.as_deref_mut() {
+                cdb.bind_model(address, *model, APP_INDEX);
+            }
         }
     }
 
-    //TODO unregister
+    if let Some(node) = cdb.and_then(|cdb| cdb.node_mut(unicast)) {
+        node.config_complete = true;
+    }
 
     Ok(())
 }
+
+/// Foundation model whose messages are handled by the mesh daemon.
+#[derive(Clone, Debug)]
+pub struct FoundationModel(ModelIdentifier);
+
+impl Model for FoundationModel {
+    fn identifier(&self) -> ModelIdentifier {
+        self.0
+    }
+    fn supports_subscription(&self) -> bool {
+        false
+    }
+    fn supports_publication(&self) -> bool {
+        false
+    }
+    fn parse<'m>(_opcode: Opcode, _parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(None)
+    }
+}
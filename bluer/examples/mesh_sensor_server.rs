@@ -1,4 +1,3 @@
-#![feature(generic_associated_types)]
 //! Attach and send/receive BT Mesh messages
 //!
 //! Example meshd
@@ -7,30 +6,30 @@
 //! Example receive
 //! [bluer/bluer-tools]$ RUST_LOG=TRACE cargo run --example mesh_sensor_server -- --token 7eb48c91911361da
 //!
-//! Example send
-//! [burrboard/gateway]$ TOKEN=dae519a06e504bd3 ./app/temp-device.py
-
-use bluer::mesh::{application::Application, *};
-use clap::Parser;
-use dbus::Path;
-use drogue_device::drivers::ble::mesh::{
-    composition::CompanyIdentifier,
-    model::{
-        sensor::{
-            PropertyId, SensorConfig, SensorData, SensorDescriptor, SensorMessage, SensorServer, SensorStatus,
-        },
-        Message, Model,
+//! Omit the token to join the network as an unprovisioned device first.
+//!
+//! Example monitor of the published sensor values
+//! [bluer-tools]$ cargo run --bin meshcat -- monitor
+
+use bluer::{
+    mesh::{
+        application::Application,
+        models::sensor::{PropertyId, SensorDescriptor, SensorServer, SENSOR_SERVER},
+        node::SendOptions,
+        *,
     },
-    pdu::ParseError,
+    Uuid,
 };
-use tokio::{signal, sync::mpsc, time, time::Duration};
+use clap::Parser;
+use dbus::Path;
 use std::sync::Arc;
+use tokio::{signal, sync::mpsc, time, time::Duration};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
     #[clap(short, long)]
-    token: String,
+    token: Option<String>,
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -41,7 +40,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mesh = session.mesh().await?;
 
-    let (_element_control, element_handle) = element_control();
+    let (element_control, element_handle) = element_control();
+    let sensor = SensorServer::new([SensorDescriptor::new(PropertyId::PRESENT_AMBIENT_TEMPERATURE)]);
 
     let root_path = Path::from("/mesh_server");
     let app_path = Path::from(format!("{}/{}", root_path.clone(), "application"));
@@ -51,17 +51,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         path: app_path,
         elements: vec![Element {
             path: element_path.clone(),
-            models: vec![Arc::new(FromDrogue::new(BoardSensor::new()))],
+            models: vec![Arc::new(sensor.clone())],
             control_handle: Some(element_handle),
+            location: None,
         }],
         ..Default::default()
     };
 
-    let _registered = mesh.application(root_path.clone(), sim.clone()).await?;
+    let _registered = mesh.application(root_path.clone(), sim).await?;
 
-    let node = mesh.attach(root_path.clone(), &args.token).await?;
+    let token = match args.token {
+        Some(token) => u64::from_str_radix(&token, 16)?,
+        None => {
+            let uuid = Uuid::new_v4();
+            println!("Waiting to be provisioned as device {}", uuid);
+            let token = mesh.join(root_path.clone(), uuid).await?;
+            println!("Joined network, use --token {:016x} to attach next time", token);
+            token
+        }
+    };
 
-    println!("{:?}", sim.path);
+    let node = mesh.attach(root_path.clone(), token).await?;
 
     println!("Sensor server ready. Press enter to send a message. Press Ctrl+C to quit");
 
@@ -72,14 +82,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         loop {
             interval.tick().await;
-            _ = messages_tx.send(generate_message()).await;
+            _ = messages_tx.send(read_temperature()).await;
         }
     });
 
     std::thread::spawn(move || loop {
         let mut line = String::new();
         std::io::stdin().read_line(&mut line).unwrap();
-        _ = lines_messages_tx.blocking_send(generate_message());
+        _ = lines_messages_tx.blocking_send(read_temperature());
     });
 
     loop {
@@ -87,83 +97,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             _ = signal::ctrl_c() => {
                 break
             },
-            Some(message) = messages_rx.recv() => {
-                node.publish::<BoardSensor>(message, element_path.clone()).await?;
+            Some(temperature) = messages_rx.recv() => {
+                sensor.set_value(PropertyId::PRESENT_AMBIENT_TEMPERATURE, Some(temperature))?;
+                let configured = element_control
+                    .configuration()
+                    .model(&SENSOR_SERVER)
+                    .map(|model| !model.bindings.is_empty())
+                    .unwrap_or_default();
+                if configured {
+                    node.publish(element_path.clone(), SENSOR_SERVER, SendOptions::default(), &sensor.status()).await?;
+                } else {
+                    println!("Sensor model has no bound application key, not publishing");
+                }
             },
         }
     }
 
     println!("Shutting down");
-    //TODO unregister
 
     Ok(())
 }
 
-fn generate_message() -> BoardSensorMessage {
-    SensorMessage::Status(SensorStatus::new(Temperature(21.0)))
-}
-
-#[derive(Clone, Debug)]
-pub struct SensorModel;
-
-#[derive(Clone, Debug, Default)]
-pub struct Temperature(f32);
-
-impl SensorConfig for SensorModel {
-    type Data<'m> = Temperature;
-
-    const DESCRIPTORS: &'static [SensorDescriptor] = &[SensorDescriptor::new(PropertyId(0x4F), 1)];
-}
-
-impl SensorData for Temperature {
-    fn decode(&mut self, id: PropertyId, params: &[u8]) -> Result<(), ParseError> {
-        if id.0 == 0x4F {
-            self.0 = params[0] as f32 / 2.0;
-            Ok(())
-        } else {
-            Err(ParseError::InvalidValue)
-        }
-    }
-
-    fn encode<const N: usize>(
-        &self, _: PropertyId, xmit: &mut heapless::Vec<u8, N>,
-    ) -> Result<(), InsufficientBuffer> {
-        let value = (self.0 * 2 as f32) as u8;
-        xmit.extend_from_slice(&value.to_le_bytes()).map_err(|_| InsufficientBuffer)?;
-        Ok(())
-    }
-}
-
-type BoardSensor = SensorServer<SensorModel, 1, 1>;
-type BoardSensorMessage = SensorMessage<'static, SensorModel, 1, 1>;
-
-const COMPANY_IDENTIFIER: CompanyIdentifier = CompanyIdentifier(0x05F1);
-const COMPANY_MODEL: ModelIdentifier = ModelIdentifier::Vendor(COMPANY_IDENTIFIER, 0x0001);
-
-#[derive(Clone, Debug)]
-pub struct VendorModel;
-
-impl Model for VendorModel {
-    const IDENTIFIER: ModelIdentifier = COMPANY_MODEL;
-    type Message<'m> = VendorMessage;
-
-    fn parse<'m>(_opcode: Opcode, _parameters: &'m [u8]) -> Result<Option<Self::Message<'m>>, ParseError> {
-        unimplemented!();
-    }
-}
-
-#[derive(Copy, Clone)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum VendorMessage {}
-
-impl Message for VendorMessage {
-    fn opcode(&self) -> Opcode {
-        unimplemented!();
-    }
-
-    fn emit_parameters<const N: usize>(
-        &self, _xmit: &mut heapless::Vec<u8, N>,
-    ) -> Result<(), InsufficientBuffer> {
-        unimplemented!();
-    }
+/// Reads the ambient temperature in degrees Celsius.
+fn read_temperature() -> f64 {
+    21.0
 }
@@ -1326,6 +1326,307 @@ impl fmt::Debug for ApplicationHandle {
     }
 }
 
+// ===========================================================================================
+// Declarative services
+// ===========================================================================================
+
+/// A local GATT service defined by a struct.
+///
+/// This is usually implemented using `#[derive(GattService)]`, which
+/// is available when the `derive` crate feature is enabled.
+/// Each struct field annotated with `#[characteristic(...)]` must be a [CharacteristicValue]
+/// and is exposed as a characteristic of the service.
+///
+/// # Example
+#[cfg_attr(feature = "derive", doc = "```no_run")]
+#[cfg_attr(not(feature = "derive"), doc = "```ignore")]
+/// use bluer::gatt::local::{CharacteristicValue, GattService};
+///
+/// #[derive(Clone, GattService)]
+/// #[gatt(uuid = "180f")]
+/// struct Battery {
+///     #[characteristic(uuid = "2a19", read, notify, descriptor(uuid = "2901", value = "Battery level"))]
+///     level: CharacteristicValue<u8>,
+/// }
+///
+/// # async fn example(adapter: bluer::Adapter) -> bluer::Result<()> {
+/// let battery = Battery { level: CharacteristicValue::new(100) };
+/// let app_handle = adapter.serve_gatt_application(battery.application()).await?;
+/// battery.level.set(99);
+/// # drop(app_handle);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The following attributes are supported.
+///
+/// * `#[gatt(uuid = "...", secondary)]` on the struct specifies the service UUID and
+///   optionally declares the service as secondary.
+/// * `#[characteristic(uuid = "...", <flags>, descriptor(uuid = "...", value = "..."))]`
+///   on a field specifies the characteristic UUID, its [flags](CharacteristicFlags) by name
+///   and any number of static, read-only descriptors.
+///
+/// UUIDs can be specified in full or as 16-bit or 32-bit short forms.
+pub trait GattService {
+    /// Builds the service definition.
+    fn service(&self) -> Service;
+
+    /// Builds an application consisting of this service only.
+    fn application(&self) -> Application {
+        Application { services: vec![self.service()], ..Default::default() }
+    }
+}
+
+#[cfg(feature = "derive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+pub use bluer_derive::GattService;
+
+/// A value that can be exchanged over a [CharacteristicValue].
+///
+/// Integers are encoded in little-endian byte order, as required by the
+/// Bluetooth specification.
+pub trait GattValue: Clone + Send + Sync + 'static {
+    /// Encodes the value for transmission to a remote device.
+    fn to_gatt(&self) -> Vec<u8>;
+
+    /// Decodes a value received from a remote device.
+    fn from_gatt(data: &[u8]) -> ReqResult<Self>;
+}
+
+macro_rules! impl_gatt_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl GattValue for $t {
+                fn to_gatt(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+
+                fn from_gatt(data: &[u8]) -> ReqResult<Self> {
+                    Ok(Self::from_le_bytes(data.try_into().map_err(|_| ReqError::InvalidValueLength)?))
+                }
+            }
+        )*
+    };
+}
+
+impl_gatt_value_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl GattValue for bool {
+    fn to_gatt(&self) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+
+    fn from_gatt(data: &[u8]) -> ReqResult<Self> {
+        match data {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [_] => Err(ReqError::NotPermitted),
+            _ => Err(ReqError::InvalidValueLength),
+        }
+    }
+}
+
+impl GattValue for String {
+    fn to_gatt(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_gatt(data: &[u8]) -> ReqResult<Self> {
+        String::from_utf8(data.to_vec()).map_err(|_| ReqError::NotPermitted)
+    }
+}
+
+impl GattValue for Vec<u8> {
+    fn to_gatt(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_gatt(data: &[u8]) -> ReqResult<Self> {
+        Ok(data.to_vec())
+    }
+}
+
+/// Typed, shared value of a characteristic of a [GattService].
+///
+/// Clones share the same value.
+/// Reads from remote devices return the current value and writes from remote devices
+/// replace it.
+/// Each remote device that has enabled notifications or indications is informed
+/// of every change.
+pub struct CharacteristicValue<T> {
+    tx: Arc<watch::Sender<T>>,
+}
+
+impl<T> Clone for CharacteristicValue<T> {
+    fn clone(&self) -> Self {
+        Self { tx: self.tx.clone() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CharacteristicValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CharacteristicValue").field(&*self.tx.borrow()).finish()
+    }
+}
+
+impl<T: GattValue + Default> Default for CharacteristicValue<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: GattValue> CharacteristicValue<T> {
+    /// Creates a characteristic value with the specified initial value.
+    pub fn new(value: T) -> Self {
+        Self { tx: Arc::new(watch::channel(value).0) }
+    }
+
+    /// Current value.
+    pub fn get(&self) -> T {
+        self.tx.borrow().clone()
+    }
+
+    /// Sets the value and notifies all subscribed remote devices.
+    pub fn set(&self, value: T) {
+        self.tx.send_replace(value);
+    }
+
+    /// Watches for changes of the value, including writes by remote devices.
+    pub fn watch(&self) -> watch::Receiver<T> {
+        self.tx.subscribe()
+    }
+
+    /// Builds a characteristic definition exposing this value.
+    ///
+    /// `flags` specifies the permitted operations and security requirements.
+    /// A write at a non-zero offset, as performed by long and reliable writes,
+    /// replaces the encoded value from that offset on.
+    pub fn characteristic(
+        &self, uuid: Uuid, flags: CharacteristicFlags, descriptors: Vec<Descriptor>,
+    ) -> Characteristic {
+        let read =
+            flags.read.then(|| {
+                let tx = self.tx.clone();
+                CharacteristicRead {
+                    read: true,
+                    encrypt_read: flags.encrypt_read,
+                    encrypt_authenticated_read: flags.encrypt_authenticated_read,
+                    secure_read: flags.secure_read,
+                    fun: Box::new(move |req| {
+                        let value = tx.borrow().to_gatt();
+                        async move {
+                            value.get(req.offset.into()..).map(|v| v.to_vec()).ok_or(ReqError::InvalidOffset)
+                        }
+                        .boxed()
+                    }),
+                    _non_exhaustive: (),
+                }
+            });
+
+        let write = (flags.write || flags.write_without_response || flags.reliable_write).then(|| {
+            let tx = self.tx.clone();
+            CharacteristicWrite {
+                write: flags.write,
+                write_without_response: flags.write_without_response,
+                reliable_write: flags.reliable_write,
+                authenticated_signed_writes: flags.authenticated_signed_writes,
+                encrypt_write: flags.encrypt_write,
+                encrypt_authenticated_write: flags.encrypt_authenticated_write,
+                secure_write: flags.secure_write,
+                method: CharacteristicWriteMethod::Fun(Box::new(move |data, req| {
+                    let res = Self::write_at(&tx, req.offset.into(), &data);
+                    async move { res }.boxed()
+                })),
+                _non_exhaustive: (),
+            }
+        });
+
+        let notify = (flags.notify || flags.indicate).then(|| {
+            let tx = self.tx.clone();
+            CharacteristicNotify {
+                notify: flags.notify,
+                indicate: flags.indicate,
+                method: CharacteristicNotifyMethod::Fun(Box::new(move |notifier| {
+                    let rx = tx.subscribe();
+                    async move {
+                        tokio::spawn(Self::notify_changes(rx, notifier));
+                    }
+                    .boxed()
+                })),
+                _non_exhaustive: (),
+            }
+        });
+
+        Characteristic {
+            uuid,
+            broadcast: flags.broadcast,
+            writable_auxiliaries: flags.writable_auxiliaries,
+            authorize: flags.authorize,
+            descriptors,
+            read,
+            write,
+            notify,
+            ..Default::default()
+        }
+    }
+
+    /// Writes `data` at `offset` of the encoded value, which then ends after the written data.
+    ///
+    /// Long and reliable writes arrive in parts with increasing offsets.
+    fn write_at(tx: &watch::Sender<T>, offset: usize, data: &[u8]) -> ReqResult<()> {
+        let mut value = if offset == 0 { Vec::new() } else { tx.borrow().to_gatt() };
+        if offset > value.len() {
+            return Err(ReqError::InvalidOffset);
+        }
+        value.truncate(offset);
+        value.extend_from_slice(data);
+        tx.send_replace(T::from_gatt(&value)?);
+        Ok(())
+    }
+
+    /// Forwards value changes to a notification session until it is stopped.
+    async fn notify_changes(mut rx: watch::Receiver<T>, mut notifier: CharacteristicNotifier) {
+        let stopped = notifier.stopped();
+        futures::pin_mut!(stopped);
+        loop {
+            let changed = {
+                let changed = rx.changed();
+                futures::pin_mut!(changed);
+                matches!(
+                    futures::future::select(changed, &mut stopped).await,
+                    futures::future::Either::Left((Ok(()), _))
+                )
+            };
+            if !changed {
+                break;
+            }
+            let value = rx.borrow_and_update().to_gatt();
+            if notifier.notify(value).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Descriptor {
+    /// Creates a read-only descriptor with a constant value.
+    pub fn with_value(uuid: Uuid, value: impl Into<Vec<u8>>) -> Self {
+        let value = value.into();
+        Self {
+            uuid,
+            read: Some(DescriptorRead {
+                read: true,
+                fun: Box::new(move |req| {
+                    let res = value.get(req.offset.into()..).map(|v| v.to_vec()).ok_or(ReqError::InvalidOffset);
+                    async move { res }.boxed()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
 // ===========================================================================================
 // GATT profile
 // ===========================================================================================
@@ -1413,3 +1714,34 @@ impl fmt::Debug for ProfileHandle {
         write!(f, "ProfileHandle {{ {} }}", &self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characteristic_value_write_at_offset() {
+        let value = CharacteristicValue::new("hello".to_string());
+        let write = |offset, data: &[u8]| CharacteristicValue::write_at(&value.tx, offset, data);
+
+        write(0, b"long ").unwrap();
+        write(5, b"write").unwrap();
+        assert_eq!(value.get(), "long write");
+        write(4, b"!").unwrap();
+        assert_eq!(value.get(), "long!");
+        assert!(matches!(write(6, b"x"), Err(ReqError::InvalidOffset)));
+        assert_eq!(value.get(), "long!");
+    }
+
+    #[test]
+    fn characteristic_value_write_invalid() {
+        let value = CharacteristicValue::new(0x1234u16);
+        let write = |offset, data: &[u8]| CharacteristicValue::write_at(&value.tx, offset, data);
+
+        assert!(matches!(write(0, &[0x01]), Err(ReqError::InvalidValueLength)));
+        write(1, &[0xab]).unwrap();
+        assert_eq!(value.get(), 0xab34);
+        assert!(matches!(write(1, &[0x01, 0x02]), Err(ReqError::InvalidValueLength)));
+        assert_eq!(value.get(), 0xab34);
+    }
+}
@@ -215,6 +215,11 @@ pub struct CharacteristicWriter {
 }
 
 impl CharacteristicWriter {
+    #[cfg(all(test, feature = "mesh"))]
+    pub(crate) fn new(mtu: usize, stream: UnixStream) -> Self {
+        Self { mtu, stream }
+    }
+
     /// Maximum transmission unit.
     pub fn mtu(&self) -> usize {
         self.mtu
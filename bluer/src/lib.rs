@@ -31,8 +31,13 @@
 //!     * two programming models supported
 //!         * callback-based interface
 //!         * low-overhead [AsyncRead] and [AsyncWrite] streams
+//!     * [declarative service definitions](gatt::local::GattService)
 //! * [sending Bluetooth Low Energy advertisements](Adapter::advertise)
 //! * [Bluetooth authorization agent](agent::Agent)
+//! * [Bluetooth mesh](mesh)
+//!     * joining, creating and importing mesh networks
+//!     * sending and receiving access layer messages
+//!     * provisioning of remote nodes
 //! * efficient event dispatching
 //!     * not affected by D-Bus match rule count
 //!     * O(1) in number of subscriptions
@@ -57,12 +62,16 @@
 //! The following crate features are available.
 //!
 //! * `bluetoothd`: Enables all functions requiring a running Bluetooth daemon.
+//! * `derive`: Enables `#[derive(GattService)]` for declarative definitions of local GATT services.
 //! * `id`: Enables database of assigned numbers.
 //! * `l2cap`: Enables L2CAP sockets.
 //! * `rfcomm`: Enables RFCOMM sockets.
-//! * `serde`: Enables serialization and deserialization of some data types.
+//! * `mesh`: Enables Bluetooth mesh functions requiring a running Bluetooth mesh daemon.
+//! * `drogue`: Enables interoperability of Bluetooth mesh models with [drogue-device].
+//! * `serde`: Enables serialization and deserialization of some data types
+//!   and the Bluetooth mesh configuration database.
 //!
-//! To enable all crate features specify the `full` crate feature.
+//! To enable all crate features except `drogue` specify the `full` crate feature.
 //!
 //! ## Basic usage
 //! Create a [Session] using [Session::new]; this establishes a connection to the Bluetooth daemon.
@@ -75,6 +84,7 @@
 //!
 //! [Linux Bluetooth protocol stack (BlueZ)]: http://www.bluez.org/
 //! [GATT services]: https://www.oreilly.com/library/view/getting-started-with/9781491900550/ch04.html
+//! [drogue-device]: https://github.com/drogue-iot/drogue-device
 //! [AsyncRead]: tokio::io::AsyncRead
 //! [AsyncWrite]: tokio::io::AsyncWrite
 
@@ -528,6 +538,8 @@ pub mod rfcomm;
 mod session;
 mod sys;
 
+#[cfg(feature = "mesh")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mesh")))]
 pub mod mesh;
 
 #[cfg(feature = "bluetoothd")]
@@ -618,6 +630,22 @@ pub enum ErrorKind {
     /// the target object was either not present or removed
     #[strum(disabled)]
     NotFound,
+    /// Bluetooth mesh provisioning failed: {0}
+    #[cfg(feature = "mesh")]
+    #[strum(disabled)]
+    ProvisioningFailed(mesh::ProvisioningError),
+    /// the Bluetooth mesh node did not respond in time
+    #[cfg(feature = "mesh")]
+    #[strum(disabled)]
+    NoResponse,
+    /// Bluetooth mesh configuration failed: {0}
+    #[cfg(feature = "mesh")]
+    #[strum(disabled)]
+    ConfigurationFailed(mesh::models::config::ConfigStatus),
+    /// invalid Bluetooth mesh message: {0}
+    #[cfg(feature = "mesh")]
+    #[strum(disabled)]
+    InvalidMessage(mesh::ParseError),
     /// internal error: {0}
     #[strum(disabled)]
     Internal(InternalErrorKind),
@@ -1,78 +1,412 @@
-//! Implement Provisioner bluetooth provisoner agent
+//! Bluetooth mesh provisioning agent.
 
-use crate::{method_call, SessionInner, mesh::ReqError};
-use std::sync::Arc;
-
-use dbus::{
-    nonblock::{Proxy, SyncConnection},
-};
 use dbus_crossroads::{Crossroads, IfaceBuilder, IfaceToken};
-use hex::FromHex;
+use futures::{pin_mut, Future};
+use std::{fmt, pin::Pin, sync::Arc};
+use strum::{Display, EnumString};
+use tokio::{
+    select,
+    sync::{oneshot, Mutex},
+};
 
-use crate::mesh::{PATH, SERVICE_NAME, TIMEOUT};
-use std::io::stdin;
+use crate::{
+    mesh::{ReqError, ReqResult},
+    method_call,
+};
 
 pub(crate) const INTERFACE: &str = "org.bluez.mesh.ProvisionAgent1";
 
-#[derive(Clone)]
-/// Implements org.bluez.mesh.ProvisionAgent1 interface
+define_flags!(pub Capabilities, "Provisioning capabilities of an agent." => {
+    /// Can blink.
+    blink ("blink"),
+    /// Can beep.
+    beep ("beep"),
+    /// Can vibrate.
+    vibrate ("vibrate"),
+    /// Can display a number.
+    out_numeric ("out-numeric"),
+    /// Can display an alphanumeric string.
+    out_alpha ("out-alpha"),
+    /// Can request to be pushed.
+    push ("push"),
+    /// Can request to be twisted.
+    twist ("twist"),
+    /// Can input a number.
+    in_numeric ("in-numeric"),
+    /// Can input an alphanumeric string.
+    in_alpha ("in-alpha"),
+    /// Has a static out-of-band value.
+    static_oob ("static-oob"),
+    /// Has a public key available out-of-band.
+    public_oob ("public-oob"),
+});
+
+define_flags!(pub OobInfo, "Out-of-band information of an unprovisioned device." => {
+    /// Other location.
+    other ("other"),
+    /// Electronic location given by URI.
+    uri ("uri"),
+    /// 2D machine-readable code.
+    machine_code_2d ("machine-code-2d"),
+    /// Bar code.
+    bar_code ("bar-code"),
+    /// Near field communication.
+    nfc ("nfc"),
+    /// Number.
+    number ("number"),
+    /// String.
+    string ("string"),
+    /// On the box.
+    on_box ("on-box"),
+    /// Inside the box.
+    in_box ("in-box"),
+    /// On a piece of paper.
+    on_paper ("on-paper"),
+    /// Inside the manual.
+    in_manual ("in-manual"),
+    /// On the device.
+    on_device ("on-device"),
+});
+
+impl OobInfo {
+    /// Decodes the OOB information field of an unprovisioned device beacon.
+    pub fn from_bits(bits: u16) -> Self {
+        let bit = |n: u16| bits & (1 << n) != 0;
+        Self {
+            other: bit(0),
+            uri: bit(1),
+            machine_code_2d: bit(2),
+            bar_code: bit(3),
+            nfc: bit(4),
+            number: bit(5),
+            string: bit(6),
+            on_box: bit(11),
+            in_box: bit(12),
+            on_paper: bit(13),
+            in_manual: bit(14),
+            on_device: bit(15),
+        }
+    }
+}
+
+/// Action of a display numeric request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+pub enum DisplayNumericType {
+    /// Blink the specified number of times.
+    Blink,
+    /// Beep the specified number of times.
+    Beep,
+    /// Vibrate the specified number of times.
+    Vibrate,
+    /// Display the specified number.
+    OutNumeric,
+    /// Request to be pushed the specified number of times.
+    Push,
+    /// Request to be twisted the specified number of times.
+    Twist,
+}
+
+/// Action of a prompt numeric request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+pub enum PromptNumericType {
+    /// Enter the number of times the remote device blinked.
+    Blink,
+    /// Enter the number of times the remote device beeped.
+    Beep,
+    /// Enter the number of times the remote device vibrated.
+    Vibrate,
+    /// Enter the number displayed by the remote device.
+    InNumeric,
+    /// Push the remote device the displayed number of times.
+    Push,
+    /// Twist the remote device the displayed number of times.
+    Twist,
+}
+
+/// Kind of a prompt static request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Display, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+pub enum PromptStaticType {
+    /// Static out-of-band value.
+    StaticOob,
+    /// Alphanumeric string displayed by the remote device.
+    InAlpha,
+}
+
+/// Function handling a private key request.
+pub type PrivateKeyFn =
+    Box<dyn (Fn() -> Pin<Box<dyn Future<Output = ReqResult<[u8; 32]>> + Send>>) + Send + Sync>;
+
+/// Function handling a public key request.
+pub type PublicKeyFn = Box<dyn (Fn() -> Pin<Box<dyn Future<Output = ReqResult<[u8; 64]>> + Send>>) + Send + Sync>;
+
+/// Arguments for a display string request.
+#[derive(custom_debug::Debug)]
+#[non_exhaustive]
+pub struct DisplayString {
+    /// Alphanumeric string to display.
+    pub value: String,
+    /// Resolves once the string should not be displayed anymore.
+    #[debug(skip)]
+    pub cancel: oneshot::Receiver<()>,
+}
+
+/// Function handling a display string request.
+pub type DisplayStringFn =
+    Box<dyn (Fn(DisplayString) -> Pin<Box<dyn Future<Output = ReqResult<()>> + Send>>) + Send + Sync>;
+
+/// Arguments for a display numeric request.
+#[derive(custom_debug::Debug)]
+#[non_exhaustive]
+pub struct DisplayNumeric {
+    /// Action to perform.
+    pub display_type: DisplayNumericType,
+    /// Number to display or count of actions to perform.
+    pub number: u32,
+    /// Resolves once the number should not be displayed anymore.
+    #[debug(skip)]
+    pub cancel: oneshot::Receiver<()>,
+}
+
+/// Function handling a display numeric request.
+pub type DisplayNumericFn =
+    Box<dyn (Fn(DisplayNumeric) -> Pin<Box<dyn Future<Output = ReqResult<()>> + Send>>) + Send + Sync>;
+
+/// Arguments for a prompt numeric request.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct PromptNumeric {
+    /// Action the user is asked to perform.
+    pub prompt_type: PromptNumericType,
+}
+
+/// Function handling a prompt numeric request.
+pub type PromptNumericFn =
+    Box<dyn (Fn(PromptNumeric) -> Pin<Box<dyn Future<Output = ReqResult<u32>> + Send>>) + Send + Sync>;
+
+/// Arguments for a prompt static request.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct PromptStatic {
+    /// Kind of value requested.
+    pub prompt_type: PromptStaticType,
+}
+
+/// Function handling a prompt static request.
+pub type PromptStaticFn =
+    Box<dyn (Fn(PromptStatic) -> Pin<Box<dyn Future<Output = ReqResult<[u8; 16]>> + Send>>) + Send + Sync>;
+
+/// Function handling cancellation of the provisioning procedure.
+pub type CancelFn = Box<dyn (Fn() -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync>;
+
+/// Bluetooth mesh provisioning agent.
+///
+/// Each handler that is set to [None] will reject the request.
+/// The capabilities of the agent must be published accordingly.
+/// The default agent has no capabilities and rejects all requests.
+///
+/// The future of a prompt request is dropped when the mesh daemon cancels that request.
+///
+/// Store the agent in [Application::agent](super::application::Application::agent).
+#[derive(Default)]
 pub struct ProvisionAgent {
-    inner: Arc<SessionInner>,
+    /// Provisioning capabilities of the agent.
+    pub capabilities: Capabilities,
+    /// Indicates availability of out-of-band information.
+    ///
+    /// Only used by unprovisioned devices.
+    pub oob_info: OobInfo,
+    /// Uniform Resource Identifier pointing to out-of-band information.
+    ///
+    /// Only used by unprovisioned devices.
+    pub uri: Option<String>,
+    /// This method is called during provisioning if the
+    /// provisioner has requested Out-Of-Band ECC key exchange.
+    ///
+    /// The private key is returned to the mesh daemon,
+    /// and the public key is delivered to the remote
+    /// provisioner using a method that does not involve
+    /// the Bluetooth radio.
+    pub private_key: Option<PrivateKeyFn>,
+    /// This method is called during provisioning if the
+    /// local device is the provisioner, and is requesting
+    /// Out-Of-Band ECC key exchange.
+    ///
+    /// The public key of the remote device is returned,
+    /// which it has delivered to the provisioner using
+    /// a method that does not involve the Bluetooth radio.
+    pub public_key: Option<PublicKeyFn>,
+    /// This method is called when the mesh daemon has something important
+    /// for the agent to display, but does not require any additional input locally.
+    pub display_string: Option<DisplayStringFn>,
+    /// This method is called when the mesh daemon has something important
+    /// for the agent to display, but does not require any additional input locally.
+    ///
+    /// For example, the number of times to blink or the number to show on a display.
+    pub display_numeric: Option<DisplayNumericFn>,
+    /// This method is called when the mesh daemon requires the user
+    /// to enter a decimal value between 1-99999999.
+    pub prompt_numeric: Option<PromptNumericFn>,
+    /// This method is called when the mesh daemon requires a
+    /// 16 octet byte array, as an Out-of-Band authentication.
+    pub prompt_static: Option<PromptStaticFn>,
+    /// This method gets called by the mesh daemon to cancel
+    /// any existing agent requests.
+    pub cancel: Option<CancelFn>,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
 }
 
-impl ProvisionAgent {
+impl fmt::Debug for ProvisionAgent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProvisionAgent")
+            .field("capabilities", &self.capabilities)
+            .field("oob_info", &self.oob_info)
+            .field("uri", &self.uri)
+            .finish()
+    }
+}
 
-    pub(crate) fn new(inner: Arc<SessionInner>) -> Self {
-        Self { inner }
+/// A provisioning agent exposed over D-Bus to the mesh daemon.
+pub(crate) struct RegisteredProvisionAgent {
+    a: ProvisionAgent,
+    cancel: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl RegisteredProvisionAgent {
+    pub(crate) fn new(agent: ProvisionAgent) -> Self {
+        Self { a: agent, cancel: Mutex::new(None) }
+    }
+
+    async fn get_cancel(&self) -> oneshot::Receiver<()> {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        *self.cancel.lock().await = Some(cancel_tx);
+        cancel_rx
     }
 
-    fn proxy(&self) -> Proxy<'_, &SyncConnection> {
-        Proxy::new(SERVICE_NAME, PATH, TIMEOUT, &*self.inner.connection)
+    async fn call<A, F, R>(&self, f: &Option<impl Fn(A) -> F>, arg: A) -> ReqResult<R>
+    where
+        F: Future<Output = ReqResult<R>> + Send + 'static,
+    {
+        match f {
+            Some(f) => f(arg).await,
+            None => Err(ReqError::NotSupported),
+        }
     }
 
-    dbus_interface!();
-    dbus_default_interface!(INTERFACE);
+    async fn call_with_cancel<A, F, R>(&self, f: &Option<impl Fn(A) -> F>, arg: A) -> ReqResult<R>
+    where
+        F: Future<Output = ReqResult<R>> + Send + 'static,
+    {
+        let cancel_rx = self.get_cancel().await;
+        match f {
+            Some(f) => {
+                let fut = f(arg);
+                pin_mut!(fut);
+                select! {
+                    result = fut => result,
+                    _ = cancel_rx => Err(ReqError::Failed)
+                }
+            }
+            None => Err(ReqError::NotSupported),
+        }
+    }
 
     pub(crate) fn register_interface(cr: &mut Crossroads) -> IfaceToken<Arc<Self>> {
         cr.register(INTERFACE, |ib: &mut IfaceBuilder<Arc<Self>>| {
-            ib.method_with_cr_async("DisplayNumeric", ("type", "value"), (), |ctx, cr, (_type, _value,): (String, u32)| {
-                method_call(ctx, cr, move |_reg: Arc<Self>| async move {
-                    println!("DisplayNumeric");
+            ib.method_with_cr_async("Cancel", (), (), |ctx, cr, ()| {
+                method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                    if let Some(cancel_tx) = reg.cancel.lock().await.take() {
+                        let _ = cancel_tx.send(());
+                    }
+                    if let Some(cancel) = &reg.a.cancel {
+                        cancel().await;
+                    }
                     Ok(())
                 })
             });
-            ib.method_with_cr_async("PromptStatic", ("type",), ("value",), |ctx, cr, (_type,): (String,)| {
-                method_call(ctx, cr, move |_reg: Arc<Self>| async move {
-                    println!("Prompt static");
-                    let mut input_string = String::new();
-                    stdin().read_line(&mut input_string)
-                    .ok()
-                    .expect("Failed to read line");
-
-                    println!("str {}", input_string);
-
-                    match Vec::from_hex(input_string.trim().clone()) {
-                        Ok(vec) => {
-                            println!("vec {}", vec.len());
-                        }
-                        Err(e) => {
-                            println!("{}", e);
-                        }
-                    }
-
-                    let hex = Vec::from_hex(input_string.trim()).map_err(|_|  ReqError::Failed)?;
-
-                    println!("hex {:?}", hex);
-
-                    //let value = (input_string).as_bytes().to_vec();
-                    Ok((hex,))
+            ib.method_with_cr_async("PrivateKey", (), ("private_key",), |ctx, cr, ()| {
+                method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                    let key = reg.call(&reg.a.private_key.as_ref().map(|f| move |()| f()), ()).await?;
+                    Ok((key.to_vec(),))
                 })
             });
-            cr_property!(ib, "Capabilities", _reg => {
-                let mt: Vec<String> = vec!["out-numeric".into(), "static-oob".into()];
-                Some(mt)
+            ib.method_with_cr_async("PublicKey", (), ("public_key",), |ctx, cr, ()| {
+                method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                    let key = reg.call(&reg.a.public_key.as_ref().map(|f| move |()| f()), ()).await?;
+                    Ok((key.to_vec(),))
+                })
+            });
+            ib.method_with_cr_async("DisplayString", ("value",), (), |ctx, cr, (value,): (String,)| {
+                method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                    reg.call(&reg.a.display_string, DisplayString { value, cancel: reg.get_cancel().await })
+                        .await?;
+                    Ok(())
+                })
+            });
+            ib.method_with_cr_async(
+                "DisplayNumeric",
+                ("type", "number"),
+                (),
+                |ctx, cr, (display_type, number): (String, u32)| {
+                    method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                        let display_type = display_type.parse().map_err(|_| {
+                            log::error!("Invalid display numeric type: {}", &display_type);
+                            ReqError::Failed
+                        })?;
+                        reg.call(
+                            &reg.a.display_numeric,
+                            DisplayNumeric { display_type, number, cancel: reg.get_cancel().await },
+                        )
+                        .await?;
+                        Ok(())
+                    })
+                },
+            );
+            ib.method_with_cr_async(
+                "PromptNumeric",
+                ("type",),
+                ("number",),
+                |ctx, cr, (prompt_type,): (String,)| {
+                    method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                        let prompt_type = prompt_type.parse().map_err(|_| {
+                            log::error!("Invalid prompt numeric type: {}", &prompt_type);
+                            ReqError::Failed
+                        })?;
+                        Ok((reg.call_with_cancel(&reg.a.prompt_numeric, PromptNumeric { prompt_type }).await?,))
+                    })
+                },
+            );
+            ib.method_with_cr_async(
+                "PromptStatic",
+                ("type",),
+                ("value",),
+                |ctx, cr, (prompt_type,): (String,)| {
+                    method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                        let prompt_type = prompt_type.parse().map_err(|_| {
+                            log::error!("Invalid prompt static type: {}", &prompt_type);
+                            ReqError::Failed
+                        })?;
+                        let value =
+                            reg.call_with_cancel(&reg.a.prompt_static, PromptStatic { prompt_type }).await?;
+                        Ok((value.to_vec(),))
+                    })
+                },
+            );
+            cr_property!(ib, "Capabilities", reg => {
+                Some(reg.a.capabilities.as_vec())
+            });
+            cr_property!(ib, "OutOfBandInfo", reg => {
+                Some(reg.a.oob_info.as_vec())
+            });
+            cr_property!(ib, "URI", reg => {
+                reg.a.uri.clone()
             });
         })
     }
-
-}
\ No newline at end of file
+}
@@ -1,55 +1,140 @@
 //! Implement Application bluetooth mesh interface
 
 use crate::{method_call, Result, SessionInner};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use dbus::{
+    blocking::stdintf::org_freedesktop_dbus::ObjectManagerInterfacesRemoved,
+    channel::Sender,
+    message::SignalArgs,
     nonblock::{Proxy, SyncConnection},
     Path,
 };
 use dbus_crossroads::{Crossroads, IfaceBuilder, IfaceToken};
 
-use crate::mesh::{Element, RegisteredElement, PATH, SERVICE_NAME, TIMEOUT};
+use crate::mesh::{
+    Element, ElementConfig, ProvisioningError, RegisteredElement, ELEMENT_INTERFACE, PATH, SERVICE_NAME, TIMEOUT,
+};
 use futures::channel::oneshot;
 use std::{fmt, mem::take};
 
-use super::{provisioner::{RegisteredProvisioner, Provisioner}, agent::ProvisionAgent};
+use super::{
+    agent::{self, ProvisionAgent, RegisteredProvisionAgent},
+    network,
+    provisioner::{self, Provisioner, RegisteredProvisioner},
+};
 
 pub(crate) const INTERFACE: &str = "org.bluez.mesh.Application1";
 
+/// Company identifier of the Linux Foundation, used by default.
+pub const DEFAULT_COMPANY_ID: u16 = 0x05f1;
+
 /// Definition of mesh application.
-#[derive(Clone, Default)]
+///
+/// The mesh daemon builds page 0 of the composition data of the node
+/// from the identifiers and elements of the application.
+#[derive(Debug)]
 pub struct Application {
     /// Application path
     pub path: Path<'static>,
     /// Application elements
     pub elements: Vec<Element>,
+    /// Company identifier assigned by the Bluetooth SIG.
+    ///
+    /// Defaults to [DEFAULT_COMPANY_ID].
+    pub company_id: u16,
+    /// Vendor-assigned product identifier.
+    pub product_id: u16,
+    /// Vendor-assigned product version identifier.
+    pub version_id: u16,
+    /// Minimum number of replay protection list entries.
+    ///
+    /// If unset, the mesh daemon uses its default.
+    pub crpl: Option<u16>,
     /// Provisioner
     pub provisioner: Option<Provisioner>,
+    /// Provisioning agent
+    pub agent: ProvisionAgent,
+    /// Leave the mesh network when the application is unregistered.
+    ///
+    /// When the [ApplicationHandle] is dropped, the node the application
+    /// has been [attached](super::network::Network::attach) to is removed
+    /// from the mesh daemon, as if [Network::leave](super::network::Network::leave)
+    /// was called with its token.
+    pub leave_on_drop: bool,
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl Default for Application {
+    fn default() -> Self {
+        Self {
+            path: Path::default(),
+            elements: Vec::new(),
+            company_id: DEFAULT_COMPANY_ID,
+            product_id: 0x0001,
+            version_id: 0x0001,
+            crpl: None,
+            provisioner: None,
+            agent: ProvisionAgent::default(),
+            leave_on_drop: false,
+            _non_exhaustive: (),
+        }
+    }
 }
 
 // ---------------
 // D-Bus interface
 // ---------------
 
+type JoinResult = std::result::Result<u64, ProvisioningError>;
+
 /// An Application exposed over D-Bus to bluez.
-#[derive(Clone)]
 pub struct RegisteredApplication {
     inner: Arc<SessionInner>,
     app: Application,
-    agent: ProvisionAgent,
-    provisioner: Option<RegisteredProvisioner>,
+    pub(crate) provisioner: Option<RegisteredProvisioner>,
+    elements: Vec<Arc<RegisteredElement>>,
+    join_tx: Mutex<Option<oneshot::Sender<JoinResult>>>,
+    token: Mutex<Option<u64>>,
 }
 
 impl RegisteredApplication {
     pub(crate) fn new(inner: Arc<SessionInner>, app: Application) -> Self {
-        let provisioner = match app.clone().provisioner {
-            Some(prov) => Some(RegisteredProvisioner::new(inner.clone(), prov.clone())),
-            None => None,
-        };
-        let agent = ProvisionAgent::new(inner.clone());
+        let provisioner = app.provisioner.clone().map(|prov| RegisteredProvisioner::new(inner.clone(), prov));
 
-        Self { inner, app, provisioner, agent }
+        Self { inner, app, provisioner, elements: Vec::new(), join_tx: Mutex::new(None), token: Mutex::new(None) }
+    }
+
+    /// Registers interest in the outcome of joining a network.
+    ///
+    /// A previously pending join is dropped.
+    pub(crate) fn expect_join(&self) -> oneshot::Receiver<JoinResult> {
+        let (tx, rx) = oneshot::channel();
+        *self.join_tx.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Removes interest in the outcome of joining a network.
+    pub(crate) fn forget_join(&self) {
+        self.join_tx.lock().unwrap().take();
+    }
+
+    /// Notifies the waiting [join](super::network::Network::join) call of the outcome of joining.
+    pub(crate) fn join_result(&self, result: JoinResult) {
+        match self.join_tx.lock().unwrap().take() {
+            Some(tx) => {
+                let _ = tx.send(result);
+            }
+            None => log::warn!("Received join result {:?} without pending join", &result),
+        }
+    }
+
+    /// Fails a pending join because it has been cancelled.
+    pub(crate) fn abort_join(&self) {
+        if let Some(tx) = self.join_tx.lock().unwrap().take() {
+            let _ = tx.send(Err(ProvisioningError::Aborted));
+        }
     }
 
     fn proxy(&self) -> Proxy<'_, &SyncConnection> {
@@ -61,68 +146,131 @@ impl RegisteredApplication {
 
     pub(crate) fn register_interface(cr: &mut Crossroads) -> IfaceToken<Arc<Self>> {
         cr.register(INTERFACE, |ib: &mut IfaceBuilder<Arc<Self>>| {
-            ib.method_with_cr_async("JoinComplete", ("token",), (), |ctx, cr, (_token,): (u64,)| {
-                method_call(ctx, cr, move |_reg: Arc<Self>| async move {
-                    println!("JoinComplete");
+            ib.method_with_cr_async("JoinComplete", ("token",), (), |ctx, cr, (token,): (u64,)| {
+                method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                    reg.join_result(Ok(token));
                     Ok(())
                 })
             });
-            ib.method_with_cr_async("JoinFailed", ("reason",), (), |ctx, cr, (_reason,): (String,)| {
-                method_call(ctx, cr, move |_reg: Arc<Self>| async move {
-                    println!("JoinFailed");
+            ib.method_with_cr_async("JoinFailed", ("reason",), (), |ctx, cr, (reason,): (String,)| {
+                method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                    reg.join_result(Err(ProvisioningError::from_reason(reason)));
                     Ok(())
                 })
             });
-            cr_property!(ib, "CompanyID", _reg => {
-                Some(0x05f1 as u16)
+            cr_property!(ib, "CompanyID", reg => {
+                Some(reg.app.company_id)
+            });
+            cr_property!(ib, "ProductID", reg => {
+                Some(reg.app.product_id)
             });
-            cr_property!(ib, "ProductID", _reg => {
-                Some(0x0001 as u16)
+            cr_property!(ib, "VersionID", reg => {
+                Some(reg.app.version_id)
             });
-            cr_property!(ib, "VersionID", _reg => {
-                Some(0x0001 as u16)
+            cr_property!(ib, "CRPL", reg => {
+                reg.app.crpl
             });
         })
     }
 
+    /// Applies the model configuration received when attaching to the network.
+    ///
+    /// Elements that are not part of the configuration have no configured models.
+    pub(crate) fn configure(&self, config: &[(u8, Vec<(u16, ElementConfig)>)]) {
+        for element in &self.elements {
+            let models = config.iter().find(|(idx, _)| *idx == element.index).map(|(_, models)| &models[..]);
+            element.configure(models.unwrap_or_default());
+        }
+    }
+
+    /// Looks up the application registered at the specified root path.
+    pub(crate) async fn find(inner: &SessionInner, root_path: &Path<'_>) -> Option<Arc<Self>> {
+        let root_path = root_path.clone().into_static();
+        inner.mesh_applications.lock().await.get(&root_path).cloned()
+    }
+
+    /// Records the token of the node the application is attached to.
+    pub(crate) fn attached(&self, token: u64) {
+        *self.token.lock().unwrap() = Some(token);
+    }
+
     pub(crate) async fn register(
         mut self, root_path: Path<'static>, inner: Arc<SessionInner>,
     ) -> Result<ApplicationHandle> {
-        {
+        let mut reg_paths = Vec::new();
+        let reg = {
             let mut cr = inner.crossroads.lock().await;
 
             let elements = take(&mut self.app.elements);
+            self.elements = elements
+                .into_iter()
+                .enumerate()
+                .map(|(element_idx, element)| {
+                    Arc::new(RegisteredElement::new(inner.clone(), element, element_idx as u8))
+                })
+                .collect();
+            let app_path = self.app.path.clone();
+            let agent = Arc::new(RegisteredProvisionAgent::new(take(&mut self.app.agent)));
+            let has_provisioner = self.provisioner.is_some();
+            let reg = Arc::new(self);
 
             let om = cr.object_manager();
             cr.insert(root_path.clone(), &[om], ());
 
-            cr.insert(Path::from(format!("{}/{}", root_path.clone(), "agent")), &[inner.provision_agent_token], Arc::new(self.clone().agent));
+            let agent_path = Path::from(format!("{}/{}", root_path.clone(), "agent"));
+            log::trace!("Publishing provisioning agent at {}", &agent_path);
+            reg_paths.push((agent_path.clone(), vec![agent::INTERFACE]));
+            cr.insert(agent_path, &[inner.provision_agent_token], agent);
 
-            match self.clone().provisioner {
-                Some(_) => {
-                    cr.insert(self.app.path.clone(), &[inner.provisioner_token, inner.application_token], Arc::new(self.clone()))
-                },
-                None => {
-                    cr.insert(self.app.path.clone(), &[inner.application_token], Arc::new(self.clone()))
-                }
+            log::trace!("Publishing application at {}", &app_path);
+            if has_provisioner {
+                reg_paths.push((app_path.clone(), vec![INTERFACE, provisioner::INTERFACE]));
+                cr.insert(app_path, &[inner.provisioner_token, inner.application_token], reg.clone());
+            } else {
+                reg_paths.push((app_path.clone(), vec![INTERFACE]));
+                cr.insert(app_path, &[inner.application_token], reg.clone());
             }
 
-            for (element_idx, element) in elements.into_iter().enumerate() {
-                let element_path = element.path.clone();
-                let reg_element = RegisteredElement::new(inner.clone(), element, element_idx as u8);
-                //TODO register and remove all paths ... reg_paths.push(element_path.clone());
-                cr.insert(element_path, &[inner.element_token], Arc::new(reg_element));
+            for reg_element in &reg.elements {
+                let element_path = reg_element.element.path.clone();
+                log::trace!("Publishing element at {}", &element_path);
+                reg_paths.push((element_path.clone(), vec![ELEMENT_INTERFACE]));
+                cr.insert(element_path, &[inner.element_token], reg_element.clone());
             }
-        }
+
+            reg
+        };
+        inner.mesh_applications.lock().await.insert(root_path.clone(), reg.clone());
 
         let (drop_tx, drop_rx) = oneshot::channel();
         let path_unreg = root_path.clone();
         tokio::spawn(async move {
             let _ = drop_rx.await;
 
-            log::trace!("Unpublishing application at {}", &path_unreg);
+            let token = *reg.token.lock().unwrap();
+            if let (true, Some(token)) = (reg.app.leave_on_drop, token) {
+                log::trace!("Leaving mesh network with node {:016x}", token);
+                let proxy = Proxy::new(SERVICE_NAME, PATH, TIMEOUT, inner.connection.clone());
+                let _: std::result::Result<(), dbus::Error> =
+                    proxy.method_call(network::INTERFACE, "Leave", (token,)).await;
+            }
+
+            inner.mesh_applications.lock().await.remove(&path_unreg);
+
             let mut cr = inner.crossroads.lock().await;
-            let _: Option<Self> = cr.remove(&path_unreg);
+            for (reg_path, interfaces) in reg_paths.into_iter().rev() {
+                log::trace!("Unpublishing {}", &reg_path);
+                let _: Option<Self> = cr.remove(&reg_path);
+
+                let removed = ObjectManagerInterfacesRemoved {
+                    object: reg_path,
+                    interfaces: interfaces.into_iter().map(String::from).collect(),
+                };
+                let _ = inner.connection.send(removed.to_emit_message(&path_unreg));
+            }
+
+            log::trace!("Unpublishing application root at {}", &path_unreg);
+            let _: Option<()> = cr.remove(&path_unreg);
         });
 
         Ok(ApplicationHandle { name: root_path, _drop_tx: drop_tx })
@@ -131,7 +279,9 @@ impl RegisteredApplication {
 
 /// Handle to Application
 ///
-/// Drop this handle to unpublish.
+/// Drop this handle to unpublish all objects of the application.
+/// If [Application::leave_on_drop] is set, the node the application
+/// is attached to also leaves the mesh network.
 pub struct ApplicationHandle {
     name: dbus::Path<'static>,
     _drop_tx: oneshot::Sender<()>,
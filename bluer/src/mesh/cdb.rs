@@ -0,0 +1,1082 @@
+//! Mesh Configuration Database.
+//!
+//! The configuration database describes a mesh network as seen by its provisioners:
+//! network and application keys, provisioned nodes with their device keys and
+//! the configuration of their models.
+//! It is stored in the JSON format defined by the Bluetooth Mesh Configuration
+//! Database Profile and allows to back up a provisioner and to move it to
+//! another machine.
+//!
+//! The mesh daemon keeps its own copy of the keys, which is not accessible to
+//! applications.
+//! Thus the database must be kept up to date using
+//! [Management::export_keys](super::management::Management::export_keys),
+//! the [AddNodeComplete](super::provisioner::ProvisionerMessage::AddNodeComplete)
+//! messages of the provisioner and the results of the [ConfigClient](super::models::config::ConfigClient).
+//! A database can be [imported](MeshCdb::import) into the mesh daemon on a fresh machine.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, ErrorKind as IoErrorKind},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+use crate::{
+    mesh::{
+        management::{ExportedKeys, Key, Management},
+        models::config::{encode_period, CompositionData, PublicationParameters, PERIOD_RESOLUTIONS},
+        provisioner::UnicastAllocator,
+        Destination, ModelIdentifier,
+    },
+    Error, ErrorKind,
+};
+
+/// JSON schema of the configuration database.
+const SCHEMA: &str = "http://json-schema.org/draft-04/schema#";
+/// Identifier of the configuration database schema.
+const SCHEMA_ID: &str = "http://www.bluetooth.com/specifications/assigned-numbers/mesh-profile/cdb-schema.json#";
+/// Version of the configuration database format.
+const VERSION: &str = "1.0.1";
+
+/// Feature state of a node: enabled.
+pub const FEATURE_ENABLED: u8 = 1;
+/// Feature state of a node: not supported.
+pub const FEATURE_UNSUPPORTED: u8 = 2;
+
+/// Mesh Configuration Database.
+///
+/// Properties not covered by this type are preserved when the database
+/// is loaded and saved again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshCdb {
+    /// JSON schema.
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    /// Schema identifier.
+    pub id: String,
+    /// Format version.
+    pub version: String,
+    /// UUID of the mesh network.
+    #[serde(rename = "meshUUID")]
+    pub mesh_uuid: Uuid,
+    /// Name of the mesh network.
+    pub mesh_name: String,
+    /// Time of the last change in RFC 3339 format.
+    pub timestamp: String,
+    /// Whether the database contains only part of the network.
+    #[serde(default)]
+    pub partial: bool,
+    /// Network keys.
+    #[serde(default)]
+    pub net_keys: Vec<CdbNetKey>,
+    /// Application keys.
+    #[serde(default)]
+    pub app_keys: Vec<CdbAppKey>,
+    /// Provisioners of the network.
+    #[serde(default)]
+    pub provisioners: Vec<CdbProvisioner>,
+    /// Nodes of the network, including the provisioners.
+    #[serde(default)]
+    pub nodes: Vec<CdbNode>,
+    /// Group addresses in use.
+    #[serde(default)]
+    pub groups: Vec<CdbGroup>,
+    /// Other properties.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Network key in the configuration database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbNetKey {
+    /// Name of the key.
+    #[serde(default)]
+    pub name: String,
+    /// Network key index.
+    pub index: u16,
+    /// Current key.
+    #[serde(with = "hex_key")]
+    pub key: Key,
+    /// Old key, if the subnet is undergoing key refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_key_opt")]
+    pub old_key: Option<Key>,
+    /// Key refresh phase.
+    #[serde(default)]
+    pub phase: u8,
+    /// Minimum security level of nodes using the key, either `secure` or `insecure`.
+    pub min_security: String,
+    /// Time the key was last changed in RFC 3339 format.
+    pub timestamp: String,
+}
+
+/// Application key in the configuration database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbAppKey {
+    /// Name of the key.
+    #[serde(default)]
+    pub name: String,
+    /// Application key index.
+    pub index: u16,
+    /// Index of the network key the application key is bound to.
+    pub bound_net_key: u16,
+    /// Current key.
+    #[serde(with = "hex_key")]
+    pub key: Key,
+    /// Old key, if the key is being updated.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_key_opt")]
+    pub old_key: Option<Key>,
+}
+
+/// Provisioner in the configuration database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbProvisioner {
+    /// Name of the provisioner.
+    pub provisioner_name: String,
+    /// Device UUID of the provisioner node.
+    #[serde(rename = "UUID")]
+    pub uuid: Uuid,
+    /// Unicast address ranges the provisioner may assign to nodes.
+    #[serde(default)]
+    pub allocated_unicast_range: Vec<CdbAddressRange>,
+    /// Group address ranges the provisioner may use.
+    #[serde(default)]
+    pub allocated_group_range: Vec<CdbAddressRange>,
+    /// Scene number ranges the provisioner may use.
+    #[serde(default)]
+    pub allocated_scene_range: Vec<CdbSceneRange>,
+}
+
+/// Range of addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbAddressRange {
+    /// First address.
+    #[serde(with = "hex_u16")]
+    pub low_address: u16,
+    /// Last address.
+    #[serde(with = "hex_u16")]
+    pub high_address: u16,
+}
+
+/// Range of scene numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbSceneRange {
+    /// First scene number.
+    #[serde(with = "hex_u16")]
+    pub first_scene: u16,
+    /// Last scene number.
+    #[serde(with = "hex_u16")]
+    pub last_scene: u16,
+}
+
+/// Reference to a network or application key known to a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbKeyRef {
+    /// Key index.
+    pub index: u16,
+    /// Whether the node has received the updated key during key refresh.
+    #[serde(default)]
+    pub updated: bool,
+}
+
+/// States of the features of a node.
+///
+/// Each feature is either disabled (0), [enabled](FEATURE_ENABLED) or [not supported](FEATURE_UNSUPPORTED).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbFeatures {
+    /// Relay feature.
+    pub relay: u8,
+    /// Proxy feature.
+    pub proxy: u8,
+    /// Friend feature.
+    pub friend: u8,
+    /// Low power feature.
+    pub low_power: u8,
+}
+
+/// Node in the configuration database.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbNode {
+    /// Device UUID.
+    #[serde(rename = "UUID")]
+    pub uuid: Uuid,
+    /// Unicast address of the primary element.
+    #[serde(with = "hex_u16")]
+    pub unicast_address: u16,
+    /// Device key, once it has been exported from the mesh daemon.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_key_opt")]
+    pub device_key: Option<Key>,
+    /// Security level, either `secure` or `insecure`.
+    pub security: String,
+    /// Network keys known to the node.
+    #[serde(default)]
+    pub net_keys: Vec<CdbKeyRef>,
+    /// Whether the configuration of the node is complete.
+    #[serde(default)]
+    pub config_complete: bool,
+    /// Name of the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Company identifier of the manufacturer.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_u16_opt")]
+    pub cid: Option<u16>,
+    /// Product identifier.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_u16_opt")]
+    pub pid: Option<u16>,
+    /// Product version identifier.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_u16_opt")]
+    pub vid: Option<u16>,
+    /// Minimum number of replay protection list entries.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_u16_opt")]
+    pub crpl: Option<u16>,
+    /// Features of the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<CdbFeatures>,
+    /// Default TTL.
+    #[serde(default, rename = "defaultTTL", skip_serializing_if = "Option::is_none")]
+    pub default_ttl: Option<u8>,
+    /// Application keys known to the node.
+    #[serde(default)]
+    pub app_keys: Vec<CdbKeyRef>,
+    /// Elements of the node.
+    #[serde(default)]
+    pub elements: Vec<CdbElement>,
+    /// Whether the node has been excluded from the network.
+    #[serde(default)]
+    pub excluded: bool,
+    /// Other properties.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl CdbNode {
+    /// Whether the element with unicast address `element` belongs to the node.
+    pub fn contains(&self, element: u16) -> bool {
+        element >= self.unicast_address && usize::from(element - self.unicast_address) < self.elements.len()
+    }
+
+    fn model_mut(&mut self, element: u16, model: ModelIdentifier) -> Option<&mut CdbModel> {
+        let element = self.elements.get_mut(usize::from(element.checked_sub(self.unicast_address)?))?;
+        if let Some(idx) = element.models.iter().position(|m| m.model_id == model) {
+            return Some(&mut element.models[idx]);
+        }
+        element.models.push(CdbModel::new(model));
+        element.models.last_mut()
+    }
+}
+
+/// Element of a node in the configuration database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbElement {
+    /// Name of the element.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Index of the element within the node.
+    pub index: u8,
+    /// Location descriptor.
+    #[serde(with = "hex_u16")]
+    pub location: u16,
+    /// Models of the element.
+    #[serde(default)]
+    pub models: Vec<CdbModel>,
+}
+
+/// Model of an element in the configuration database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbModel {
+    /// Model identifier.
+    #[serde(with = "hex_model")]
+    pub model_id: ModelIdentifier,
+    /// Subscription addresses.
+    #[serde(default, with = "hex_destinations")]
+    pub subscribe: Vec<Destination>,
+    /// Publication configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish: Option<CdbPublish>,
+    /// Indices of the bound application keys.
+    #[serde(default)]
+    pub bind: Vec<u16>,
+}
+
+impl CdbModel {
+    fn new(model_id: ModelIdentifier) -> Self {
+        Self { model_id, subscribe: Vec::new(), publish: None, bind: Vec::new() }
+    }
+}
+
+/// Publication configuration of a model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbPublish {
+    /// Publish address.
+    #[serde(with = "hex_destination")]
+    pub address: Destination,
+    /// Index of the application key used for publishing.
+    pub index: u16,
+    /// TTL of published messages.
+    pub ttl: u8,
+    /// Publish period.
+    pub period: CdbPeriod,
+    /// Retransmission of published messages.
+    pub retransmit: CdbRetransmit,
+    /// Whether friendship credentials are used (1) or not (0).
+    pub credentials: u8,
+}
+
+/// Publish period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbPeriod {
+    /// Number of steps.
+    pub number_of_steps: u8,
+    /// Step resolution in milliseconds.
+    pub resolution: u32,
+}
+
+/// Retransmission of published messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbRetransmit {
+    /// Number of retransmissions.
+    pub count: u8,
+    /// Interval between retransmissions in milliseconds.
+    pub interval: u16,
+}
+
+impl CdbPublish {
+    fn new(address: Destination, parameters: &PublicationParameters) -> Self {
+        let period = encode_period(parameters.period);
+        Self {
+            address,
+            index: parameters.app_key,
+            ttl: parameters.ttl,
+            period: CdbPeriod {
+                number_of_steps: period & 0x3f,
+                resolution: PERIOD_RESOLUTIONS[usize::from(period >> 6)] as u32,
+            },
+            retransmit: CdbRetransmit {
+                count: parameters.retransmit_count,
+                interval: parameters.retransmit_interval.as_millis().min(1600) as u16,
+            },
+            credentials: parameters.friendship_credentials.into(),
+        }
+    }
+}
+
+/// Group address in the configuration database.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CdbGroup {
+    /// Name of the group.
+    pub name: String,
+    /// Group or virtual address.
+    #[serde(with = "hex_destination")]
+    pub address: Destination,
+    /// Address of the parent group or the unassigned address.
+    #[serde(with = "hex_u16")]
+    pub parent_address: u16,
+}
+
+impl MeshCdb {
+    /// Creates an empty configuration database for the mesh network with the specified name and UUID.
+    pub fn new(mesh_name: impl Into<String>, mesh_uuid: Uuid) -> Self {
+        Self {
+            schema: SCHEMA.to_string(),
+            id: SCHEMA_ID.to_string(),
+            version: VERSION.to_string(),
+            mesh_uuid,
+            mesh_name: mesh_name.into(),
+            timestamp: timestamp(),
+            partial: false,
+            net_keys: Vec::new(),
+            app_keys: Vec::new(),
+            provisioners: Vec::new(),
+            nodes: Vec::new(),
+            groups: Vec::new(),
+            other: serde_json::Map::new(),
+        }
+    }
+
+    /// Parses a configuration database from JSON.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Formats the configuration database as JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Loads a configuration database from a file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Self::from_json(&json).map_err(|err| io::Error::new(IoErrorKind::InvalidData, err))
+    }
+
+    /// Saves the configuration database to a file.
+    ///
+    /// The file is replaced atomically, so that a failure does not corrupt an existing backup.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let json = self.to_json().map_err(|err| io::Error::new(IoErrorKind::InvalidData, err))?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Updates the modification timestamp.
+    fn touch(&mut self) {
+        self.timestamp = timestamp();
+    }
+
+    /// Node with the specified primary element address.
+    pub fn node(&self, unicast: u16) -> Option<&CdbNode> {
+        self.nodes.iter().find(|node| node.unicast_address == unicast)
+    }
+
+    /// Mutable node with the specified primary element address.
+    pub fn node_mut(&mut self, unicast: u16) -> Option<&mut CdbNode> {
+        self.nodes.iter_mut().find(|node| node.unicast_address == unicast)
+    }
+
+    /// Mutable node containing the element with the specified address.
+    fn element_node_mut(&mut self, element: u16) -> Option<&mut CdbNode> {
+        self.nodes.iter_mut().find(|node| node.contains(element))
+    }
+
+    /// Updates the network, application and device keys from the keys
+    /// exported by the mesh daemon.
+    pub fn update_keys(&mut self, keys: &ExportedKeys) {
+        let now = timestamp();
+        for net_key in &keys.net_keys {
+            let phase = net_key.phase as u8;
+            match self.net_keys.iter_mut().find(|k| k.index == net_key.index) {
+                Some(k) => {
+                    if k.key != net_key.key || k.phase != phase {
+                        k.timestamp = now.clone();
+                    }
+                    k.key = net_key.key;
+                    k.old_key = net_key.old_key;
+                    k.phase = phase;
+                }
+                None => self.net_keys.push(CdbNetKey {
+                    name: format!("Network key {}", net_key.index),
+                    index: net_key.index,
+                    key: net_key.key,
+                    old_key: net_key.old_key,
+                    phase,
+                    min_security: "secure".to_string(),
+                    timestamp: now.clone(),
+                }),
+            }
+
+            for app_key in &net_key.app_keys {
+                match self.app_keys.iter_mut().find(|k| k.index == app_key.index) {
+                    Some(k) => {
+                        k.bound_net_key = net_key.index;
+                        k.key = app_key.key;
+                        k.old_key = app_key.old_key;
+                    }
+                    None => self.app_keys.push(CdbAppKey {
+                        name: format!("Application key {}", app_key.index),
+                        index: app_key.index,
+                        bound_net_key: net_key.index,
+                        key: app_key.key,
+                        old_key: app_key.old_key,
+                    }),
+                }
+            }
+        }
+
+        for dev_key in &keys.dev_keys {
+            if let Some(node) = self.node_mut(dev_key.address) {
+                node.device_key = Some(dev_key.key);
+            }
+        }
+
+        self.touch();
+    }
+
+    /// Adds a node that has been provisioned into the network with key index `net_index`.
+    ///
+    /// Use the fields of [AddNodeComplete](crate::mesh::provisioner::ProvisionerMessage::AddNodeComplete).
+    /// An existing node with the same UUID or primary element address is replaced.
+    pub fn add_node(&mut self, uuid: Uuid, unicast: u16, count: u8, net_index: u16) {
+        self.nodes.retain(|node| node.uuid != uuid && node.unicast_address != unicast);
+        self.nodes.push(CdbNode {
+            uuid,
+            unicast_address: unicast,
+            device_key: None,
+            security: "secure".to_string(),
+            net_keys: vec![CdbKeyRef { index: net_index, updated: false }],
+            config_complete: false,
+            name: None,
+            cid: None,
+            pid: None,
+            vid: None,
+            crpl: None,
+            features: None,
+            default_ttl: None,
+            app_keys: Vec::new(),
+            elements: (0..count)
+                .map(|index| CdbElement { name: None, index, location: 0, models: Vec::new() })
+                .collect(),
+            excluded: false,
+            other: serde_json::Map::new(),
+        });
+        self.touch();
+    }
+
+    /// Removes the node with the specified primary element address.
+    ///
+    /// Returns the removed node.
+    pub fn remove_node(&mut self, unicast: u16) -> Option<CdbNode> {
+        let idx = self.nodes.iter().position(|node| node.unicast_address == unicast)?;
+        let node = self.nodes.remove(idx);
+        self.touch();
+        Some(node)
+    }
+
+    /// Records the composition data of the node at `unicast`.
+    ///
+    /// Supported features are recorded as [enabled](FEATURE_ENABLED).
+    /// Existing model configuration is kept.
+    ///
+    /// Returns false if the node is unknown.
+    pub fn set_composition(&mut self, unicast: u16, composition: &CompositionData) -> bool {
+        let node = match self.node_mut(unicast) {
+            Some(node) => node,
+            None => return false,
+        };
+
+        let feature = |supported| if supported { FEATURE_ENABLED } else { FEATURE_UNSUPPORTED };
+        node.cid = Some(composition.company_id.0);
+        node.pid = Some(composition.product_id);
+        node.vid = Some(composition.version_id);
+        node.crpl = Some(composition.replay_protection);
+        node.features = Some(CdbFeatures {
+            relay: feature(composition.features.relay),
+            proxy: feature(composition.features.proxy),
+            friend: feature(composition.features.friend),
+            low_power: feature(composition.features.low_power),
+        });
+
+        node.elements.truncate(composition.elements.len());
+        for (index, element) in composition.elements.iter().enumerate() {
+            if index == node.elements.len() {
+                node.elements.push(CdbElement {
+                    name: None,
+                    index: index as u8,
+                    location: 0,
+                    models: Vec::new(),
+                });
+            }
+            let cdb_element = &mut node.elements[index];
+            cdb_element.location = element.location;
+            cdb_element.models.retain(|model| element.models.contains(&model.model_id));
+            for model in &element.models {
+                if !cdb_element.models.iter().any(|m| m.model_id == *model) {
+                    cdb_element.models.push(CdbModel::new(*model));
+                }
+            }
+        }
+
+        self.touch();
+        true
+    }
+
+    /// Records that the application key with index `app_index` has been added to the node at `unicast`.
+    ///
+    /// Returns false if the node is unknown.
+    pub fn add_node_app_key(&mut self, unicast: u16, app_index: u16) -> bool {
+        let node = match self.node_mut(unicast) {
+            Some(node) => node,
+            None => return false,
+        };
+        if !node.app_keys.iter().any(|k| k.index == app_index) {
+            node.app_keys.push(CdbKeyRef { index: app_index, updated: false });
+        }
+        self.touch();
+        true
+    }
+
+    /// Records that the application key with index `app_index` has been bound to a model
+    /// of the element with address `element`.
+    ///
+    /// Returns false if the element is unknown.
+    pub fn bind_model(&mut self, element: u16, model: ModelIdentifier, app_index: u16) -> bool {
+        let model = match self.element_node_mut(element).and_then(|node| node.model_mut(element, model)) {
+            Some(model) => model,
+            None => return false,
+        };
+        if !model.bind.contains(&app_index) {
+            model.bind.push(app_index);
+        }
+        self.touch();
+        true
+    }
+
+    /// Records that a model of the element with address `element` has been subscribed to `address`.
+    ///
+    /// Returns false if the element is unknown.
+    pub fn add_subscription(&mut self, element: u16, model: ModelIdentifier, address: Destination) -> bool {
+        let model = match self.element_node_mut(element).and_then(|node| node.model_mut(element, model)) {
+            Some(model) => model,
+            None => return false,
+        };
+        if !model.subscribe.contains(&address) {
+            model.subscribe.push(address);
+        }
+        self.touch();
+        true
+    }
+
+    /// Records the publication of a model of the element with address `element`.
+    ///
+    /// Returns false if the element is unknown.
+    pub fn set_publication(
+        &mut self, element: u16, model: ModelIdentifier, address: Destination, parameters: &PublicationParameters,
+    ) -> bool {
+        let model = match self.element_node_mut(element).and_then(|node| node.model_mut(element, model)) {
+            Some(model) => model,
+            None => return false,
+        };
+        model.publish = Some(CdbPublish::new(address, parameters));
+        self.touch();
+        true
+    }
+
+    /// Creates an address allocator for nodes provisioned into the network with key index `net_index`.
+    ///
+    /// Addresses are allocated from the first unicast range of the first provisioner, if any,
+    /// and the addresses of all nodes in the database are reserved.
+    pub fn unicast_allocator(&self, net_index: u16) -> UnicastAllocator {
+        let range = self.provisioners.first().and_then(|p| p.allocated_unicast_range.first());
+        let allocator = match range {
+            Some(range) => UnicastAllocator::with_range(net_index, range.low_address..=range.high_address),
+            None => UnicastAllocator::new(net_index),
+        };
+        for node in &self.nodes {
+            allocator.reserve(node.unicast_address, node.elements.len().clamp(1, u8::MAX.into()) as u8);
+        }
+        allocator
+    }
+
+    /// Imports the keys of the database into the key database of the mesh daemon.
+    ///
+    /// Network and application keys as well as the device keys of all nodes,
+    /// except the node at `local` that is managed by `management`, are imported.
+    /// Keys that are already present are left unchanged.
+    pub async fn import(&self, management: &Management, local: u16) -> crate::Result<()> {
+        fn ignore_existing(res: crate::Result<()>) -> crate::Result<()> {
+            match res {
+                Err(Error { kind: ErrorKind::AlreadyExists, .. }) => Ok(()),
+                res => res,
+            }
+        }
+
+        for net_key in &self.net_keys {
+            ignore_existing(management.import_subnet(net_key.index, net_key.key).await)?;
+        }
+        for app_key in &self.app_keys {
+            ignore_existing(management.import_app_key(app_key.bound_net_key, app_key.index, app_key.key).await)?;
+        }
+        for node in &self.nodes {
+            if node.unicast_address == local || node.excluded {
+                continue;
+            }
+            if let Some(device_key) = node.device_key {
+                let count = node.elements.len().clamp(1, u8::MAX.into()) as u8;
+                ignore_existing(management.import_remote_node(node.unicast_address, count, device_key).await)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Current time in RFC 3339 format.
+fn timestamp() -> String {
+    format_timestamp(SystemTime::now())
+}
+
+/// Formats a time in RFC 3339 format.
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Convert days since epoch to a civil date.
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, secs / 3_600, secs % 3_600 / 60, secs % 60)
+}
+
+fn parse_hex_u16(s: &str) -> Result<u16, String> {
+    match s.len() {
+        4 => u16::from_str_radix(s, 16).map_err(|err| err.to_string()),
+        _ => Err(format!("invalid 16-bit hex value: {}", s)),
+    }
+}
+
+fn format_destination(destination: &Destination) -> String {
+    match destination {
+        Destination::Virtual(label) => hex::encode_upper(label.as_bytes()),
+        other => format!("{:04X}", other.address()),
+    }
+}
+
+fn parse_destination(s: &str) -> Result<Destination, String> {
+    match s.len() {
+        32 => {
+            let mut label = [0; 16];
+            hex::decode_to_slice(s, &mut label).map_err(|err| err.to_string())?;
+            Ok(Destination::Virtual(Uuid::from_bytes(label)))
+        }
+        _ => Destination::from_address(parse_hex_u16(s)?).ok_or_else(|| format!("invalid address: {}", s)),
+    }
+}
+
+mod hex_u16 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u16, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(&format!("{:04X}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<u16, D::Error> {
+        super::parse_hex_u16(&String::deserialize(deser)?).map_err(D::Error::custom)
+    }
+}
+
+mod hex_u16_opt {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<u16>, ser: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::hex_u16::serialize(value, ser),
+            None => ser.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<Option<u16>, D::Error> {
+        match Option::<String>::deserialize(deser)? {
+            Some(s) => Ok(Some(super::parse_hex_u16(&s).map_err(D::Error::custom)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+mod hex_key {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::mesh::management::Key;
+
+    pub fn serialize<S: Serializer>(key: &Key, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(&hex::encode_upper(key))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<Key, D::Error> {
+        let mut key = [0; 16];
+        hex::decode_to_slice(String::deserialize(deser)?, &mut key).map_err(D::Error::custom)?;
+        Ok(key)
+    }
+}
+
+mod hex_key_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::mesh::management::Key;
+
+    pub fn serialize<S: Serializer>(key: &Option<Key>, ser: S) -> Result<S::Ok, S::Error> {
+        match key {
+            Some(key) => super::hex_key::serialize(key, ser),
+            None => ser.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<Option<Key>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::hex_key")] Key);
+        Ok(Option::<Wrapper>::deserialize(deser)?.map(|Wrapper(key)| key))
+    }
+}
+
+mod hex_model {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::mesh::{CompanyIdentifier, ModelIdentifier};
+
+    pub fn serialize<S: Serializer>(model: &ModelIdentifier, ser: S) -> Result<S::Ok, S::Error> {
+        match model {
+            ModelIdentifier::SIG(id) => ser.serialize_str(&format!("{:04X}", id)),
+            ModelIdentifier::Vendor(company, id) => ser.serialize_str(&format!("{:04X}{:04X}", company.0, id)),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<ModelIdentifier, D::Error> {
+        let s = String::deserialize(deser)?;
+        match s.len() {
+            4 => Ok(ModelIdentifier::SIG(super::parse_hex_u16(&s).map_err(D::Error::custom)?)),
+            8 if s.is_char_boundary(4) => {
+                let company = super::parse_hex_u16(&s[..4]).map_err(D::Error::custom)?;
+                let id = super::parse_hex_u16(&s[4..]).map_err(D::Error::custom)?;
+                Ok(ModelIdentifier::Vendor(CompanyIdentifier(company), id))
+            }
+            _ => Err(D::Error::custom(format!("invalid model identifier: {}", s))),
+        }
+    }
+}
+
+mod hex_destination {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::mesh::Destination;
+
+    pub fn serialize<S: Serializer>(destination: &Destination, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(&super::format_destination(destination))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<Destination, D::Error> {
+        super::parse_destination(&String::deserialize(deser)?).map_err(D::Error::custom)
+    }
+}
+
+mod hex_destinations {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::mesh::Destination;
+
+    pub fn serialize<S: Serializer>(destinations: &[Destination], ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_seq(destinations.iter().map(super::format_destination))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<Vec<Destination>, D::Error> {
+        Vec::<String>::deserialize(deser)?
+            .iter()
+            .map(|s| super::parse_destination(s).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mesh::{models::config::DEFAULT_TTL, provisioner::AddressAllocator, CompanyIdentifier};
+
+    const CDB: &str = r#"{
+        "$schema": "http://json-schema.org/draft-04/schema#",
+        "id": "http://www.bluetooth.com/specifications/assigned-numbers/mesh-profile/cdb-schema.json#",
+        "version": "1.0.1",
+        "meshUUID": "6e7a5a4f7e4c4b0c9d9d3b1e1a0c8e11",
+        "meshName": "home",
+        "timestamp": "2023-11-14T22:13:20Z",
+        "partial": false,
+        "netKeys": [{
+            "name": "primary",
+            "index": 0,
+            "key": "7DD7364CD842AD18C17C2B820C84C3D6",
+            "phase": 0,
+            "minSecurity": "secure",
+            "timestamp": "2023-11-14T22:13:20Z"
+        }],
+        "appKeys": [{
+            "name": "lights",
+            "index": 1,
+            "boundNetKey": 0,
+            "key": "63964771734FBD76E3B40519D1D94A48"
+        }],
+        "provisioners": [{
+            "provisionerName": "bluer",
+            "UUID": "6e7a5a4f7e4c4b0c9d9d3b1e1a0c8e12",
+            "allocatedUnicastRange": [{ "lowAddress": "0001", "highAddress": "00FF" }],
+            "allocatedGroupRange": [{ "lowAddress": "C000", "highAddress": "C0FF" }],
+            "allocatedSceneRange": []
+        }],
+        "nodes": [{
+            "UUID": "6e7a5a4f7e4c4b0c9d9d3b1e1a0c8e13",
+            "unicastAddress": "0001",
+            "deviceKey": "9D6DD0E96EB25DC19A40ED9914F8F03F",
+            "security": "secure",
+            "netKeys": [{ "index": 0, "updated": false }],
+            "configComplete": true,
+            "cid": "05F1",
+            "appKeys": [{ "index": 1, "updated": false }],
+            "elements": [
+                {
+                    "index": 0,
+                    "location": "0100",
+                    "models": [
+                        { "modelId": "1000", "subscribe": ["C001"], "bind": [1] },
+                        {
+                            "modelId": "05F10001",
+                            "subscribe": ["0073E7E4D8B9440FAF8415DF4C56C0E1"],
+                            "publish": {
+                                "address": "C000",
+                                "index": 1,
+                                "ttl": 255,
+                                "period": { "numberOfSteps": 10, "resolution": 1000 },
+                                "retransmit": { "count": 2, "interval": 100 },
+                                "credentials": 0
+                            },
+                            "bind": [1]
+                        }
+                    ]
+                },
+                { "index": 1, "location": "0000", "models": [] }
+            ],
+            "excluded": false,
+            "heartbeatPub": { "address": "0100", "period": 8, "ttl": 5, "index": 0, "features": [] }
+        }],
+        "groups": [{ "name": "living room", "address": "C001", "parentAddress": "0000" }],
+        "scenes": [{ "name": "evening", "number": "0001", "addresses": ["0001"] }],
+        "networkExclusions": []
+    }"#;
+
+    #[test]
+    fn parse() {
+        let cdb = MeshCdb::from_json(CDB).unwrap();
+        assert_eq!(cdb.mesh_name, "home");
+        assert_eq!(cdb.net_keys[0].key[..2], [0x7d, 0xd7]);
+        assert_eq!(cdb.app_keys[0].bound_net_key, 0);
+        assert_eq!(cdb.provisioners[0].allocated_group_range[0].high_address, 0xc0ff);
+
+        let node = cdb.node(0x0001).unwrap();
+        assert_eq!(node.cid, Some(0x05f1));
+        assert_eq!(node.pid, None);
+        assert_eq!(node.elements[0].location, 0x0100);
+        assert!(node.contains(0x0002));
+        assert!(!node.contains(0x0003));
+
+        let vendor = &node.elements[0].models[1];
+        assert_eq!(vendor.model_id, ModelIdentifier::Vendor(CompanyIdentifier(0x05f1), 0x0001));
+        assert_eq!(
+            vendor.subscribe,
+            [Destination::Virtual(Uuid::from_u128(0x0073e7e4_d8b9_440f_af84_15df4c56c0e1))]
+        );
+        assert_eq!(vendor.publish.unwrap().address, Destination::Group(0xc000));
+        assert_eq!(cdb.groups[0].address, Destination::Group(0xc001));
+    }
+
+    #[test]
+    fn round_trip_preserves_unknown_properties() {
+        let cdb = MeshCdb::from_json(CDB).unwrap();
+        assert!(cdb.other.contains_key("scenes"));
+        assert!(cdb.nodes[0].other.contains_key("heartbeatPub"));
+
+        let json = cdb.to_json().unwrap();
+        assert_eq!(MeshCdb::from_json(&json).unwrap(), cdb);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(CDB).unwrap();
+        assert_eq!(value["scenes"], expected["scenes"]);
+        assert_eq!(value["networkExclusions"], expected["networkExclusions"]);
+        assert_eq!(value["nodes"][0]["heartbeatPub"], expected["nodes"][0]["heartbeatPub"]);
+        assert_eq!(value["nodes"][0]["unicastAddress"], "0001");
+        assert_eq!(value["nodes"][0]["deviceKey"], "9D6DD0E96EB25DC19A40ED9914F8F03F");
+        assert_eq!(value["nodes"][0]["elements"][0]["models"][1]["modelId"], "05F10001");
+        assert_eq!(
+            value["nodes"][0]["elements"][0]["models"][1]["subscribe"][0],
+            "0073E7E4D8B9440FAF8415DF4C56C0E1"
+        );
+        assert!(value["nodes"][0].get("pid").is_none());
+    }
+
+    #[test]
+    fn hex_values() {
+        assert_eq!(parse_hex_u16("00C1"), Ok(0x00c1));
+        assert_eq!(parse_hex_u16("ffff"), Ok(0xffff));
+        assert!(parse_hex_u16("C1").is_err());
+        assert!(parse_hex_u16("0000C1").is_err());
+        assert!(parse_hex_u16("00G1").is_err());
+
+        assert_eq!(parse_destination("0001"), Ok(Destination::Unicast(0x0001)));
+        assert_eq!(parse_destination("FFFF"), Ok(Destination::Group(0xffff)));
+        assert!(parse_destination("0000").is_err());
+        assert!(parse_destination("8000").is_err());
+        assert!(parse_destination("0073E7E4D8B9440FAF8415DF4C56C0E").is_err());
+        assert_eq!(format_destination(&Destination::Unicast(0x00c1)), "00C1");
+
+        let model = |s: &str| {
+            MeshCdb::from_json(&CDB.replace("\"05F10001\"", s))
+                .map(|cdb| cdb.nodes[0].elements[0].models[1].model_id)
+        };
+        assert_eq!(model("\"1002\"").unwrap(), ModelIdentifier::SIG(0x1002));
+        assert!(model("\"100\"").is_err());
+        assert!(model("\"05F1000\"").is_err());
+
+        let key = |s: &str| MeshCdb::from_json(&CDB.replace("\"9D6DD0E96EB25DC19A40ED9914F8F03F\"", s));
+        assert_eq!(key("null").unwrap().nodes[0].device_key, None);
+        assert_eq!(key("\"9d6dd0e96eb25dc19a40ed9914f8f03f\"").unwrap(), MeshCdb::from_json(CDB).unwrap());
+        assert!(key("\"9D6DD0E96EB25DC19A40ED9914F8F0\"").is_err());
+    }
+
+    #[test]
+    fn timestamps() {
+        let at = |secs| format_timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(at(1_709_251_199), "2024-02-29T23:59:59Z");
+        assert_eq!(at(4_107_542_399), "2100-02-28T23:59:59Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH - Duration::from_secs(1)), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn unicast_allocator_reserves_nodes() {
+        let mut cdb = MeshCdb::from_json(CDB).unwrap();
+        cdb.add_node(Uuid::from_u128(1), 0x0010, 3, 0);
+
+        let allocator = cdb.unicast_allocator(0);
+        assert_eq!(allocator.allocated(), [0x0001..=0x0002, 0x0010..=0x0012]);
+        assert_eq!(allocator.allocate(8).unwrap(), (0, 0x0003));
+        assert_eq!(allocator.allocate(8).unwrap(), (0, 0x0013));
+
+        cdb.provisioners[0].allocated_unicast_range[0].high_address = 0x0014;
+        assert!(cdb.unicast_allocator(0).allocate(14).is_err());
+    }
+
+    #[test]
+    fn publication_period() {
+        let publish = |period| {
+            let parameters = PublicationParameters { period, ..Default::default() };
+            CdbPublish::new(Destination::Group(0xc000), &parameters)
+        };
+        assert_eq!(publish(None).period, CdbPeriod { number_of_steps: 0, resolution: 100 });
+        assert_eq!(
+            publish(Some(Duration::from_millis(6_300))).period,
+            CdbPeriod { number_of_steps: 63, resolution: 100 }
+        );
+        assert_eq!(
+            publish(Some(Duration::from_secs(7))).period,
+            CdbPeriod { number_of_steps: 7, resolution: 1_000 }
+        );
+        assert_eq!(
+            publish(Some(Duration::from_secs(100_000))).period,
+            CdbPeriod { number_of_steps: 63, resolution: 600_000 }
+        );
+        assert_eq!(publish(None).ttl, DEFAULT_TTL);
+    }
+}
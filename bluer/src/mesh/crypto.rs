@@ -0,0 +1,218 @@
+//! Bluetooth mesh security toolbox.
+
+use aes::{
+    cipher::{BlockEncrypt, KeyInit},
+    Aes128, Block,
+};
+use cmac::{Cmac, Mac};
+use uuid::Uuid;
+
+/// Length of a CCM nonce.
+pub(crate) const NONCE_LEN: usize = 13;
+
+/// AES-128 encryption of a single block.
+pub(crate) fn e(key: &[u8; 16], data: &[u8; 16]) -> [u8; 16] {
+    let mut block = Block::from(*data);
+    Aes128::new(key.into()).encrypt_block(&mut block);
+    block.into()
+}
+
+/// AES-CMAC of the concatenation of `data` using `key`.
+pub(crate) fn aes_cmac(key: &[u8; 16], data: &[&[u8]]) -> [u8; 16] {
+    let mut mac = <Cmac<Aes128> as Mac>::new(key.into());
+    for d in data {
+        mac.update(d);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Salt generation function s1.
+pub(crate) fn s1(m: &[u8]) -> [u8; 16] {
+    aes_cmac(&[0; 16], &[m])
+}
+
+/// Virtual address derived from a label UUID.
+pub(crate) fn virtual_address(label: &Uuid) -> u16 {
+    let hash = aes_cmac(&s1(b"vtad"), &[label.as_bytes()]);
+    0x8000 | (u16::from_be_bytes([hash[14], hash[15]]) & 0x3fff)
+}
+
+/// Key derivation function k2.
+///
+/// Returns the NID, encryption key and privacy key derived from network key `n`
+/// for the managed flooding security credentials.
+pub(crate) fn k2(n: &[u8; 16]) -> (u8, [u8; 16], [u8; 16]) {
+    let t = aes_cmac(&s1(b"smk2"), &[n]);
+    let t1 = aes_cmac(&t, &[&[0x00, 0x01]]);
+    let t2 = aes_cmac(&t, &[&t1, &[0x00, 0x02]]);
+    let t3 = aes_cmac(&t, &[&t2, &[0x00, 0x03]]);
+    (t1[15] & 0x7f, t2, t3)
+}
+
+/// Key derivation function k4.
+///
+/// Returns the application key identifier of application key `n`.
+pub(crate) fn k4(n: &[u8; 16]) -> u8 {
+    let t = aes_cmac(&s1(b"smk4"), &[n]);
+    aes_cmac(&t, &[b"id6", &[0x01]])[15] & 0x3f
+}
+
+/// CBC-MAC of AES-CCM with a 2-octet length field.
+fn ccm_mac(cipher: &Aes128, nonce: &[u8; NONCE_LEN], aad: &[u8], data: &[u8], mic_len: usize) -> [u8; 16] {
+    let mut b0 = [0; 16];
+    b0[0] = if aad.is_empty() { 0 } else { 0x40 } | ((mic_len as u8 - 2) / 2) << 3 | 0x01;
+    b0[1..14].copy_from_slice(nonce);
+    b0[14..].copy_from_slice(&(data.len() as u16).to_be_bytes());
+    let mut x = Block::from(b0);
+    cipher.encrypt_block(&mut x);
+
+    let mut mac_blocks = |input: &[u8]| {
+        for chunk in input.chunks(16) {
+            for (x, c) in x.iter_mut().zip(chunk) {
+                *x ^= c;
+            }
+            cipher.encrypt_block(&mut x);
+        }
+    };
+    if !aad.is_empty() {
+        let mut a = (aad.len() as u16).to_be_bytes().to_vec();
+        a.extend_from_slice(aad);
+        mac_blocks(&a);
+    }
+    mac_blocks(data);
+
+    x.into()
+}
+
+/// Counter mode key stream of AES-CCM for `len` bytes of data, preceded by the block encrypting the MAC.
+fn ccm_stream(cipher: &Aes128, nonce: &[u8; NONCE_LEN], len: usize) -> Vec<u8> {
+    let mut stream = Vec::with_capacity(16 + len);
+    for i in 0..=(len + 15) / 16 {
+        let mut a = [0; 16];
+        a[0] = 0x01;
+        a[1..14].copy_from_slice(nonce);
+        a[14..].copy_from_slice(&(i as u16).to_be_bytes());
+        let mut a = Block::from(a);
+        cipher.encrypt_block(&mut a);
+        stream.extend_from_slice(&a);
+    }
+    stream
+}
+
+/// AES-CCM encryption.
+///
+/// Returns the encrypted data followed by the message integrity check of length `mic_len`.
+pub(crate) fn aes_ccm_encrypt(
+    key: &[u8; 16], nonce: &[u8; NONCE_LEN], aad: &[u8], data: &[u8], mic_len: usize,
+) -> Vec<u8> {
+    let cipher = Aes128::new(key.into());
+    let mac = ccm_mac(&cipher, nonce, aad, data, mic_len);
+    let stream = ccm_stream(&cipher, nonce, data.len());
+    let mut out: Vec<u8> = data.iter().zip(&stream[16..]).map(|(d, s)| d ^ s).collect();
+    out.extend(mac[..mic_len].iter().zip(&stream[..16]).map(|(m, s)| m ^ s));
+    out
+}
+
+/// AES-CCM decryption.
+///
+/// `data` consists of the encrypted data followed by the message integrity check of length `mic_len`.
+/// Returns [None] if the message integrity check fails.
+pub(crate) fn aes_ccm_decrypt(
+    key: &[u8; 16], nonce: &[u8; NONCE_LEN], aad: &[u8], data: &[u8], mic_len: usize,
+) -> Option<Vec<u8>> {
+    let (data, mic) = data.split_at(data.len().checked_sub(mic_len)?);
+    let cipher = Aes128::new(key.into());
+    let stream = ccm_stream(&cipher, nonce, data.len());
+    let plain: Vec<u8> = data.iter().zip(&stream[16..]).map(|(d, s)| d ^ s).collect();
+    let mac = ccm_mac(&cipher, nonce, aad, &plain, mic_len);
+    let diff = mac[..mic_len].iter().zip(&stream[..16]).zip(mic).fold(0, |acc, ((m, s), c)| acc | (m ^ s ^ c));
+    (diff == 0).then(|| plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample data of the Mesh Profile specification, section 8.3.
+    #[test]
+    fn virtual_address_sample() {
+        let label = Uuid::parse_str("0073e7e4-d8b9-440f-af84-15df4c56c0e1").unwrap();
+        assert_eq!(virtual_address(&label), 0xb529);
+
+        let label = Uuid::parse_str("f4a002c7-fb1e-4ca0-a469-a021de0db875").unwrap();
+        assert_eq!(virtual_address(&label), 0x9736);
+    }
+
+    fn key(s: &str) -> [u8; 16] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    fn nonce(s: &str) -> [u8; NONCE_LEN] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn s1_sample() {
+        assert_eq!(s1(b"test"), key("b73cefbd641ef2ea598c2b6efb62f79c"));
+    }
+
+    #[test]
+    fn k2_sample() {
+        let (nid, encryption, privacy) = k2(&key("f7a2a44f8e8a8029064f173ddc1e2b00"));
+        assert_eq!(nid, 0x7f);
+        assert_eq!(encryption, key("9f589181a0f50de73c8070c7a6d27f46"));
+        assert_eq!(privacy, key("4c715bd4a64b938f99b453351653124f"));
+
+        let (nid, encryption, privacy) = k2(&key("7dd7364cd842ad18c17c2b820c84c3d6"));
+        assert_eq!(nid, 0x68);
+        assert_eq!(encryption, key("0953fa93e7caac9638f58820220a398e"));
+        assert_eq!(privacy, key("8b84eedec100067d670971dd2aa700cf"));
+    }
+
+    #[test]
+    fn k4_sample() {
+        assert_eq!(k4(&key("3216d1509884b533248541792b877f98")), 0x38);
+        assert_eq!(k4(&key("63964771734fbd76e3b40519d1d94a48")), 0x26);
+    }
+
+    /// Network layer encryption of message #1.
+    #[test]
+    fn network_pdu_sample() {
+        let encryption = key("0953fa93e7caac9638f58820220a398e");
+        let nonce = nonce("00800000011201000012345678");
+        let plain = hex::decode("fffd034b50057e400000010000").unwrap();
+        let encrypted = hex::decode("b5e5bfdacbaf6cb7fb6bff871f035444ce83a670df").unwrap();
+
+        assert_eq!(aes_ccm_encrypt(&encryption, &nonce, &[], &plain, 8), encrypted);
+        assert_eq!(aes_ccm_decrypt(&encryption, &nonce, &[], &encrypted, 8), Some(plain));
+    }
+
+    /// Upper transport encryption of message #6 using a device key.
+    #[test]
+    fn access_pdu_sample() {
+        let dev_key = key("9d6dd0e96eb25dc19a40ed9914f8f03f");
+        let nonce = nonce("02003129ab0003120112345678");
+        let plain = hex::decode("0056341263964771734fbd76e3b40519d1d94a48").unwrap();
+        let encrypted = hex::decode("ee9dddfd2169326d23f3afdfcfdc18c52fdef772e0e17308").unwrap();
+
+        assert_eq!(aes_ccm_encrypt(&dev_key, &nonce, &[], &plain, 4), encrypted);
+        assert_eq!(aes_ccm_decrypt(&dev_key, &nonce, &[], &encrypted, 4), Some(plain));
+    }
+
+    #[test]
+    fn ccm_rejects_modified_data() {
+        let key = key("63964771734fbd76e3b40519d1d94a48");
+        let nonce = nonce("01003129ab0003c00112345678");
+        let label = Uuid::parse_str("f4a002c7-fb1e-4ca0-a469-a021de0db875").unwrap();
+        let plain: Vec<u8> = (0..40).collect();
+
+        let mut encrypted = aes_ccm_encrypt(&key, &nonce, label.as_bytes(), &plain, 8);
+        assert_eq!(encrypted.len(), plain.len() + 8);
+        assert_eq!(aes_ccm_decrypt(&key, &nonce, label.as_bytes(), &encrypted, 8), Some(plain));
+        assert_eq!(aes_ccm_decrypt(&key, &nonce, &[], &encrypted, 8), None);
+        assert_eq!(aes_ccm_decrypt(&key, &nonce, label.as_bytes(), &encrypted[..7], 8), None);
+
+        encrypted[3] ^= 0x01;
+        assert_eq!(aes_ccm_decrypt(&key, &nonce, label.as_bytes(), &encrypted, 8), None);
+    }
+}
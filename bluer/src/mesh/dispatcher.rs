@@ -0,0 +1,313 @@
+//! Dispatching of received messages to typed model handlers.
+
+use dbus::Path;
+use futures::{select, stream::FuturesUnordered, Future, StreamExt};
+use std::{fmt, pin::Pin};
+
+use crate::{
+    mesh::{
+        models::config::CONFIGURATION_SERVER,
+        node::{Node, SendOptions},
+        AccessPayload, Destination, Element, ElementControl, ElementEvent, Message, Model, ModelIdentifier,
+        Opcode, ParseError,
+    },
+    Error, ErrorKind, Result,
+};
+
+/// A model that parses the messages it receives into a typed message.
+///
+/// Implement this trait to handle messages of a model using a [Dispatcher].
+pub trait TypedModel: Model + Sized {
+    /// Message type of the model.
+    type Message: Send + 'static;
+
+    /// Parses a message received by the model.
+    ///
+    /// Returns [None] if the opcode does not belong to the model.
+    fn parse_message(
+        opcode: &Opcode, parameters: &[u8],
+    ) -> std::result::Result<Option<Self::Message>, ParseError>;
+}
+
+/// Key a received message was encrypted with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    /// Application key with the specified index.
+    App(u16),
+    /// Device key.
+    Dev {
+        /// Whether the device key of the remote node was used
+        /// instead of the device key of the local node.
+        remote: bool,
+        /// Index of the network key the message was received on.
+        net_index: u16,
+    },
+}
+
+/// A typed message received by a model.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Request<T> {
+    /// Parsed message.
+    pub message: T,
+    /// Unicast address of the message source.
+    pub src: u16,
+    /// Message destination.
+    ///
+    /// This is [None] for device key messages, which are always addressed
+    /// to the unicast address of the element.
+    pub dest: Option<Destination>,
+    /// Key the message was encrypted with.
+    pub key: MessageKey,
+}
+
+/// Maximum number of handlers of a [Dispatcher] running concurrently.
+pub const MAX_PENDING: usize = 32;
+
+/// Reply to a received message.
+pub type Reply = Box<dyn Message + Send>;
+
+/// Function handling a typed message and optionally returning a reply.
+///
+/// The returned future runs concurrently with the dispatching of further messages,
+/// so it may await replies to messages it sends, for example using a client model of the same element.
+pub type HandlerFn<T> =
+    Box<dyn (Fn(Request<T>) -> Pin<Box<dyn Future<Output = Option<Reply>> + Send>>) + Send + Sync>;
+
+/// Handler of a model with its message type erased.
+trait ErasedHandler: Send + Sync {
+    /// Parses and handles a message.
+    ///
+    /// Returns [None] if the message does not belong to the model.
+    fn handle(
+        &self, payload: &AccessPayload, src: u16, dest: Option<Destination>, key: MessageKey,
+    ) -> Option<Pin<Box<dyn Future<Output = Option<Reply>> + Send>>>;
+}
+
+struct ModelHandler<M: TypedModel> {
+    f: HandlerFn<M::Message>,
+}
+
+impl<M: TypedModel> ErasedHandler for ModelHandler<M> {
+    fn handle(
+        &self, payload: &AccessPayload, src: u16, dest: Option<Destination>, key: MessageKey,
+    ) -> Option<Pin<Box<dyn Future<Output = Option<Reply>> + Send>>> {
+        match M::parse_message(&payload.opcode, &payload.parameters) {
+            Ok(Some(message)) => Some((self.f)(Request { message, src, dest, key })),
+            Ok(None) => None,
+            Err(err) => {
+                log::warn!("Cannot parse message {:?} from {:04x}: {:?}", &payload, src, err);
+                None
+            }
+        }
+    }
+}
+
+/// Handlers of the models of an element.
+struct Routes {
+    models: Vec<ModelIdentifier>,
+    handlers: Vec<(ModelIdentifier, Box<dyn ErasedHandler>)>,
+}
+
+impl Routes {
+    /// Checks that the handlers match the models of the element.
+    fn check(&self) -> Result<()> {
+        for (id, _) in &self.handlers {
+            if !self.models.contains(id) {
+                return Err(Error {
+                    kind: ErrorKind::InvalidArguments,
+                    message: format!("handler for model {:?} that is not part of the element", id),
+                });
+            }
+        }
+        for id in &self.models {
+            if *id != CONFIGURATION_SERVER && !self.handlers.iter().any(|(handler_id, _)| handler_id == id) {
+                return Err(Error {
+                    kind: ErrorKind::InvalidArguments,
+                    message: format!("no handler for model {:?} of the element", id),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes a message to the handler of the first model that recognizes it.
+    fn dispatch(
+        &self, payload: &AccessPayload, src: u16, dest: Option<Destination>, key: MessageKey,
+    ) -> Option<Pin<Box<dyn Future<Output = Option<Reply>> + Send>>> {
+        self.models.iter().find_map(|id| {
+            self.handlers
+                .iter()
+                .filter(|(handler_id, _)| handler_id == id)
+                .find_map(|(_, handler)| handler.handle(payload, src, dest, key))
+        })
+    }
+}
+
+/// Routes the messages received by an element to the handlers of its models.
+///
+/// Each received message is parsed by the handlers of the [Element::models] in their order,
+/// and passed to the first one that recognizes its opcode.
+/// Up to [MAX_PENDING] handlers run concurrently.
+/// A reply returned by the handler is sent back to the source of the message,
+/// encrypted with the same key.
+///
+/// Every model of the element, except the [configuration server](CONFIGURATION_SERVER)
+/// implemented by the mesh daemon, must have a handler.
+pub struct Dispatcher {
+    node: Node,
+    path: Path<'static>,
+    routes: Routes,
+}
+
+impl fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Dispatcher {{ {}, {} handlers }}", &self.path, self.routes.handlers.len())
+    }
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher for the models of `element` of `node`.
+    pub fn new(node: Node, element: &Element) -> Self {
+        let models = element.models.iter().map(|model| model.identifier()).collect();
+        Self { node, path: element.path.clone(), routes: Routes { models, handlers: Vec::new() } }
+    }
+
+    /// Adds a handler for the messages of `model`.
+    pub fn handle<M: TypedModel + 'static>(mut self, model: &M, handler: HandlerFn<M::Message>) -> Self {
+        self.routes.handlers.push((model.identifier(), Box::new(ModelHandler::<M> { f: handler })));
+        self
+    }
+
+    /// Dispatches the events of the element until its control stream ends.
+    ///
+    /// Fails if a model of the element has no handler or a handler belongs to
+    /// a model that is not part of the element.
+    /// A reply that cannot be sent is logged and does not stop dispatching.
+    ///
+    /// While [MAX_PENDING] handlers are running, no further messages are dispatched
+    /// until one of them completes.
+    pub async fn run(&self, control: ElementControl) -> Result<()> {
+        self.routes.check()?;
+
+        let mut control = control.fuse();
+        let mut pending = FuturesUnordered::new();
+        loop {
+            let event = select! {
+                event = control.next() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                () = pending.select_next_some() => continue,
+            };
+            let (payload, src, dest, key) = match event {
+                ElementEvent::Message(msg) => (msg.payload, msg.src, Some(msg.dest), MessageKey::App(msg.key)),
+                ElementEvent::DevKeyMessage(msg) => {
+                    (msg.payload, msg.src, None, MessageKey::Dev { remote: msg.remote, net_index: msg.net_index })
+                }
+            };
+
+            let fut = match self.routes.dispatch(&payload, src, dest, key) {
+                Some(fut) => fut,
+                None => {
+                    log::debug!("No handler for message {:?} from {:04x}", &payload, src);
+                    continue;
+                }
+            };
+
+            pending.push(self.complete(fut, src, key));
+            while pending.len() >= MAX_PENDING {
+                pending.next().await;
+            }
+        }
+
+        while pending.next().await.is_some() {}
+        Ok(())
+    }
+
+    /// Waits for a handler and sends its reply, if any.
+    async fn complete(
+        &self, fut: Pin<Box<dyn Future<Output = Option<Reply>> + Send>>, src: u16, key: MessageKey,
+    ) {
+        if let Some(reply) = fut.await {
+            if let Err(err) = self.reply(src, key, &*reply).await {
+                log::warn!("Cannot send reply to {:04x} using {:?}: {}", src, key, err);
+            }
+        }
+    }
+
+    /// Sends a reply to the source of a message using the same key.
+    async fn reply(&self, src: u16, key: MessageKey, reply: &(dyn Message + Send)) -> Result<()> {
+        let path = self.path.clone();
+        match key {
+            MessageKey::App(app_key) => {
+                self.node.send(path, Destination::Unicast(src), app_key, SendOptions::default(), reply).await
+            }
+            MessageKey::Dev { remote, net_index } => {
+                self.node.dev_key_send(path, src, remote, net_index, SendOptions::default(), reply).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::models::generic::{
+        GenericOnOffClient, GenericOnOffServer, OnOffMessage, GENERIC_ONOFF_CLIENT, GENERIC_ONOFF_SERVER,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn onoff_routes(models: &[ModelIdentifier], received: &Arc<Mutex<Vec<ModelIdentifier>>>) -> Routes {
+        let mut handlers: Vec<(ModelIdentifier, Box<dyn ErasedHandler>)> = Vec::new();
+        let recorder = |id| -> HandlerFn<OnOffMessage> {
+            let received = received.clone();
+            Box::new(move |_| {
+                received.lock().unwrap().push(id);
+                Box::pin(async { None })
+            })
+        };
+        handlers.push((
+            GENERIC_ONOFF_SERVER,
+            Box::new(ModelHandler::<GenericOnOffServer> { f: recorder(GENERIC_ONOFF_SERVER) }),
+        ));
+        handlers.push((
+            GENERIC_ONOFF_CLIENT,
+            Box::new(ModelHandler::<GenericOnOffClient> { f: recorder(GENERIC_ONOFF_CLIENT) }),
+        ));
+        Routes { models: models.to_vec(), handlers }
+    }
+
+    #[test]
+    fn routes_check_handlers_against_models() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        assert!(onoff_routes(&[CONFIGURATION_SERVER, GENERIC_ONOFF_SERVER, GENERIC_ONOFF_CLIENT], &received)
+            .check()
+            .is_ok());
+
+        let err = onoff_routes(&[GENERIC_ONOFF_SERVER], &received).check().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidArguments);
+
+        let vendor = ModelIdentifier::Vendor(crate::mesh::CompanyIdentifier(0x05f1), 0x0001);
+        let err =
+            onoff_routes(&[GENERIC_ONOFF_SERVER, GENERIC_ONOFF_CLIENT, vendor], &received).check().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidArguments);
+    }
+
+    #[test]
+    fn routes_dispatch_in_model_order() {
+        let get = AccessPayload { opcode: Opcode::TwoOctet(0x82, 0x01), parameters: Vec::new() };
+        let unknown = AccessPayload { opcode: Opcode::OneOctet(0x7f), parameters: Vec::new() };
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let routes = onoff_routes(&[GENERIC_ONOFF_CLIENT, GENERIC_ONOFF_SERVER], &received);
+        assert!(routes.dispatch(&get, 0x0100, None, MessageKey::App(0)).is_some());
+        assert!(routes.dispatch(&unknown, 0x0100, None, MessageKey::App(0)).is_none());
+        assert_eq!(*received.lock().unwrap(), vec![GENERIC_ONOFF_CLIENT]);
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let routes = onoff_routes(&[GENERIC_ONOFF_SERVER], &received);
+        assert!(routes.dispatch(&get, 0x0100, None, MessageKey::App(0)).is_some());
+        assert_eq!(*received.lock().unwrap(), vec![GENERIC_ONOFF_SERVER]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Interoperability with mesh models of [drogue-device](https://github.com/drogue-iot/drogue-device).
+
+use core::fmt::Debug;
+use drogue_device::drivers::ble::mesh::{
+    composition::CompanyIdentifier as DrogueCompanyIdentifier,
+    model::{Message as DrogueMessage, Model as DrogueModel, ModelIdentifier as DrogueModelIdentifier},
+    pdu::{access::Opcode as DrogueOpcode, ParseError as DrogueParseError},
+};
+
+use super::{CompanyIdentifier, Message, Model, ModelIdentifier, Opcode, ParseError};
+
+impl From<DrogueOpcode> for Opcode {
+    fn from(opcode: DrogueOpcode) -> Self {
+        match opcode {
+            DrogueOpcode::OneOctet(o0) => Self::OneOctet(o0),
+            DrogueOpcode::TwoOctet(o0, o1) => Self::TwoOctet(o0, o1),
+            DrogueOpcode::ThreeOctet(o0, o1, o2) => Self::ThreeOctet(o0, o1, o2),
+        }
+    }
+}
+
+impl From<Opcode> for DrogueOpcode {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::OneOctet(o0) => Self::OneOctet(o0),
+            Opcode::TwoOctet(o0, o1) => Self::TwoOctet(o0, o1),
+            Opcode::ThreeOctet(o0, o1, o2) => Self::ThreeOctet(o0, o1, o2),
+        }
+    }
+}
+
+impl From<DrogueModelIdentifier> for ModelIdentifier {
+    fn from(id: DrogueModelIdentifier) -> Self {
+        match id {
+            DrogueModelIdentifier::SIG(id) => Self::SIG(id),
+            DrogueModelIdentifier::Vendor(vendor, id) => Self::Vendor(CompanyIdentifier(vendor.0), id),
+        }
+    }
+}
+
+impl From<ModelIdentifier> for DrogueModelIdentifier {
+    fn from(id: ModelIdentifier) -> Self {
+        match id {
+            ModelIdentifier::SIG(id) => Self::SIG(id),
+            ModelIdentifier::Vendor(vendor, id) => Self::Vendor(DrogueCompanyIdentifier(vendor.0), id),
+        }
+    }
+}
+
+impl From<DrogueParseError> for ParseError {
+    fn from(err: DrogueParseError) -> Self {
+        match err {
+            DrogueParseError::InvalidLength | DrogueParseError::InsufficientBuffer => Self::InvalidLength,
+            _ => Self::InvalidValue,
+        }
+    }
+}
+
+/// Message of a drogue-device model.
+pub struct ModelMessage<M> {
+    m: M,
+}
+
+impl<M> ModelMessage<M>
+where
+    M: DrogueMessage,
+{
+    /// Wraps a message of a drogue-device model.
+    pub fn new(m: M) -> Self {
+        Self { m }
+    }
+}
+
+impl<M> Message for ModelMessage<M>
+where
+    M: DrogueMessage,
+{
+    fn opcode(&self) -> Opcode {
+        self.m.opcode().into()
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        let mut v: heapless::Vec<u8, 512> = heapless::Vec::new();
+        self.m.emit_parameters(&mut v).unwrap();
+        xmit.extend_from_slice(&v[..]);
+    }
+}
+
+/// Adapter exposing a drogue-device model as a mesh [Model].
+#[derive(Debug)]
+pub struct FromDrogue<M>
+where
+    M: Debug,
+{
+    _m: core::marker::PhantomData<M>,
+}
+
+impl<M: Debug> FromDrogue<M> {
+    /// New model
+    pub fn new(_m: M) -> Self {
+        Self { _m: core::marker::PhantomData }
+    }
+}
+
+unsafe impl<M> Sync for FromDrogue<M> where M: Debug {}
+unsafe impl<M> Send for FromDrogue<M> where M: Debug {}
+
+impl<M> Model for FromDrogue<M>
+where
+    M: DrogueModel + Debug,
+{
+    fn identifier(&self) -> ModelIdentifier {
+        M::IDENTIFIER.into()
+    }
+
+    fn supports_subscription(&self) -> bool {
+        M::SUPPORTS_SUBSCRIPTION
+    }
+
+    fn supports_publication(&self) -> bool {
+        M::SUPPORTS_PUBLICATION
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        let m = M::parse(opcode.into(), parameters)?;
+        if let Some(m) = m {
+            let b: Box<dyn Message + 'm> = Box::new(ModelMessage { m });
+            Ok(Some(b))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -1,7 +1,12 @@
-//! Implements Node bluetooth mesh interface
+//! Implements Management bluetooth mesh interface
 
-use crate::{Result, SessionInner};
-use std::{collections::HashMap, sync::Arc};
+use crate::{variant_hashmap, Error, ErrorKind, InternalErrorKind, Result, SessionInner};
+use futures::{future, FutureExt, Stream, StreamExt};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use dbus::{
     arg::{RefArg, Variant},
@@ -10,28 +15,310 @@ use dbus::{
 };
 use uuid::Uuid;
 
-use crate::{
-    mesh::{SERVICE_NAME, TIMEOUT},
+use crate::mesh::{
+    provisioner::{AddedNode, RegisteredProvisioner, ScanResult},
+    SERVICE_NAME, TIMEOUT,
 };
 
 pub(crate) const INTERFACE: &str = "org.bluez.mesh.Management1";
 
-/// Interface to a Bluetooth mesh node.
+/// 128-bit network, application or device key.
+pub type Key = [u8; 16];
+
+/// Phase of the key refresh procedure of a subnet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyRefreshPhase {
+    /// Normal operation.
+    ///
+    /// Setting this phase cancels the key refresh procedure.
+    /// This is only allowed from [KeyDistribution](Self::KeyDistribution).
+    Normal = 0,
+    /// New keys are being distributed.
+    ///
+    /// This phase is entered by [Management::update_subnet] and cannot be set directly.
+    KeyDistribution = 1,
+    /// New keys are used for transmission.
+    ///
+    /// This phase may only be set from [KeyDistribution](Self::KeyDistribution).
+    UsingNewKeys = 2,
+    /// Old keys are revoked, completing the key refresh procedure.
+    ///
+    /// This phase may only be set from [UsingNewKeys](Self::UsingNewKeys).
+    RevokeOldKeys = 3,
+}
+
+/// Keys of the network exported from the local key database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportedKeys {
+    /// Network keys and their bound application keys.
+    pub net_keys: Vec<ExportedNetKey>,
+    /// Device keys of remote nodes.
+    pub dev_keys: Vec<ExportedDevKey>,
+}
+
+/// Exported network key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportedNetKey {
+    /// Network key index.
+    pub index: u16,
+    /// Key refresh phase of the subnet.
+    pub phase: KeyRefreshPhase,
+    /// Current key.
+    pub key: Key,
+    /// Old key, if the subnet is undergoing key refresh.
+    pub old_key: Option<Key>,
+    /// Application keys bound to this network key.
+    pub app_keys: Vec<ExportedAppKey>,
+}
+
+/// Exported application key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportedAppKey {
+    /// Application key index.
+    pub index: u16,
+    /// Current key.
+    pub key: Key,
+    /// Old key, if the key is being updated.
+    pub old_key: Option<Key>,
+}
+
+/// Exported device key of a remote node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportedDevKey {
+    /// Unicast address of the primary element of the node.
+    pub address: u16,
+    /// Device key.
+    pub key: Key,
+}
+
+type Dict = HashMap<String, Variant<Box<dyn RefArg + 'static>>>;
+
+fn invalid_export() -> Error {
+    Error::new(ErrorKind::Internal(InternalErrorKind::InvalidValue))
+}
+
+fn dict_list(dict: &Dict, name: &str) -> Result<Vec<Dict>> {
+    match dict.get(name) {
+        Some(value) => Ok(value
+            .0
+            .as_iter()
+            .ok_or_else(invalid_export)?
+            .map(|item| variant_hashmap::<String>(&*item.box_clone()))
+            .collect()),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn dict_u16(dict: &Dict, name: &str) -> Result<u16> {
+    dict.get(name).and_then(|v| v.0.as_u64()).and_then(|v| u16::try_from(v).ok()).ok_or_else(invalid_export)
+}
+
+fn dict_key(dict: &Dict, name: &str) -> Result<Option<Key>> {
+    match dict.get(name) {
+        Some(value) => {
+            let key: &Vec<u8> = dbus::arg::cast(&value.0).ok_or_else(invalid_export)?;
+            Ok(Some(key.as_slice().try_into().map_err(|_| invalid_export())?))
+        }
+        None => Ok(None),
+    }
+}
+
+impl ExportedKeys {
+    fn from_dict(dict: &Dict) -> Result<Self> {
+        let mut net_keys = Vec::new();
+        for nk in dict_list(dict, "NetKeys")? {
+            let mut app_keys = Vec::new();
+            for ak in dict_list(&nk, "AppKeys")? {
+                app_keys.push(ExportedAppKey {
+                    index: dict_u16(&ak, "Index")?,
+                    key: dict_key(&ak, "Key")?.ok_or_else(invalid_export)?,
+                    old_key: dict_key(&ak, "OldKey")?,
+                });
+            }
+            let phase = nk.get("Phase").and_then(|v| v.0.as_u64()).unwrap_or_default();
+            net_keys.push(ExportedNetKey {
+                index: dict_u16(&nk, "Index")?,
+                phase: KeyRefreshPhase::from_u64(phase).ok_or_else(invalid_export)?,
+                key: dict_key(&nk, "Key")?.ok_or_else(invalid_export)?,
+                old_key: dict_key(&nk, "OldKey")?,
+                app_keys,
+            });
+        }
+
+        let mut dev_keys = Vec::new();
+        for dk in dict_list(dict, "DevKeys")? {
+            dev_keys.push(ExportedDevKey {
+                address: dict_u16(&dk, "Address")?,
+                key: dict_key(&dk, "Key")?.ok_or_else(invalid_export)?,
+            });
+        }
+
+        Ok(Self { net_keys, dev_keys })
+    }
+}
+
+/// Interface to the management of a Bluetooth mesh network.
+///
+/// The mesh daemon only permits these operations for nodes
+/// that hold the key database of the network.
+#[derive(Clone)]
 pub struct Management {
     inner: Arc<SessionInner>,
     path: Path<'static>,
+    provisioner: Option<RegisteredProvisioner>,
 }
 
 impl Management {
-    pub(crate) async fn new(path: Path<'static>, inner: Arc<SessionInner>) -> Result<Self> {
-        Ok(Self { inner, path })
+    pub(crate) async fn new(
+        path: Path<'static>, inner: Arc<SessionInner>, provisioner: Option<RegisteredProvisioner>,
+    ) -> Result<Self> {
+        Ok(Self { inner, path, provisioner })
+    }
+
+    /// Adds an unprovisioned device with the specified UUID to the network.
+    ///
+    /// The application must have been registered with a
+    /// [Provisioner](crate::mesh::provisioner::Provisioner).
+    ///
+    /// The returned future resolves once provisioning of the device has finished.
+    /// If provisioning fails an error with [ErrorKind::ProvisioningFailed] is returned.
+    pub async fn add_node(&self, uuid: Uuid) -> Result<AddedNode> {
+        let provisioner = self.provisioner.as_ref().ok_or_else(|| Error::new(ErrorKind::NotSupported))?;
+
+        let result_rx = provisioner.expect_node(uuid);
+        if let Err(err) = self.call_method::<_, ()>("AddNode", (uuid.as_bytes().to_vec(), Dict::new())).await {
+            provisioner.forget_node(&uuid);
+            return Err(err);
+        }
+
+        match result_rx.await {
+            Ok(Ok(node)) => Ok(node),
+            Ok(Err(reason)) => Err(Error::new(ErrorKind::ProvisioningFailed(reason))),
+            Err(_) => Err(Error::new(ErrorKind::NotRegistered)),
+        }
     }
 
-    /// Publish message to the mesh
-    pub async fn add_node(&self, uuid: Uuid) -> Result<()> {
-        self.call_method("AddNode", (uuid.as_bytes().to_vec(), HashMap::<String, Variant<Box<dyn RefArg + 'static>>>::new())).await?;
+    /// Scans for unprovisioned devices.
+    ///
+    /// The application must have been registered with a
+    /// [Provisioner](crate::mesh::provisioner::Provisioner).
+    ///
+    /// Returns a stream of beacons received from unprovisioned devices.
+    /// Scanning stops after `seconds`, or runs until cancelled when [None].
+    /// The stream ends when scanning stops or another scan is started.
+    pub async fn unprovisioned_scan(&self, seconds: Option<u16>) -> Result<impl Stream<Item = ScanResult>> {
+        let provisioner = self.provisioner.as_ref().ok_or_else(|| Error::new(ErrorKind::NotSupported))?;
+
+        let mut options: Dict = HashMap::new();
+        if let Some(seconds) = seconds {
+            options.insert("Seconds".to_string(), Variant(Box::new(seconds)));
+        }
+
+        let results_rx = provisioner.start_scan();
+        if let Err(err) = self.call_method::<_, ()>("UnprovisionedScan", (options,)).await {
+            provisioner.stop_scan();
+            return Err(err);
+        }
+
+        let timeout = match seconds {
+            Some(seconds) => sleep(Duration::from_secs(seconds.into())).boxed(),
+            None => future::pending().boxed(),
+        };
+        Ok(UnboundedReceiverStream::new(results_rx).take_until(timeout))
+    }
+
+    /// Stops scanning for unprovisioned devices.
+    pub async fn unprovisioned_scan_cancel(&self) -> Result<()> {
+        if let Some(provisioner) = &self.provisioner {
+            provisioner.stop_scan();
+        }
+        self.call_method("UnprovisionedScanCancel", ()).await
+    }
+
+    /// Generates a new network key and stores it in the key database
+    /// under the index `net_index`.
+    ///
+    /// The subnet is not used until a remote node is configured with it.
+    pub async fn create_subnet(&self, net_index: u16) -> Result<()> {
+        self.call_method("CreateSubnet", (net_index,)).await
+    }
+
+    /// Stores an existing network key in the key database under the index `net_index`.
+    pub async fn import_subnet(&self, net_index: u16, net_key: Key) -> Result<()> {
+        self.call_method("ImportSubnet", (net_index, net_key.to_vec())).await
+    }
+
+    /// Generates a new network key for the subnet with index `net_index`,
+    /// starting the key refresh procedure in [KeyRefreshPhase::KeyDistribution].
+    pub async fn update_subnet(&self, net_index: u16) -> Result<()> {
+        self.call_method("UpdateSubnet", (net_index,)).await
+    }
+
+    /// Deletes the network key with index `net_index` from the key database.
+    pub async fn delete_subnet(&self, net_index: u16) -> Result<()> {
+        self.call_method("DeleteSubnet", (net_index,)).await
+    }
+
+    /// Sets the key refresh phase of the subnet with index `net_index`.
+    pub async fn set_key_phase(&self, net_index: u16, phase: KeyRefreshPhase) -> Result<()> {
+        self.call_method("SetKeyPhase", (net_index, phase as u8)).await
+    }
+
+    /// Generates a new application key under the index `app_index`,
+    /// bound to the network key with index `net_index`.
+    pub async fn create_app_key(&self, net_index: u16, app_index: u16) -> Result<()> {
+        self.call_method("CreateAppKey", (net_index, app_index)).await
+    }
+
+    /// Stores an existing application key under the index `app_index`,
+    /// bound to the network key with index `net_index`.
+    pub async fn import_app_key(&self, net_index: u16, app_index: u16, app_key: Key) -> Result<()> {
+        self.call_method("ImportAppKey", (net_index, app_index, app_key.to_vec())).await
+    }
+
+    /// Generates a new key for the application key with index `app_index`.
+    ///
+    /// The bound network key must be in [KeyRefreshPhase::KeyDistribution].
+    pub async fn update_app_key(&self, app_index: u16) -> Result<()> {
+        self.call_method("UpdateAppKey", (app_index,)).await
+    }
+
+    /// Deletes the application key with index `app_index` from the key database.
+    pub async fn delete_app_key(&self, app_index: u16) -> Result<()> {
+        self.call_method("DeleteAppKey", (app_index,)).await
+    }
+
+    /// Completes the update of the application key with index `app_index`,
+    /// revoking its old key.
+    pub async fn complete_app_key_update(&self, app_index: u16) -> Result<()> {
+        self.call_method("CompleteAppKeyUpdate", (app_index,)).await
+    }
+
+    /// Stores the device key of a remote node in the key database.
+    ///
+    /// `primary` is the unicast address of the primary element of the node
+    /// and `count` the number of its elements.
+    pub async fn import_remote_node(&self, primary: u16, count: u8, device_key: Key) -> Result<()> {
+        self.call_method("ImportRemoteNode", (primary, count, device_key.to_vec())).await
+    }
+
+    /// Deletes the device key of a remote node from the key database.
+    ///
+    /// `primary` is the unicast address of the primary element of the node
+    /// and `count` the number of its elements.
+    pub async fn delete_remote_node(&self, primary: u16, count: u8) -> Result<()> {
+        self.call_method("DeleteRemoteNode", (primary, count)).await
+    }
 
-        Ok(())
+    /// Exports all network, application and device keys from the key database.
+    pub async fn export_keys(&self) -> Result<ExportedKeys> {
+        let (dict,): (Dict,) = self.call_method("ExportKeys", ()).await?;
+        ExportedKeys::from_dict(&dict)
     }
 
     fn proxy(&self) -> Proxy<'_, &SyncConnection> {
@@ -2,10 +2,20 @@
 
 pub mod agent;
 pub mod application;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod cdb;
+pub mod dispatcher;
 pub mod network;
 pub mod node;
 pub mod provisioner;
+pub mod proxy;
 pub mod management;
+pub mod models;
+mod crypto;
+#[cfg(feature = "drogue")]
+#[cfg_attr(docsrs, doc(cfg(feature = "drogue")))]
+pub mod drogue;
 mod types;
 pub use types::*;
 
@@ -16,15 +26,10 @@ use dbus::{
     Path,
 };
 use dbus_crossroads::{Crossroads, IfaceBuilder, IfaceToken};
-use drogue_device::drivers::ble::mesh::{
-    address::{Address, UnicastAddress},
-    app::ApplicationKeyIdentifier,
-    pdu::access::AccessPayload,
-};
 use futures::Stream;
 use pin_project::pin_project;
 use std::{collections::HashMap, fmt, num::NonZeroU16, pin::Pin, sync::Arc, task::Poll, time::Duration};
-use strum::IntoStaticStr;
+use strum::{EnumString, IntoStaticStr};
 use tokio::sync::{mpsc, watch};
 use tokio_stream::wrappers::ReceiverStream;
 
@@ -45,6 +50,11 @@ pub struct Element {
     pub path: Path<'static>,
     /// Control handle for element once it has been registered.
     pub control_handle: Option<ElementControlHandle>,
+    /// Location descriptor of the element, as defined by the
+    /// GATT Bluetooth Namespace Descriptors.
+    ///
+    /// If unset, the location is unknown.
+    pub location: Option<u16>,
 }
 
 /// An element exposed over D-Bus to bluez.
@@ -63,6 +73,25 @@ impl RegisteredElement {
         Proxy::new(SERVICE_NAME, PATH, TIMEOUT, &*self.inner.connection)
     }
 
+    /// Replaces the configuration of all models of the element.
+    pub(crate) fn configure(&self, models: &[(u16, ElementConfig)]) {
+        let config = ElementConfiguration {
+            models: models.iter().map(|(id, dict)| ModelConfiguration::from_dict(*id, dict)).collect(),
+        };
+        log::trace!("Configuration of element {}: {:?}", self.index, &config);
+        if let Some(handle) = &self.element.control_handle {
+            handle.config_tx.send_replace(config);
+        }
+    }
+
+    /// Forwards an event to the element control, if any.
+    async fn send_event(&self, event: ElementEvent) -> ReqResult<()> {
+        match &self.element.control_handle {
+            Some(handler) => handler.events_tx.send(event).await.map_err(|_| ReqError::Failed),
+            None => Ok(()),
+        }
+    }
+
     dbus_interface!();
     dbus_default_interface!(ELEMENT_INTERFACE);
 
@@ -90,47 +119,77 @@ impl RegisteredElement {
                             data
                         );
 
-                        let key = ApplicationKeyIdentifier::from(u8::try_from(key_index).unwrap_or_default());
-                        let src: UnicastAddress = source.try_into().map_err(|_| ReqError::Failed)?;
-                        // TODO handle virtual addresses
-                        let value = &destination.0;
-                        let dest = Address::parse(dbus::arg::cast::<u16>(value).unwrap().to_be_bytes());
-                        // TODO properly parse opcode and hanlde multiple octet cases
-                        //let payload = AccessPayload::parse(&data).map_err(|_| ReqError::Failed)?;
-                        let payload = AccessPayload {
-                            opcode: Opcode::OneOctet(data[0]),
-                            parameters:  heapless::Vec::from_slice(&data[1..]).map_err(|_|  ReqError::Failed)?,
-                        };
+                        let dest = Destination::from_variant(&destination).ok_or_else(|| {
+                            log::error!("Invalid message destination {:?}", &destination);
+                            ReqError::Failed
+                        })?;
+                        let payload = AccessPayload::parse(&data).map_err(|err| {
+                            log::error!("Malformed access PDU {:x?} from {:04x}: {:?}", &data, source, err);
+                            ReqError::Failed
+                        })?;
 
                         let msg = ElementMessage {
-                            key, src, dest, payload
+                            key: key_index, src: source, dest, payload
                         };
+                        reg.send_event(ElementEvent::Message(msg)).await?;
 
-                        match &reg.element.control_handle {
-                            Some(handler) => {
-                                handler
-                                .messages_tx
-                                .send(msg)
-                                .await
-                                .map_err(|_| ReqError::Failed)?;
-                            }
-                            None => ()
-                        }
+                        Ok(())
+                    })
+                },
+            );
+            ib.method_with_cr_async(
+                "DevKeyMessageReceived",
+                ("source", "remote", "net_index", "data"),
+                (),
+                |ctx, cr, (source, remote, net_index, data): (u16, bool, u16, Vec<u8>)| {
+                    method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                        log::trace!(
+                            "Device key message received for element {:?}: (source: {:?}, remote: {:?}, net_index: {:?}, data: {:?})",
+                            reg.index,
+                            source,
+                            remote,
+                            net_index,
+                            data
+                        );
+
+                        let payload = AccessPayload::parse(&data).map_err(|err| {
+                            log::error!("Malformed access PDU {:x?} from {:04x}: {:?}", &data, source, err);
+                            ReqError::Failed
+                        })?;
+
+                        let msg = DevKeyMessage { src: source, remote, net_index, payload };
+                        reg.send_event(ElementEvent::DevKeyMessage(msg)).await?;
 
                         Ok(())
                     })
                 },
             );
+            ib.method_with_cr_async(
+                "UpdateModelConfiguration",
+                ("model_id", "config"),
+                (),
+                |ctx, cr, (model_id, config): (u16, ElementConfig)| {
+                    method_call(ctx, cr, move |reg: Arc<Self>| async move {
+                        let (model, config) = ModelConfiguration::from_dict(model_id, &config);
+                        log::trace!("Configuration of model {:?} of element {}: {:?}", &model, reg.index, &config);
+                        if let Some(handle) = &reg.element.control_handle {
+                            handle.config_tx.send_modify(|element| element.update(model, config));
+                        }
+                        Ok(())
+                    })
+                },
+            );
             cr_property!(ib, "Index", reg => {
                 Some(reg.index)
             });
+            cr_property!(ib, "Location", reg => {
+                reg.element.location
+            });
             cr_property!(ib, "Models", reg => {
                 let mut mt: Vec<(u16, ElementConfig)> = vec![];
-                // TODO rewrite
                 for model in &reg.element.models {
                     if let ModelIdentifier::SIG(id) = model.identifier() {
-                        // TODO what about opts?
-                        mt.push((id, HashMap::new()));
+                        mt.push((id, model_options(&**model)));
                     }
                 }
                 Some(mt)
@@ -139,7 +198,7 @@ impl RegisteredElement {
                 let mut mt: Vec<(u16, u16, ElementConfig)> = vec![];
                 for model in &reg.element.models {
                     if let ModelIdentifier::Vendor(vid, id) = model.identifier() {
-                        mt.push((vid.0, id, HashMap::new()));
+                        mt.push((vid.0, id, model_options(&**model)));
                     }
                 }
                 Some(mt)
@@ -148,14 +207,92 @@ impl RegisteredElement {
     }
 }
 
-/// An object to control a element and receive messages once it has been registered.
+/// Options of a model exported in the `Models` and `VendorModels` properties.
+fn model_options(model: &dyn Model) -> ElementConfig {
+    let mut options: ElementConfig = HashMap::new();
+    options.insert("Publish".to_string(), Variant(Box::new(model.supports_publication())));
+    options.insert("Subscribe".to_string(), Variant(Box::new(model.supports_subscription())));
+    options
+}
+
+/// Configuration of a model, as set by a configuration client.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ModelConfiguration {
+    /// Indices of the application keys bound to the model.
+    pub bindings: Vec<u16>,
+    /// Period of periodic publication, if enabled.
+    pub publication_period: Option<Duration>,
+    /// Subscribed group addresses and virtual labels.
+    pub subscriptions: Vec<Destination>,
+}
+
+impl ModelConfiguration {
+    /// Parses the model configuration passed by the mesh daemon.
+    fn from_dict(id: u16, dict: &ElementConfig) -> (ModelIdentifier, Self) {
+        let model = match dict.get("Vendor").and_then(|v| dbus::arg::cast::<u16>(&v.0)) {
+            Some(vendor) => ModelIdentifier::Vendor(CompanyIdentifier(*vendor), id),
+            None => ModelIdentifier::SIG(id),
+        };
+
+        let bindings =
+            dict.get("Bindings").and_then(|v| dbus::arg::cast::<Vec<u16>>(&v.0)).cloned().unwrap_or_default();
+        let publication_period = dict
+            .get("PublicationPeriod")
+            .and_then(|v| dbus::arg::cast::<u32>(&v.0))
+            .filter(|ms| **ms != 0)
+            .map(|ms| Duration::from_millis((*ms).into()));
+        let subscriptions = match dict.get("Subscriptions").and_then(|v| v.0.as_iter()) {
+            Some(items) => items
+                .filter_map(|item| {
+                    let item = item.box_clone();
+                    let sub = match dbus::arg::cast::<Variant<Box<dyn RefArg + 'static>>>(&*item) {
+                        Some(var) => Destination::from_variant(var),
+                        None => Destination::from_variant(&Variant(item)),
+                    };
+                    if sub.is_none() {
+                        log::warn!("Invalid subscription of model {:?}", &model);
+                    }
+                    sub
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        (model, Self { bindings, publication_period, subscriptions })
+    }
+}
+
+/// Configuration of the models of an element.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElementConfiguration {
+    /// Configuration of each configured model.
+    pub models: Vec<(ModelIdentifier, ModelConfiguration)>,
+}
+
+impl ElementConfiguration {
+    /// Configuration of the specified model, if it has been configured.
+    pub fn model(&self, model: &ModelIdentifier) -> Option<&ModelConfiguration> {
+        self.models.iter().find(|(id, _)| id == model).map(|(_, config)| config)
+    }
+
+    fn update(&mut self, model: ModelIdentifier, config: ModelConfiguration) {
+        match self.models.iter_mut().find(|(id, _)| *id == model) {
+            Some((_, old)) => *old = config,
+            None => self.models.push((model, config)),
+        }
+    }
+}
+
+/// An object to control a element and receive events once it has been registered.
 ///
 /// Use [element_control] to obtain controller and associated handle.
 #[pin_project]
 pub struct ElementControl {
     handle_rx: watch::Receiver<Option<NonZeroU16>>,
+    config_rx: watch::Receiver<ElementConfiguration>,
     #[pin]
-    messages_rx: ReceiverStream<ElementMessage>,
+    events_rx: ReceiverStream<ElementEvent>,
 }
 
 impl fmt::Debug for ElementControl {
@@ -172,13 +309,26 @@ impl ElementControl {
             None => Err(Error::new(ErrorKind::NotRegistered)),
         }
     }
+
+    /// Current configuration of the models of the element.
+    ///
+    /// It is populated when the application attaches to the network
+    /// and updated when a configuration client changes it.
+    pub fn configuration(&self) -> ElementConfiguration {
+        self.config_rx.borrow().clone()
+    }
+
+    /// Watches the configuration of the models of the element.
+    pub fn configuration_watch(&self) -> watch::Receiver<ElementConfiguration> {
+        self.config_rx.clone()
+    }
 }
 
 impl Stream for ElementControl {
-    type Item = ElementMessage;
+    type Item = ElementEvent;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context) -> Poll<Option<Self::Item>> {
-        self.project().messages_rx.poll_next(cx)
+        self.project().events_rx.poll_next(cx)
     }
 }
 
@@ -189,12 +339,17 @@ impl Stream for ElementControl {
 #[derive(Clone)]
 pub struct ElementControlHandle {
     handle_tx: Arc::<watch::Sender<Option<NonZeroU16>>>,
-    messages_tx: mpsc::Sender<ElementMessage>,
+    config_tx: Arc<watch::Sender<ElementConfiguration>>,
+    events_tx: mpsc::Sender<ElementEvent>,
 }
 
 impl Default for ElementControlHandle {
     fn default() -> Self {
-        Self { handle_tx: Arc::new(watch::channel(None).0), messages_tx: mpsc::channel(1).0 }
+        Self {
+            handle_tx: Arc::new(watch::channel(None).0),
+            config_tx: Arc::new(watch::channel(ElementConfiguration::default()).0),
+            events_tx: mpsc::channel(1).0,
+        }
     }
 }
 
@@ -209,10 +364,11 @@ impl fmt::Debug for ElementControlHandle {
 /// Keep the [ElementControl] and store the [ElementControlHandle] in [Element::control_handle].
 pub fn element_control() -> (ElementControl, ElementControlHandle) {
     let (handle_tx, handle_rx) = watch::channel(None);
-    let (messages_tx, messages_rx) = mpsc::channel(1);
+    let (config_tx, config_rx) = watch::channel(ElementConfiguration::default());
+    let (events_tx, events_rx) = mpsc::channel(1);
     (
-        ElementControl { handle_rx, messages_rx: ReceiverStream::new(messages_rx) },
-        ElementControlHandle { handle_tx: Arc::new(handle_tx), messages_tx },
+        ElementControl { handle_rx, config_rx, events_rx: ReceiverStream::new(events_rx) },
+        ElementControlHandle { handle_tx: Arc::new(handle_tx), config_tx: Arc::new(config_tx), events_tx },
     )
 }
 
@@ -259,6 +415,46 @@ impl From<ReqError> for dbus::MethodErr {
 /// Result of a Bluetooth request to us.
 pub type ReqResult<T> = std::result::Result<T, ReqError>;
 
+// ===========================================================================================
+// Provisioning error
+// ===========================================================================================
+
+/// Reason reported by the mesh daemon for a failed provisioning procedure.
+#[derive(Clone, Debug, displaydoc::Display, Eq, PartialEq, Ord, PartialOrd, Hash, EnumString)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(serialize_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ProvisioningError {
+    /// provisioning was aborted
+    Aborted,
+    /// provisioning timed out
+    Timeout,
+    /// invalid provisioning PDU received
+    BadPdu,
+    /// provisioning confirmation failed
+    ConfirmationFailed,
+    /// out of resources
+    OutOfResources,
+    /// decryption of provisioning data failed
+    DecryptionError,
+    /// unexpected provisioning error
+    UnexpectedError,
+    /// cannot assign unicast addresses
+    CannotAssignAddresses,
+    /// {0}
+    #[strum(default)]
+    Other(String),
+}
+
+impl std::error::Error for ProvisioningError {}
+
+impl ProvisioningError {
+    /// Parses the reason string passed by the mesh daemon.
+    pub(crate) fn from_reason(reason: String) -> Self {
+        reason.parse().unwrap_or(Self::Other(reason))
+    }
+}
+
     /// Gets all D-Bus objects from the BlueZ service.
     async fn all_dbus_objects(
         connection: &SyncConnection,
@@ -0,0 +1,1018 @@
+//! Configuration Client model.
+//!
+//! The configuration client configures remote nodes using messages
+//! encrypted with their device key.
+//! The Configuration Server of the local node is implemented by the mesh daemon.
+
+use dbus::Path;
+use futures::Future;
+use std::{sync::Arc, time::Duration};
+use tokio::time::timeout;
+
+use super::StatusWaiters;
+use crate::{
+    mesh::{
+        dispatcher::{HandlerFn, Request, TypedModel},
+        node::{Node, NodeFeatures, SendOptions},
+        CompanyIdentifier, Destination, Message, Model, ModelIdentifier, Opcode, ParseError,
+    },
+    Error, ErrorKind,
+};
+
+/// Identifier of the Configuration Server model.
+pub const CONFIGURATION_SERVER: ModelIdentifier = ModelIdentifier::SIG(0x0000);
+/// Identifier of the Configuration Client model.
+pub const CONFIGURATION_CLIENT: ModelIdentifier = ModelIdentifier::SIG(0x0001);
+
+const APP_KEY_STATUS: Opcode = Opcode::TwoOctet(0x80, 0x03);
+const COMPOSITION_DATA_GET: Opcode = Opcode::TwoOctet(0x80, 0x08);
+const COMPOSITION_DATA_STATUS: Opcode = Opcode::OneOctet(0x02);
+const MODEL_APP_BIND: Opcode = Opcode::TwoOctet(0x80, 0x3d);
+const MODEL_APP_STATUS: Opcode = Opcode::TwoOctet(0x80, 0x3e);
+const MODEL_PUBLICATION_SET: Opcode = Opcode::OneOctet(0x03);
+const MODEL_PUBLICATION_VIRTUAL_ADDRESS_SET: Opcode = Opcode::TwoOctet(0x80, 0x1a);
+const MODEL_PUBLICATION_STATUS: Opcode = Opcode::TwoOctet(0x80, 0x19);
+const MODEL_SUBSCRIPTION_ADD: Opcode = Opcode::TwoOctet(0x80, 0x1b);
+const MODEL_SUBSCRIPTION_VIRTUAL_ADDRESS_ADD: Opcode = Opcode::TwoOctet(0x80, 0x20);
+const MODEL_SUBSCRIPTION_STATUS: Opcode = Opcode::TwoOctet(0x80, 0x1f);
+
+/// Number of times a configuration request is sent before giving up.
+const ATTEMPTS: usize = 3;
+
+/// Time to wait for the status reply to a single attempt.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// TTL value requesting the default TTL of the node.
+pub const DEFAULT_TTL: u8 = 0xff;
+
+/// Status code of a configuration server.
+#[derive(Clone, Copy, Debug, displaydoc::Display, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ConfigStatus {
+    /// success
+    Success,
+    /// invalid address
+    InvalidAddress,
+    /// invalid model
+    InvalidModel,
+    /// invalid application key index
+    InvalidAppKeyIndex,
+    /// invalid network key index
+    InvalidNetKeyIndex,
+    /// insufficient resources
+    InsufficientResources,
+    /// key index already stored
+    KeyIndexAlreadyStored,
+    /// invalid publish parameters
+    InvalidPublishParameters,
+    /// not a subscribe model
+    NotASubscribeModel,
+    /// storage failure
+    StorageFailure,
+    /// feature not supported
+    FeatureNotSupported,
+    /// cannot update
+    CannotUpdate,
+    /// cannot remove
+    CannotRemove,
+    /// cannot bind
+    CannotBind,
+    /// temporarily unable to change state
+    TemporarilyUnableToChangeState,
+    /// cannot set
+    CannotSet,
+    /// unspecified error
+    UnspecifiedError,
+    /// invalid binding
+    InvalidBinding,
+    /// unknown status code {0}
+    Other(u8),
+}
+
+impl From<u8> for ConfigStatus {
+    fn from(code: u8) -> Self {
+        match code {
+            0x00 => Self::Success,
+            0x01 => Self::InvalidAddress,
+            0x02 => Self::InvalidModel,
+            0x03 => Self::InvalidAppKeyIndex,
+            0x04 => Self::InvalidNetKeyIndex,
+            0x05 => Self::InsufficientResources,
+            0x06 => Self::KeyIndexAlreadyStored,
+            0x07 => Self::InvalidPublishParameters,
+            0x08 => Self::NotASubscribeModel,
+            0x09 => Self::StorageFailure,
+            0x0a => Self::FeatureNotSupported,
+            0x0b => Self::CannotUpdate,
+            0x0c => Self::CannotRemove,
+            0x0d => Self::CannotBind,
+            0x0e => Self::TemporarilyUnableToChangeState,
+            0x0f => Self::CannotSet,
+            0x10 => Self::UnspecifiedError,
+            0x11 => Self::InvalidBinding,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<ConfigStatus> for u8 {
+    fn from(status: ConfigStatus) -> Self {
+        match status {
+            ConfigStatus::Success => 0x00,
+            ConfigStatus::InvalidAddress => 0x01,
+            ConfigStatus::InvalidModel => 0x02,
+            ConfigStatus::InvalidAppKeyIndex => 0x03,
+            ConfigStatus::InvalidNetKeyIndex => 0x04,
+            ConfigStatus::InsufficientResources => 0x05,
+            ConfigStatus::KeyIndexAlreadyStored => 0x06,
+            ConfigStatus::InvalidPublishParameters => 0x07,
+            ConfigStatus::NotASubscribeModel => 0x08,
+            ConfigStatus::StorageFailure => 0x09,
+            ConfigStatus::FeatureNotSupported => 0x0a,
+            ConfigStatus::CannotUpdate => 0x0b,
+            ConfigStatus::CannotRemove => 0x0c,
+            ConfigStatus::CannotBind => 0x0d,
+            ConfigStatus::TemporarilyUnableToChangeState => 0x0e,
+            ConfigStatus::CannotSet => 0x0f,
+            ConfigStatus::UnspecifiedError => 0x10,
+            ConfigStatus::InvalidBinding => 0x11,
+            ConfigStatus::Other(code) => code,
+        }
+    }
+}
+
+impl std::error::Error for ConfigStatus {}
+
+impl ConfigStatus {
+    /// Converts the status into a result.
+    fn into_result(self) -> crate::Result<()> {
+        match self {
+            Self::Success => Ok(()),
+            status => Err(Error::new(ErrorKind::ConfigurationFailed(status))),
+        }
+    }
+}
+
+/// Publication parameters of a model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PublicationParameters {
+    /// Index of the application key used for publishing.
+    pub app_key: u16,
+    /// Whether friendship credentials are used for publishing.
+    pub friendship_credentials: bool,
+    /// TTL of published messages or [DEFAULT_TTL].
+    pub ttl: u8,
+    /// Period for periodic publishing.
+    ///
+    /// It is rounded down to the resolution supported by the message.
+    pub period: Option<Duration>,
+    /// Number of retransmissions of each published message.
+    ///
+    /// At most 7 retransmissions are supported.
+    pub retransmit_count: u8,
+    /// Interval between retransmissions.
+    ///
+    /// It is rounded down to a multiple of 50 ms between 50 ms and 1.6 s.
+    pub retransmit_interval: Duration,
+}
+
+impl Default for PublicationParameters {
+    fn default() -> Self {
+        Self {
+            app_key: 0,
+            friendship_credentials: false,
+            ttl: DEFAULT_TTL,
+            period: None,
+            retransmit_count: 0,
+            retransmit_interval: Duration::from_millis(50),
+        }
+    }
+}
+
+impl PublicationParameters {
+    fn parse(parameters: &[u8]) -> Result<Self, ParseError> {
+        match parameters {
+            [a0, a1, ttl, period, retransmit] => {
+                let app_key = u16::from_le_bytes([*a0, *a1]);
+                Ok(Self {
+                    app_key: app_key & 0x0fff,
+                    friendship_credentials: app_key & 0x1000 != 0,
+                    ttl: *ttl,
+                    period: decode_period(*period),
+                    retransmit_count: retransmit & 0x07,
+                    retransmit_interval: Duration::from_millis((u64::from(retransmit >> 3) + 1) * 50),
+                })
+            }
+            _ => Err(ParseError::InvalidLength),
+        }
+    }
+
+    fn emit(&self, xmit: &mut Vec<u8>) {
+        let app_key = (self.app_key & 0x0fff) | if self.friendship_credentials { 0x1000 } else { 0 };
+        xmit.extend_from_slice(&app_key.to_le_bytes());
+        xmit.push(self.ttl);
+        xmit.push(encode_period(self.period));
+        let interval_steps = (self.retransmit_interval.as_millis() / 50).clamp(1, 32) as u8 - 1;
+        xmit.push(self.retransmit_count.min(7) | interval_steps << 3);
+    }
+}
+
+/// Step resolutions of the publish period in milliseconds.
+pub(crate) const PERIOD_RESOLUTIONS: [u64; 4] = [100, 1_000, 10_000, 600_000];
+
+fn decode_period(period: u8) -> Option<Duration> {
+    let steps = u64::from(period & 0x3f);
+    let resolution = PERIOD_RESOLUTIONS[usize::from(period >> 6)];
+    (steps != 0).then(|| Duration::from_millis(steps * resolution))
+}
+
+/// Encodes a publish period using the finest step resolution it fits into.
+///
+/// Longer periods than representable are saturated.
+pub(crate) fn encode_period(period: Option<Duration>) -> u8 {
+    let ms = match period {
+        Some(period) => period.as_millis() as u64,
+        None => return 0,
+    };
+    for (idx, resolution) in PERIOD_RESOLUTIONS.iter().enumerate() {
+        let steps = ms / resolution;
+        if steps <= 0x3f {
+            return (idx as u8) << 6 | steps as u8;
+        }
+    }
+    0xff
+}
+
+fn parse_model(parameters: &[u8]) -> Result<ModelIdentifier, ParseError> {
+    match parameters {
+        [m0, m1] => Ok(ModelIdentifier::SIG(u16::from_le_bytes([*m0, *m1]))),
+        [c0, c1, m0, m1] => Ok(ModelIdentifier::Vendor(
+            CompanyIdentifier(u16::from_le_bytes([*c0, *c1])),
+            u16::from_le_bytes([*m0, *m1]),
+        )),
+        _ => Err(ParseError::InvalidLength),
+    }
+}
+
+fn emit_model(model: &ModelIdentifier, xmit: &mut Vec<u8>) {
+    match model {
+        ModelIdentifier::SIG(id) => xmit.extend_from_slice(&id.to_le_bytes()),
+        ModelIdentifier::Vendor(company, id) => {
+            xmit.extend_from_slice(&company.0.to_le_bytes());
+            xmit.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+}
+
+fn parse_u16(parameters: &[u8]) -> Result<(u16, &[u8]), ParseError> {
+    match parameters {
+        [b0, b1, rest @ ..] => Ok((u16::from_le_bytes([*b0, *b1]), rest)),
+        _ => Err(ParseError::InvalidLength),
+    }
+}
+
+/// Parses a 16-bit address or a label UUID, depending on the opcode.
+fn parse_address(parameters: &[u8], is_virtual: bool) -> Result<(Destination, &[u8]), ParseError> {
+    if is_virtual {
+        if parameters.len() < 16 {
+            return Err(ParseError::InvalidLength);
+        }
+        let (label, rest) = parameters.split_at(16);
+        let label = uuid::Uuid::from_slice(label).map_err(|_| ParseError::InvalidLength)?;
+        Ok((Destination::Virtual(label), rest))
+    } else {
+        let (address, rest) = parse_u16(parameters)?;
+        Ok((Destination::from_address(address).ok_or(ParseError::InvalidValue)?, rest))
+    }
+}
+
+fn emit_address(address: &Destination, xmit: &mut Vec<u8>) {
+    match address {
+        Destination::Virtual(label) => xmit.extend_from_slice(label.as_bytes()),
+        other => xmit.extend_from_slice(&other.address().to_le_bytes()),
+    }
+}
+
+/// Status of an application key of a remote node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AppKeyStatus {
+    /// Status code.
+    pub status: ConfigStatus,
+    /// Index of the network key the application key is bound to.
+    pub net_index: u16,
+    /// Index of the application key.
+    pub app_index: u16,
+}
+
+/// Raw composition data page of a remote node.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompositionDataStatus {
+    /// Page number.
+    pub page: u8,
+    /// Page contents.
+    pub data: Vec<u8>,
+}
+
+/// Status of an application key binding of a model of a remote node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModelAppStatus {
+    /// Status code.
+    pub status: ConfigStatus,
+    /// Unicast address of the element.
+    pub element: u16,
+    /// Index of the application key.
+    pub app_index: u16,
+    /// Model identifier.
+    pub model: ModelIdentifier,
+}
+
+/// Publication status of a model of a remote node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModelPublicationStatus {
+    /// Status code.
+    pub status: ConfigStatus,
+    /// Unicast address of the element.
+    pub element: u16,
+    /// Publish address, which is unassigned if publication is disabled.
+    pub address: u16,
+    /// Publication parameters.
+    pub parameters: PublicationParameters,
+    /// Model identifier.
+    pub model: ModelIdentifier,
+}
+
+/// Subscription status of a model of a remote node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModelSubscriptionStatus {
+    /// Status code.
+    pub status: ConfigStatus,
+    /// Unicast address of the element.
+    pub element: u16,
+    /// Subscription address.
+    pub address: u16,
+    /// Model identifier.
+    pub model: ModelIdentifier,
+}
+
+/// Configuration message.
+///
+/// Only the messages sent and received by the [ConfigClient] are supported.
+/// Application keys are added through [Node::add_app_key], since the mesh
+/// daemon does not send messages carrying keys on behalf of applications.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConfigMessage {
+    /// Application key status.
+    AppKeyStatus(AppKeyStatus),
+    /// Get a page of composition data.
+    CompositionDataGet(u8),
+    /// Page of composition data.
+    CompositionDataStatus(CompositionDataStatus),
+    /// Bind an application key to a model.
+    ModelAppBind {
+        /// Unicast address of the element.
+        element: u16,
+        /// Index of the application key.
+        app_index: u16,
+        /// Model identifier.
+        model: ModelIdentifier,
+    },
+    /// Application key binding status.
+    ModelAppStatus(ModelAppStatus),
+    /// Set the publication of a model.
+    ModelPublicationSet {
+        /// Unicast address of the element.
+        element: u16,
+        /// Publish address.
+        address: Destination,
+        /// Publication parameters.
+        parameters: PublicationParameters,
+        /// Model identifier.
+        model: ModelIdentifier,
+    },
+    /// Publication status.
+    ModelPublicationStatus(ModelPublicationStatus),
+    /// Add a subscription address to a model.
+    ModelSubscriptionAdd {
+        /// Unicast address of the element.
+        element: u16,
+        /// Subscription address.
+        address: Destination,
+        /// Model identifier.
+        model: ModelIdentifier,
+    },
+    /// Subscription status.
+    ModelSubscriptionStatus(ModelSubscriptionStatus),
+}
+
+impl ConfigMessage {
+    /// Parses a configuration message.
+    ///
+    /// Returns [None] if the opcode does not belong to a supported configuration message.
+    pub fn parse(opcode: &Opcode, parameters: &[u8]) -> Result<Option<Self>, ParseError> {
+        let msg = match *opcode {
+            APP_KEY_STATUS => match parameters {
+                [status, k0, k1, k2] => {
+                    let indexes = u32::from_le_bytes([*k0, *k1, *k2, 0]);
+                    Self::AppKeyStatus(AppKeyStatus {
+                        status: (*status).into(),
+                        net_index: (indexes & 0x0fff) as u16,
+                        app_index: (indexes >> 12) as u16,
+                    })
+                }
+                _ => return Err(ParseError::InvalidLength),
+            },
+            COMPOSITION_DATA_GET => match parameters {
+                [page] => Self::CompositionDataGet(*page),
+                _ => return Err(ParseError::InvalidLength),
+            },
+            COMPOSITION_DATA_STATUS => match parameters {
+                [page, data @ ..] => {
+                    Self::CompositionDataStatus(CompositionDataStatus { page: *page, data: data.to_vec() })
+                }
+                _ => return Err(ParseError::InvalidLength),
+            },
+            MODEL_APP_BIND => {
+                let (element, rest) = parse_u16(parameters)?;
+                let (app_index, rest) = parse_u16(rest)?;
+                Self::ModelAppBind { element, app_index, model: parse_model(rest)? }
+            }
+            MODEL_APP_STATUS => {
+                let (status, rest) = parameters.split_first().ok_or(ParseError::InvalidLength)?;
+                let (element, rest) = parse_u16(rest)?;
+                let (app_index, rest) = parse_u16(rest)?;
+                Self::ModelAppStatus(ModelAppStatus {
+                    status: (*status).into(),
+                    element,
+                    app_index,
+                    model: parse_model(rest)?,
+                })
+            }
+            MODEL_PUBLICATION_SET | MODEL_PUBLICATION_VIRTUAL_ADDRESS_SET => {
+                let (element, rest) = parse_u16(parameters)?;
+                let (address, rest) = parse_address(rest, *opcode == MODEL_PUBLICATION_VIRTUAL_ADDRESS_SET)?;
+                if rest.len() < 5 {
+                    return Err(ParseError::InvalidLength);
+                }
+                let (parameters, rest) = rest.split_at(5);
+                Self::ModelPublicationSet {
+                    element,
+                    address,
+                    parameters: PublicationParameters::parse(parameters)?,
+                    model: parse_model(rest)?,
+                }
+            }
+            MODEL_PUBLICATION_STATUS => {
+                let (status, rest) = parameters.split_first().ok_or(ParseError::InvalidLength)?;
+                let (element, rest) = parse_u16(rest)?;
+                let (address, rest) = parse_u16(rest)?;
+                if rest.len() < 5 {
+                    return Err(ParseError::InvalidLength);
+                }
+                let (parameters, rest) = rest.split_at(5);
+                Self::ModelPublicationStatus(ModelPublicationStatus {
+                    status: (*status).into(),
+                    element,
+                    address,
+                    parameters: PublicationParameters::parse(parameters)?,
+                    model: parse_model(rest)?,
+                })
+            }
+            MODEL_SUBSCRIPTION_ADD | MODEL_SUBSCRIPTION_VIRTUAL_ADDRESS_ADD => {
+                let (element, rest) = parse_u16(parameters)?;
+                let (address, rest) = parse_address(rest, *opcode == MODEL_SUBSCRIPTION_VIRTUAL_ADDRESS_ADD)?;
+                Self::ModelSubscriptionAdd { element, address, model: parse_model(rest)? }
+            }
+            MODEL_SUBSCRIPTION_STATUS => {
+                let (status, rest) = parameters.split_first().ok_or(ParseError::InvalidLength)?;
+                let (element, rest) = parse_u16(rest)?;
+                let (address, rest) = parse_u16(rest)?;
+                Self::ModelSubscriptionStatus(ModelSubscriptionStatus {
+                    status: (*status).into(),
+                    element,
+                    address,
+                    model: parse_model(rest)?,
+                })
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(msg))
+    }
+}
+
+impl Message for ConfigMessage {
+    fn opcode(&self) -> Opcode {
+        match self {
+            Self::AppKeyStatus(_) => APP_KEY_STATUS,
+            Self::CompositionDataGet(_) => COMPOSITION_DATA_GET,
+            Self::CompositionDataStatus(_) => COMPOSITION_DATA_STATUS,
+            Self::ModelAppBind { .. } => MODEL_APP_BIND,
+            Self::ModelAppStatus(_) => MODEL_APP_STATUS,
+            Self::ModelPublicationSet { address: Destination::Virtual(_), .. } => {
+                MODEL_PUBLICATION_VIRTUAL_ADDRESS_SET
+            }
+            Self::ModelPublicationSet { .. } => MODEL_PUBLICATION_SET,
+            Self::ModelPublicationStatus(_) => MODEL_PUBLICATION_STATUS,
+            Self::ModelSubscriptionAdd { address: Destination::Virtual(_), .. } => {
+                MODEL_SUBSCRIPTION_VIRTUAL_ADDRESS_ADD
+            }
+            Self::ModelSubscriptionAdd { .. } => MODEL_SUBSCRIPTION_ADD,
+            Self::ModelSubscriptionStatus(_) => MODEL_SUBSCRIPTION_STATUS,
+        }
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        match self {
+            Self::AppKeyStatus(status) => {
+                xmit.push(status.status.into());
+                let indexes = u32::from(status.net_index & 0x0fff) | u32::from(status.app_index & 0x0fff) << 12;
+                xmit.extend_from_slice(&indexes.to_le_bytes()[..3]);
+            }
+            Self::CompositionDataGet(page) => xmit.push(*page),
+            Self::CompositionDataStatus(status) => {
+                xmit.push(status.page);
+                xmit.extend_from_slice(&status.data);
+            }
+            Self::ModelAppBind { element, app_index, model } => {
+                xmit.extend_from_slice(&element.to_le_bytes());
+                xmit.extend_from_slice(&app_index.to_le_bytes());
+                emit_model(model, xmit);
+            }
+            Self::ModelAppStatus(status) => {
+                xmit.push(status.status.into());
+                xmit.extend_from_slice(&status.element.to_le_bytes());
+                xmit.extend_from_slice(&status.app_index.to_le_bytes());
+                emit_model(&status.model, xmit);
+            }
+            Self::ModelPublicationSet { element, address, parameters, model } => {
+                xmit.extend_from_slice(&element.to_le_bytes());
+                emit_address(address, xmit);
+                parameters.emit(xmit);
+                emit_model(model, xmit);
+            }
+            Self::ModelPublicationStatus(status) => {
+                xmit.push(status.status.into());
+                xmit.extend_from_slice(&status.element.to_le_bytes());
+                xmit.extend_from_slice(&status.address.to_le_bytes());
+                status.parameters.emit(xmit);
+                emit_model(&status.model, xmit);
+            }
+            Self::ModelSubscriptionAdd { element, address, model } => {
+                xmit.extend_from_slice(&element.to_le_bytes());
+                emit_address(address, xmit);
+                emit_model(model, xmit);
+            }
+            Self::ModelSubscriptionStatus(status) => {
+                xmit.push(status.status.into());
+                xmit.extend_from_slice(&status.element.to_le_bytes());
+                xmit.extend_from_slice(&status.address.to_le_bytes());
+                emit_model(&status.model, xmit);
+            }
+        }
+    }
+}
+
+/// Element of a node as described by its composition data.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompositionElement {
+    /// Location descriptor.
+    pub location: u16,
+    /// Models of the element, SIG models first.
+    pub models: Vec<ModelIdentifier>,
+}
+
+/// Composition data page 0 of a node.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompositionData {
+    /// Company identifier of the manufacturer.
+    pub company_id: CompanyIdentifier,
+    /// Vendor-assigned product identifier.
+    pub product_id: u16,
+    /// Vendor-assigned product version identifier.
+    pub version_id: u16,
+    /// Minimum number of replay protection list entries.
+    pub replay_protection: u16,
+    /// Features supported by the node.
+    pub features: NodeFeatures,
+    /// Elements of the node, starting with the primary element.
+    pub elements: Vec<CompositionElement>,
+}
+
+impl CompositionData {
+    /// Parses composition data page 0.
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let (company_id, rest) = parse_u16(data)?;
+        let (product_id, rest) = parse_u16(rest)?;
+        let (version_id, rest) = parse_u16(rest)?;
+        let (replay_protection, rest) = parse_u16(rest)?;
+        let (features, mut rest) = parse_u16(rest)?;
+
+        let mut elements = Vec::new();
+        while !rest.is_empty() {
+            let (location, models) = parse_u16(rest)?;
+            let (num_sig, num_vendor, mut models) = match models {
+                [num_sig, num_vendor, models @ ..] => (*num_sig, *num_vendor, models),
+                _ => return Err(ParseError::InvalidLength),
+            };
+
+            let mut element = CompositionElement { location, models: Vec::new() };
+            for n in 0..usize::from(num_sig) + usize::from(num_vendor) {
+                let len = if n < usize::from(num_sig) { 2 } else { 4 };
+                if models.len() < len {
+                    return Err(ParseError::InvalidLength);
+                }
+                let (model, next) = models.split_at(len);
+                element.models.push(parse_model(model)?);
+                models = next;
+            }
+            elements.push(element);
+            rest = models;
+        }
+
+        Ok(Self {
+            company_id: CompanyIdentifier(company_id),
+            product_id,
+            version_id,
+            replay_protection,
+            features: NodeFeatures {
+                relay: features & 0x01 != 0,
+                proxy: features & 0x02 != 0,
+                friend: features & 0x04 != 0,
+                low_power: features & 0x08 != 0,
+            },
+            elements,
+        })
+    }
+}
+
+/// Configuration Client model.
+///
+/// Add the model to [Element::models](crate::mesh::Element::models) and its
+/// [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the same element, so that status replies are received.
+///
+/// Each request is sent to the remote node encrypted with its device key and
+/// repeated if no status reply arrives in time.
+/// Status replies to concurrent requests to the same node are matched to the requests
+/// by the key indices, element and model they refer to.
+///
+/// The `path` argument of the requests is the path of the element
+/// the client belongs to, `dest` is the unicast address of the primary element
+/// of the remote node and `net_index` is the index of the network key used
+/// to send the request.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigClient {
+    app_keys: Arc<StatusWaiters<AppKeyStatus>>,
+    composition: Arc<StatusWaiters<CompositionDataStatus>>,
+    bindings: Arc<StatusWaiters<ModelAppStatus>>,
+    publications: Arc<StatusWaiters<ModelPublicationStatus>>,
+    subscriptions: Arc<StatusWaiters<ModelSubscriptionStatus>>,
+}
+
+impl ConfigClient {
+    /// Creates a client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handler receiving status replies.
+    pub fn handler(&self) -> HandlerFn<ConfigMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<ConfigMessage>| {
+            let unsolicited = match req.message {
+                ConfigMessage::AppKeyStatus(status) => {
+                    this.app_keys.deliver(req.src, req.dest, status).map(ConfigMessage::AppKeyStatus)
+                }
+                ConfigMessage::CompositionDataStatus(status) => {
+                    this.composition.deliver(req.src, req.dest, status).map(ConfigMessage::CompositionDataStatus)
+                }
+                ConfigMessage::ModelAppStatus(status) => {
+                    this.bindings.deliver(req.src, req.dest, status).map(ConfigMessage::ModelAppStatus)
+                }
+                ConfigMessage::ModelPublicationStatus(status) => this
+                    .publications
+                    .deliver(req.src, req.dest, status)
+                    .map(ConfigMessage::ModelPublicationStatus),
+                ConfigMessage::ModelSubscriptionStatus(status) => this
+                    .subscriptions
+                    .deliver(req.src, req.dest, status)
+                    .map(ConfigMessage::ModelSubscriptionStatus),
+                msg => Some(msg),
+            };
+            if let Some(msg) = unsolicited {
+                log::trace!("Unsolicited configuration message {:?} from {:04x}", msg, req.src);
+            }
+            Box::pin(async { None })
+        })
+    }
+
+    /// Sends a request until the status reply from `dest` for which `filter` returns true arrives.
+    async fn transact<T, F, Fut>(
+        waiters: &StatusWaiters<T>, dest: u16, filter: impl Fn(&T) -> bool + Send + 'static, send: F,
+    ) -> crate::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = crate::Result<()>>,
+    {
+        let mut rx = waiters.expect(dest, filter);
+        for attempt in 1..=ATTEMPTS {
+            send().await?;
+            match timeout(ATTEMPT_TIMEOUT, &mut rx).await {
+                Ok(Ok(status)) => return Ok(status),
+                Ok(Err(_)) => break,
+                Err(_) => log::debug!("No configuration status from {:04x} on attempt {}", dest, attempt),
+            }
+        }
+        Err(Error::new(ErrorKind::NoResponse))
+    }
+
+    /// Sends a configuration message to `dest` and waits for its status reply.
+    #[allow(clippy::too_many_arguments)]
+    async fn request<T>(
+        &self, waiters: &StatusWaiters<T>, node: &Node, path: Path<'_>, dest: u16, net_index: u16,
+        message: ConfigMessage, filter: impl Fn(&T) -> bool + Send + 'static,
+    ) -> crate::Result<T> {
+        Self::transact(waiters, dest, filter, || {
+            node.dev_key_send(path.clone(), dest, true, net_index, SendOptions::default(), &message)
+        })
+        .await
+    }
+
+    /// Gets composition data page 0 of the node at `dest`.
+    pub async fn composition_data(
+        &self, node: &Node, path: Path<'_>, dest: u16, net_index: u16,
+    ) -> crate::Result<CompositionData> {
+        let msg = ConfigMessage::CompositionDataGet(0);
+        let status =
+            self.request(&self.composition, node, path, dest, net_index, msg, |status| status.page == 0).await?;
+        CompositionData::parse(&status.data).map_err(|err| Error::new(ErrorKind::InvalidMessage(err)))
+    }
+
+    /// Adds the application key with index `app_index` of the local node
+    /// to the node at `dest`.
+    ///
+    /// The key is bound to the network key it is bound to in the local node.
+    pub async fn app_key_add(
+        &self, node: &Node, path: Path<'_>, dest: u16, net_index: u16, app_index: u16,
+    ) -> crate::Result<()> {
+        let status = Self::transact(
+            &self.app_keys,
+            dest,
+            move |status| status.app_index == app_index,
+            || node.add_app_key(path.clone(), dest, app_index, net_index, false),
+        )
+        .await?;
+        status.status.into_result()
+    }
+
+    /// Binds the application key with index `app_index` to a model of the element
+    /// with unicast address `element` of the node at `dest`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn model_app_bind(
+        &self, node: &Node, path: Path<'_>, dest: u16, net_index: u16, element: u16, app_index: u16,
+        model: ModelIdentifier,
+    ) -> crate::Result<()> {
+        let msg = ConfigMessage::ModelAppBind { element, app_index, model };
+        let status = self
+            .request(&self.bindings, node, path, dest, net_index, msg, move |status| {
+                status.element == element && status.app_index == app_index && status.model == model
+            })
+            .await?;
+        status.status.into_result()
+    }
+
+    /// Sets the publication of a model of the element with unicast address `element`
+    /// of the node at `dest`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn model_publication_set(
+        &self, node: &Node, path: Path<'_>, dest: u16, net_index: u16, element: u16, address: Destination,
+        parameters: PublicationParameters, model: ModelIdentifier,
+    ) -> crate::Result<()> {
+        let msg = ConfigMessage::ModelPublicationSet { element, address, parameters, model };
+        let status = self
+            .request(&self.publications, node, path, dest, net_index, msg, move |status| {
+                status.element == element && status.model == model
+            })
+            .await?;
+        status.status.into_result()
+    }
+
+    /// Subscribes a model of the element with unicast address `element`
+    /// of the node at `dest` to `address`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn model_subscription_add(
+        &self, node: &Node, path: Path<'_>, dest: u16, net_index: u16, element: u16, address: Destination,
+        model: ModelIdentifier,
+    ) -> crate::Result<()> {
+        let msg = ConfigMessage::ModelSubscriptionAdd { element, address, model };
+        let status = self
+            .request(&self.subscriptions, node, path, dest, net_index, msg, move |status| {
+                status.element == element && status.model == model
+            })
+            .await?;
+        status.status.into_result()
+    }
+}
+
+impl Model for ConfigClient {
+    fn identifier(&self) -> ModelIdentifier {
+        CONFIGURATION_CLIENT
+    }
+
+    fn supports_subscription(&self) -> bool {
+        false
+    }
+
+    fn supports_publication(&self) -> bool {
+        false
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(ConfigMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for ConfigClient {
+    type Message = ConfigMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<ConfigMessage>, ParseError> {
+        ConfigMessage::parse(opcode, parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emit(msg: &ConfigMessage) -> Vec<u8> {
+        let mut xmit = Vec::new();
+        msg.emit_parameters(&mut xmit);
+        xmit
+    }
+
+    #[test]
+    fn composition_data() {
+        let data = [
+            0x0c, 0x00, 0x1a, 0x00, 0x01, 0x00, 0x08, 0x00, 0x03, 0x00, // header
+            0x00, 0x01, 0x03, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x10, 0x3f, 0x00, 0x2a, 0x00, // element 0
+            0x00, 0x00, 0x01, 0x00, 0x02, 0x10, // element 1
+        ];
+        let composition = CompositionData::parse(&data).unwrap();
+        assert_eq!(composition.company_id, CompanyIdentifier(0x000c));
+        assert_eq!(composition.product_id, 0x001a);
+        assert_eq!(composition.version_id, 0x0001);
+        assert_eq!(composition.replay_protection, 0x0008);
+        assert_eq!(
+            composition.features,
+            NodeFeatures { relay: true, proxy: true, friend: false, low_power: false }
+        );
+        assert_eq!(
+            composition.elements,
+            [
+                CompositionElement {
+                    location: 0x0100,
+                    models: vec![
+                        ModelIdentifier::SIG(0x0000),
+                        ModelIdentifier::SIG(0x0002),
+                        ModelIdentifier::SIG(0x1000),
+                        ModelIdentifier::Vendor(CompanyIdentifier(0x003f), 0x002a),
+                    ],
+                },
+                CompositionElement { location: 0x0000, models: vec![ModelIdentifier::SIG(0x1002)] },
+            ]
+        );
+
+        assert!(CompositionData::parse(&data[..10]).unwrap().elements.is_empty());
+        assert_eq!(CompositionData::parse(&data[..9]), Err(ParseError::InvalidLength));
+        assert_eq!(CompositionData::parse(&data[..13]), Err(ParseError::InvalidLength));
+        assert_eq!(CompositionData::parse(&data[..22]), Err(ParseError::InvalidLength));
+        assert_eq!(CompositionData::parse(&data[..29]), Err(ParseError::InvalidLength));
+    }
+
+    #[test]
+    fn publication_parameters() {
+        let parameters = PublicationParameters {
+            app_key: 0x0123,
+            friendship_credentials: true,
+            ttl: 5,
+            period: Some(Duration::from_secs(10)),
+            retransmit_count: 2,
+            retransmit_interval: Duration::from_millis(150),
+        };
+        let mut xmit = Vec::new();
+        parameters.emit(&mut xmit);
+        assert_eq!(xmit, [0x23, 0x11, 0x05, 0x4a, 0x12]);
+        assert_eq!(PublicationParameters::parse(&xmit), Ok(parameters));
+        assert_eq!(PublicationParameters::parse(&xmit[..4]), Err(ParseError::InvalidLength));
+    }
+
+    #[test]
+    fn publication_retransmit() {
+        let encode = |retransmit_count, retransmit_interval| {
+            let mut xmit = Vec::new();
+            PublicationParameters { retransmit_count, retransmit_interval, ..Default::default() }.emit(&mut xmit);
+            xmit[4]
+        };
+        assert_eq!(encode(0, Duration::from_millis(50)), 0x00);
+        assert_eq!(encode(9, Duration::ZERO), 0x07);
+        assert_eq!(encode(1, Duration::from_millis(1_600)), 0xf9);
+        assert_eq!(encode(1, Duration::from_secs(2)), 0xf9);
+        assert_eq!(encode(1, Duration::from_millis(120)), 0x09);
+
+        let parameters = PublicationParameters::parse(&[0x00, 0x00, 0xff, 0x00, 0xff]).unwrap();
+        assert_eq!(parameters.retransmit_count, 7);
+        assert_eq!(parameters.retransmit_interval, Duration::from_millis(1_600));
+        assert_eq!(parameters.period, None);
+    }
+
+    #[test]
+    fn publish_period() {
+        assert_eq!(encode_period(None), 0x00);
+        assert_eq!(encode_period(Some(Duration::from_millis(6_300))), 0x3f);
+        assert_eq!(encode_period(Some(Duration::from_secs(10))), 0x4a);
+        assert_eq!(encode_period(Some(Duration::from_secs(64))), 0x86);
+        assert_eq!(encode_period(Some(Duration::from_secs(3_600))), 0xc6);
+        assert_eq!(encode_period(Some(Duration::from_secs(100_000))), 0xff);
+
+        assert_eq!(decode_period(0x00), None);
+        assert_eq!(decode_period(0xc0), None);
+        assert_eq!(decode_period(0x3f), Some(Duration::from_millis(6_300)));
+        assert_eq!(decode_period(0x86), Some(Duration::from_secs(60)));
+        assert_eq!(decode_period(0xff), Some(Duration::from_secs(63 * 600)));
+    }
+
+    #[test]
+    fn app_key_status_indices() {
+        let status = ConfigMessage::AppKeyStatus(AppKeyStatus {
+            status: ConfigStatus::Success,
+            net_index: 0x0123,
+            app_index: 0x0456,
+        });
+        assert_eq!(emit(&status), [0x00, 0x23, 0x61, 0x45]);
+        assert_eq!(ConfigMessage::parse(&APP_KEY_STATUS, &[0x00, 0x23, 0x61, 0x45]), Ok(Some(status)));
+
+        let max = ConfigMessage::parse(&APP_KEY_STATUS, &[0x06, 0xff, 0xff, 0xff]).unwrap().unwrap();
+        assert_eq!(
+            max,
+            ConfigMessage::AppKeyStatus(AppKeyStatus {
+                status: ConfigStatus::KeyIndexAlreadyStored,
+                net_index: 0x0fff,
+                app_index: 0x0fff,
+            })
+        );
+        assert_eq!(ConfigMessage::parse(&APP_KEY_STATUS, &[0x00, 0x23, 0x61]), Err(ParseError::InvalidLength));
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let label = uuid::Uuid::from_u128(0x0073e7e4_d8b9_440f_af84_15df4c56c0e1);
+        let msgs = [
+            ConfigMessage::CompositionDataGet(0),
+            ConfigMessage::ModelAppBind { element: 0x0102, app_index: 1, model: ModelIdentifier::SIG(0x1000) },
+            ConfigMessage::ModelAppStatus(ModelAppStatus {
+                status: ConfigStatus::InvalidModel,
+                element: 0x0102,
+                app_index: 1,
+                model: ModelIdentifier::Vendor(CompanyIdentifier(0x05f1), 0x0001),
+            }),
+            ConfigMessage::ModelPublicationSet {
+                element: 0x0102,
+                address: Destination::Group(0xc000),
+                parameters: PublicationParameters::default(),
+                model: ModelIdentifier::SIG(0x1100),
+            },
+            ConfigMessage::ModelPublicationSet {
+                element: 0x0102,
+                address: Destination::Virtual(label),
+                parameters: PublicationParameters::default(),
+                model: ModelIdentifier::SIG(0x1100),
+            },
+            ConfigMessage::ModelSubscriptionAdd {
+                element: 0x0102,
+                address: Destination::Virtual(label),
+                model: ModelIdentifier::SIG(0x1001),
+            },
+            ConfigMessage::ModelSubscriptionStatus(ModelSubscriptionStatus {
+                status: ConfigStatus::Success,
+                element: 0x0102,
+                address: 0xc001,
+                model: ModelIdentifier::SIG(0x1001),
+            }),
+        ];
+        for msg in msgs {
+            assert_eq!(ConfigMessage::parse(&msg.opcode(), &emit(&msg)), Ok(Some(msg)));
+        }
+    }
+}
@@ -0,0 +1,831 @@
+//! Generic OnOff and Generic Level models.
+//!
+//! Transitions requested by a client are not executed by the servers.
+//! Instead, the target state is applied immediately and the requested
+//! [Transition] is passed on in the state, so that the application can
+//! perform it on the controlled device.
+
+use dbus::Path;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::watch;
+
+use super::{StatusWaiters, TidCache};
+use crate::mesh::{
+    dispatcher::{HandlerFn, Reply, Request, TypedModel},
+    node::{Node, SendOptions},
+    Destination, Message, Model, ModelIdentifier, Opcode, ParseError,
+};
+
+/// Identifier of the Generic OnOff Server model.
+pub const GENERIC_ONOFF_SERVER: ModelIdentifier = ModelIdentifier::SIG(0x1000);
+/// Identifier of the Generic OnOff Client model.
+pub const GENERIC_ONOFF_CLIENT: ModelIdentifier = ModelIdentifier::SIG(0x1001);
+/// Identifier of the Generic Level Server model.
+pub const GENERIC_LEVEL_SERVER: ModelIdentifier = ModelIdentifier::SIG(0x1002);
+/// Identifier of the Generic Level Client model.
+pub const GENERIC_LEVEL_CLIENT: ModelIdentifier = ModelIdentifier::SIG(0x1003);
+
+const ONOFF_GET: Opcode = Opcode::TwoOctet(0x82, 0x01);
+const ONOFF_SET: Opcode = Opcode::TwoOctet(0x82, 0x02);
+const ONOFF_SET_UNACKNOWLEDGED: Opcode = Opcode::TwoOctet(0x82, 0x03);
+const ONOFF_STATUS: Opcode = Opcode::TwoOctet(0x82, 0x04);
+
+const LEVEL_GET: Opcode = Opcode::TwoOctet(0x82, 0x05);
+const LEVEL_SET: Opcode = Opcode::TwoOctet(0x82, 0x06);
+const LEVEL_SET_UNACKNOWLEDGED: Opcode = Opcode::TwoOctet(0x82, 0x07);
+const LEVEL_STATUS: Opcode = Opcode::TwoOctet(0x82, 0x08);
+
+/// Step resolutions of a transition time in milliseconds.
+const STEP_RESOLUTIONS: [u64; 4] = [100, 1_000, 10_000, 600_000];
+
+/// Number of steps denoting an unknown transition time.
+const UNKNOWN_STEPS: u8 = 0x3f;
+
+/// Duration of a delay step in milliseconds.
+const DELAY_STEP: u64 = 5;
+
+/// Time of a state transition.
+///
+/// It is encoded as a number of steps with a step resolution
+/// of 100 milliseconds, 1 second, 10 seconds or 10 minutes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransitionTime(pub u8);
+
+impl TransitionTime {
+    /// Immediate transition.
+    pub const IMMEDIATE: Self = Self(0);
+
+    /// Unknown transition time.
+    pub const UNKNOWN: Self = Self(UNKNOWN_STEPS);
+
+    /// Encodes a duration using the finest step resolution it fits into.
+    ///
+    /// Durations are rounded to the nearest step and longer durations
+    /// than representable are saturated.
+    pub fn from_duration(duration: Duration) -> Self {
+        let millis = duration.as_millis().min(u64::MAX as u128) as u64;
+        for (resolution, step) in STEP_RESOLUTIONS.iter().enumerate() {
+            let steps = millis.saturating_add(step / 2) / step;
+            if steps < UNKNOWN_STEPS as u64 {
+                return Self((resolution as u8) << 6 | steps as u8);
+            }
+        }
+        Self(3 << 6 | (UNKNOWN_STEPS - 1))
+    }
+
+    /// Duration of the transition.
+    ///
+    /// Returns [None] if the transition time is unknown.
+    pub fn duration(&self) -> Option<Duration> {
+        let steps = self.0 & 0x3f;
+        if steps == UNKNOWN_STEPS {
+            return None;
+        }
+        Some(Duration::from_millis(steps as u64 * STEP_RESOLUTIONS[(self.0 >> 6) as usize]))
+    }
+}
+
+/// Transition to a new state requested by a client.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transition {
+    /// Time the transition takes.
+    pub time: TransitionTime,
+    /// Delay before the transition starts.
+    ///
+    /// It is transmitted in steps of 5 milliseconds up to 1.275 seconds.
+    pub delay: Duration,
+}
+
+impl Transition {
+    fn parse(parameters: &[u8]) -> Result<Option<Self>, ParseError> {
+        match parameters {
+            [] => Ok(None),
+            [time, delay] => Ok(Some(Self {
+                time: TransitionTime(*time),
+                delay: Duration::from_millis(*delay as u64 * DELAY_STEP),
+            })),
+            _ => Err(ParseError::InvalidLength),
+        }
+    }
+
+    fn emit(transition: &Option<Self>, xmit: &mut Vec<u8>) {
+        if let Some(transition) = transition {
+            let delay = (transition.delay.as_millis() / DELAY_STEP as u128).min(u8::MAX as u128) as u8;
+            xmit.extend_from_slice(&[transition.time.0, delay]);
+        }
+    }
+}
+
+/// Parses a target state with its remaining transition time.
+fn parse_target<T>(
+    parameters: &[u8], parse: impl FnOnce(&[u8]) -> Result<T, ParseError>,
+) -> Result<Option<(T, TransitionTime)>, ParseError> {
+    match parameters {
+        [] => Ok(None),
+        [target @ .., remaining] => Ok(Some((parse(target)?, TransitionTime(*remaining)))),
+    }
+}
+
+// ------------
+// Generic OnOff
+// ------------
+
+fn parse_on_off(value: u8) -> Result<bool, ParseError> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ParseError::InvalidValue),
+    }
+}
+
+/// Request to change the Generic OnOff state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OnOffSet {
+    /// Target state.
+    pub on_off: bool,
+    /// Transaction identifier.
+    ///
+    /// Retransmissions of the same request must use the same identifier.
+    pub tid: u8,
+    /// Requested transition.
+    ///
+    /// If unset, the server uses its default transition.
+    pub transition: Option<Transition>,
+}
+
+/// Current Generic OnOff state of a server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OnOffStatus {
+    /// Present state.
+    pub present: bool,
+    /// Target state and remaining time, if a transition is in progress.
+    pub target: Option<(bool, TransitionTime)>,
+}
+
+/// Generic OnOff message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OnOffMessage {
+    /// Get the state.
+    Get,
+    /// Set the state and reply with the status.
+    Set(OnOffSet),
+    /// Set the state without reply.
+    SetUnacknowledged(OnOffSet),
+    /// State of a server.
+    Status(OnOffStatus),
+}
+
+impl OnOffMessage {
+    /// Parses a Generic OnOff message.
+    ///
+    /// Returns [None] if the opcode does not belong to a Generic OnOff message.
+    pub fn parse(opcode: &Opcode, parameters: &[u8]) -> Result<Option<Self>, ParseError> {
+        let parse_set = |parameters: &[u8]| match parameters {
+            [on_off, tid, transition @ ..] => Ok(OnOffSet {
+                on_off: parse_on_off(*on_off)?,
+                tid: *tid,
+                transition: Transition::parse(transition)?,
+            }),
+            _ => Err(ParseError::InvalidLength),
+        };
+
+        let msg = match *opcode {
+            ONOFF_GET if parameters.is_empty() => Self::Get,
+            ONOFF_GET => return Err(ParseError::InvalidLength),
+            ONOFF_SET => Self::Set(parse_set(parameters)?),
+            ONOFF_SET_UNACKNOWLEDGED => Self::SetUnacknowledged(parse_set(parameters)?),
+            ONOFF_STATUS => match parameters {
+                [present, target @ ..] => Self::Status(OnOffStatus {
+                    present: parse_on_off(*present)?,
+                    target: parse_target(target, |target| match target {
+                        [target] => parse_on_off(*target),
+                        _ => Err(ParseError::InvalidLength),
+                    })?,
+                }),
+                _ => return Err(ParseError::InvalidLength),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(msg))
+    }
+}
+
+impl Message for OnOffMessage {
+    fn opcode(&self) -> Opcode {
+        match self {
+            Self::Get => ONOFF_GET,
+            Self::Set(_) => ONOFF_SET,
+            Self::SetUnacknowledged(_) => ONOFF_SET_UNACKNOWLEDGED,
+            Self::Status(_) => ONOFF_STATUS,
+        }
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        match self {
+            Self::Get => (),
+            Self::Set(set) | Self::SetUnacknowledged(set) => {
+                xmit.extend_from_slice(&[set.on_off as u8, set.tid]);
+                Transition::emit(&set.transition, xmit);
+            }
+            Self::Status(status) => {
+                xmit.push(status.present as u8);
+                if let Some((target, remaining)) = status.target {
+                    xmit.extend_from_slice(&[target as u8, remaining.0]);
+                }
+            }
+        }
+    }
+}
+
+/// Generic OnOff state of a server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OnOffState {
+    /// Whether the controlled device is on.
+    pub on_off: bool,
+    /// Transition requested by the client that changed the state last.
+    pub transition: Option<Transition>,
+}
+
+/// Generic OnOff Server model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to and [watch](Self::watch) the state
+/// to control the device.
+#[derive(Clone, Debug)]
+pub struct GenericOnOffServer {
+    state: Arc<watch::Sender<OnOffState>>,
+    tids: Arc<TidCache>,
+}
+
+impl GenericOnOffServer {
+    /// Creates a server with the specified initial state.
+    pub fn new(on_off: bool) -> Self {
+        let (state, _) = watch::channel(OnOffState { on_off, transition: None });
+        Self { state: Arc::new(state), tids: Arc::new(TidCache::default()) }
+    }
+
+    /// Current state.
+    pub fn state(&self) -> OnOffState {
+        *self.state.borrow()
+    }
+
+    /// Changes the state locally, for example when the device has been switched by hand.
+    pub fn set_state(&self, on_off: bool) {
+        self.state.send_replace(OnOffState { on_off, transition: None });
+    }
+
+    /// Watch state changes.
+    pub fn watch(&self) -> watch::Receiver<OnOffState> {
+        self.state.subscribe()
+    }
+
+    /// Handler answering Get and Set requests.
+    pub fn handler(&self) -> HandlerFn<OnOffMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<OnOffMessage>| {
+            let reply = this.handle(req);
+            Box::pin(async move { reply })
+        })
+    }
+
+    fn handle(&self, req: Request<OnOffMessage>) -> Option<Reply> {
+        let (set, acknowledged) = match req.message {
+            OnOffMessage::Get => (None, true),
+            OnOffMessage::Set(set) => (Some(set), true),
+            OnOffMessage::SetUnacknowledged(set) => (Some(set), false),
+            OnOffMessage::Status(_) => return None,
+        };
+
+        if let Some(set) = set {
+            if self.tids.is_new(req.src, set.tid) {
+                self.state.send_replace(OnOffState { on_off: set.on_off, transition: set.transition });
+            }
+        }
+
+        if acknowledged {
+            let status = OnOffStatus { present: self.state().on_off, target: None };
+            Some(Box::new(OnOffMessage::Status(status)))
+        } else {
+            None
+        }
+    }
+}
+
+impl Model for GenericOnOffServer {
+    fn identifier(&self) -> ModelIdentifier {
+        GENERIC_ONOFF_SERVER
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(OnOffMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for GenericOnOffServer {
+    type Message = OnOffMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<OnOffMessage>, ParseError> {
+        OnOffMessage::parse(opcode, parameters)
+    }
+}
+
+/// Generic OnOff Client model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to, so that status replies are received.
+#[derive(Clone, Debug, Default)]
+pub struct GenericOnOffClient {
+    waiters: Arc<StatusWaiters<OnOffStatus>>,
+}
+
+impl GenericOnOffClient {
+    /// Creates a client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handler receiving status replies.
+    pub fn handler(&self) -> HandlerFn<OnOffMessage> {
+        let waiters = self.waiters.clone();
+        Box::new(move |req: Request<OnOffMessage>| {
+            if let OnOffMessage::Status(status) = req.message {
+                if let Some(status) = waiters.deliver(req.src, req.dest, status) {
+                    log::trace!("Unsolicited Generic OnOff status {:?} from {:04x}", status, req.src);
+                }
+            }
+            Box::pin(async { None })
+        })
+    }
+
+    /// Gets the state of the server at `dest`.
+    ///
+    /// `path` is the path of the element the client belongs to and
+    /// `app_key` is the index of the application key used to encrypt the request.
+    pub async fn get(&self, node: &Node, path: Path<'_>, dest: u16, app_key: u16) -> crate::Result<OnOffStatus> {
+        self.waiters.request(node, path, dest, app_key, &OnOffMessage::Get, |_| true).await
+    }
+
+    /// Sets the state of the server at `dest` and returns its new status.
+    pub async fn set(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, set: OnOffSet,
+    ) -> crate::Result<OnOffStatus> {
+        self.waiters.request(node, path, dest, app_key, &OnOffMessage::Set(set), |_| true).await
+    }
+
+    /// Sets the state of the servers at `dest` without waiting for a reply.
+    pub async fn set_unacknowledged(
+        &self, node: &Node, path: Path<'_>, dest: Destination, app_key: u16, set: OnOffSet,
+    ) -> crate::Result<()> {
+        node.send(path, dest, app_key, SendOptions::default(), &OnOffMessage::SetUnacknowledged(set)).await
+    }
+}
+
+impl Model for GenericOnOffClient {
+    fn identifier(&self) -> ModelIdentifier {
+        GENERIC_ONOFF_CLIENT
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(OnOffMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for GenericOnOffClient {
+    type Message = OnOffMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<OnOffMessage>, ParseError> {
+        OnOffMessage::parse(opcode, parameters)
+    }
+}
+
+// -------------
+// Generic Level
+// -------------
+
+fn parse_level(value: &[u8]) -> Result<i16, ParseError> {
+    match value {
+        [l0, l1] => Ok(i16::from_le_bytes([*l0, *l1])),
+        _ => Err(ParseError::InvalidLength),
+    }
+}
+
+/// Request to change the Generic Level state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelSet {
+    /// Target level.
+    pub level: i16,
+    /// Transaction identifier.
+    ///
+    /// Retransmissions of the same request must use the same identifier.
+    pub tid: u8,
+    /// Requested transition.
+    ///
+    /// If unset, the server uses its default transition.
+    pub transition: Option<Transition>,
+}
+
+/// Current Generic Level state of a server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelStatus {
+    /// Present level.
+    pub present: i16,
+    /// Target level and remaining time, if a transition is in progress.
+    pub target: Option<(i16, TransitionTime)>,
+}
+
+/// Generic Level message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LevelMessage {
+    /// Get the level.
+    Get,
+    /// Set the level and reply with the status.
+    Set(LevelSet),
+    /// Set the level without reply.
+    SetUnacknowledged(LevelSet),
+    /// Level of a server.
+    Status(LevelStatus),
+}
+
+impl LevelMessage {
+    /// Parses a Generic Level message.
+    ///
+    /// Returns [None] if the opcode does not belong to a Generic Level message.
+    pub fn parse(opcode: &Opcode, parameters: &[u8]) -> Result<Option<Self>, ParseError> {
+        let parse_set = |parameters: &[u8]| match parameters {
+            [l0, l1, tid, transition @ ..] => Ok(LevelSet {
+                level: i16::from_le_bytes([*l0, *l1]),
+                tid: *tid,
+                transition: Transition::parse(transition)?,
+            }),
+            _ => Err(ParseError::InvalidLength),
+        };
+
+        let msg = match *opcode {
+            LEVEL_GET if parameters.is_empty() => Self::Get,
+            LEVEL_GET => return Err(ParseError::InvalidLength),
+            LEVEL_SET => Self::Set(parse_set(parameters)?),
+            LEVEL_SET_UNACKNOWLEDGED => Self::SetUnacknowledged(parse_set(parameters)?),
+            LEVEL_STATUS => match parameters {
+                [p0, p1, target @ ..] => Self::Status(LevelStatus {
+                    present: i16::from_le_bytes([*p0, *p1]),
+                    target: parse_target(target, parse_level)?,
+                }),
+                _ => return Err(ParseError::InvalidLength),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(msg))
+    }
+}
+
+impl Message for LevelMessage {
+    fn opcode(&self) -> Opcode {
+        match self {
+            Self::Get => LEVEL_GET,
+            Self::Set(_) => LEVEL_SET,
+            Self::SetUnacknowledged(_) => LEVEL_SET_UNACKNOWLEDGED,
+            Self::Status(_) => LEVEL_STATUS,
+        }
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        match self {
+            Self::Get => (),
+            Self::Set(set) | Self::SetUnacknowledged(set) => {
+                xmit.extend_from_slice(&set.level.to_le_bytes());
+                xmit.push(set.tid);
+                Transition::emit(&set.transition, xmit);
+            }
+            Self::Status(status) => {
+                xmit.extend_from_slice(&status.present.to_le_bytes());
+                if let Some((target, remaining)) = status.target {
+                    xmit.extend_from_slice(&target.to_le_bytes());
+                    xmit.push(remaining.0);
+                }
+            }
+        }
+    }
+}
+
+/// Generic Level state of a server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelState {
+    /// Level of the controlled device.
+    pub level: i16,
+    /// Transition requested by the client that changed the state last.
+    pub transition: Option<Transition>,
+}
+
+/// Generic Level Server model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to and [watch](Self::watch) the state
+/// to control the device.
+#[derive(Clone, Debug)]
+pub struct GenericLevelServer {
+    state: Arc<watch::Sender<LevelState>>,
+    tids: Arc<TidCache>,
+}
+
+impl GenericLevelServer {
+    /// Creates a server with the specified initial level.
+    pub fn new(level: i16) -> Self {
+        let (state, _) = watch::channel(LevelState { level, transition: None });
+        Self { state: Arc::new(state), tids: Arc::new(TidCache::default()) }
+    }
+
+    /// Current state.
+    pub fn state(&self) -> LevelState {
+        *self.state.borrow()
+    }
+
+    /// Changes the level locally, for example when the device has been adjusted by hand.
+    pub fn set_state(&self, level: i16) {
+        self.state.send_replace(LevelState { level, transition: None });
+    }
+
+    /// Watch state changes.
+    pub fn watch(&self) -> watch::Receiver<LevelState> {
+        self.state.subscribe()
+    }
+
+    /// Handler answering Get and Set requests.
+    pub fn handler(&self) -> HandlerFn<LevelMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<LevelMessage>| {
+            let reply = this.handle(req);
+            Box::pin(async move { reply })
+        })
+    }
+
+    fn handle(&self, req: Request<LevelMessage>) -> Option<Reply> {
+        let (set, acknowledged) = match req.message {
+            LevelMessage::Get => (None, true),
+            LevelMessage::Set(set) => (Some(set), true),
+            LevelMessage::SetUnacknowledged(set) => (Some(set), false),
+            LevelMessage::Status(_) => return None,
+        };
+
+        if let Some(set) = set {
+            if self.tids.is_new(req.src, set.tid) {
+                self.state.send_replace(LevelState { level: set.level, transition: set.transition });
+            }
+        }
+
+        if acknowledged {
+            let status = LevelStatus { present: self.state().level, target: None };
+            Some(Box::new(LevelMessage::Status(status)))
+        } else {
+            None
+        }
+    }
+}
+
+impl Model for GenericLevelServer {
+    fn identifier(&self) -> ModelIdentifier {
+        GENERIC_LEVEL_SERVER
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(LevelMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for GenericLevelServer {
+    type Message = LevelMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<LevelMessage>, ParseError> {
+        LevelMessage::parse(opcode, parameters)
+    }
+}
+
+/// Generic Level Client model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to, so that status replies are received.
+#[derive(Clone, Debug, Default)]
+pub struct GenericLevelClient {
+    waiters: Arc<StatusWaiters<LevelStatus>>,
+}
+
+impl GenericLevelClient {
+    /// Creates a client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handler receiving status replies.
+    pub fn handler(&self) -> HandlerFn<LevelMessage> {
+        let waiters = self.waiters.clone();
+        Box::new(move |req: Request<LevelMessage>| {
+            if let LevelMessage::Status(status) = req.message {
+                if let Some(status) = waiters.deliver(req.src, req.dest, status) {
+                    log::trace!("Unsolicited Generic Level status {:?} from {:04x}", status, req.src);
+                }
+            }
+            Box::pin(async { None })
+        })
+    }
+
+    /// Gets the level of the server at `dest`.
+    ///
+    /// `path` is the path of the element the client belongs to and
+    /// `app_key` is the index of the application key used to encrypt the request.
+    pub async fn get(&self, node: &Node, path: Path<'_>, dest: u16, app_key: u16) -> crate::Result<LevelStatus> {
+        self.waiters.request(node, path, dest, app_key, &LevelMessage::Get, |_| true).await
+    }
+
+    /// Sets the level of the server at `dest` and returns its new status.
+    pub async fn set(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, set: LevelSet,
+    ) -> crate::Result<LevelStatus> {
+        self.waiters.request(node, path, dest, app_key, &LevelMessage::Set(set), |_| true).await
+    }
+
+    /// Sets the level of the servers at `dest` without waiting for a reply.
+    pub async fn set_unacknowledged(
+        &self, node: &Node, path: Path<'_>, dest: Destination, app_key: u16, set: LevelSet,
+    ) -> crate::Result<()> {
+        node.send(path, dest, app_key, SendOptions::default(), &LevelMessage::SetUnacknowledged(set)).await
+    }
+}
+
+impl Model for GenericLevelClient {
+    fn identifier(&self) -> ModelIdentifier {
+        GENERIC_LEVEL_CLIENT
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(LevelMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for GenericLevelClient {
+    type Message = LevelMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<LevelMessage>, ParseError> {
+        LevelMessage::parse(opcode, parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emit(msg: &dyn Message) -> Vec<u8> {
+        let mut xmit = Vec::new();
+        msg.emit_parameters(&mut xmit);
+        xmit
+    }
+
+    #[test]
+    fn transition_time_resolution() {
+        assert_eq!(TransitionTime::from_duration(Duration::ZERO), TransitionTime::IMMEDIATE);
+        assert_eq!(TransitionTime::from_duration(Duration::from_millis(6_200)), TransitionTime(0x3e));
+        assert_eq!(TransitionTime::from_duration(Duration::from_millis(6_250)), TransitionTime(0x46));
+        assert_eq!(TransitionTime::from_duration(Duration::from_secs(62)), TransitionTime(0x7e));
+        assert_eq!(TransitionTime::from_duration(Duration::from_secs(63)), TransitionTime(0x86));
+        assert_eq!(TransitionTime::from_duration(Duration::from_secs(630)), TransitionTime(0xc1));
+
+        assert_eq!(TransitionTime(0x3e).duration(), Some(Duration::from_millis(6_200)));
+        assert_eq!(TransitionTime(0x46).duration(), Some(Duration::from_secs(6)));
+        assert_eq!(TransitionTime(0x86).duration(), Some(Duration::from_secs(60)));
+        assert_eq!(TransitionTime(0xc1).duration(), Some(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn transition_time_saturates() {
+        let max = TransitionTime::from_duration(Duration::from_secs(24 * 3600));
+        assert_eq!(max, TransitionTime(0xfe));
+        assert_eq!(max.duration(), Some(Duration::from_secs(62 * 600)));
+        assert_eq!(TransitionTime::from_duration(Duration::MAX), max);
+    }
+
+    #[test]
+    fn transition_time_unknown() {
+        assert_eq!(TransitionTime::UNKNOWN.duration(), None);
+        for resolution in 0..4 {
+            assert_eq!(TransitionTime(resolution << 6 | 0x3f).duration(), None);
+        }
+    }
+
+    #[test]
+    fn on_off_round_trip() {
+        let msgs = [
+            OnOffMessage::Get,
+            OnOffMessage::Set(OnOffSet { on_off: true, tid: 5, transition: None }),
+            OnOffMessage::SetUnacknowledged(OnOffSet {
+                on_off: false,
+                tid: 6,
+                transition: Some(Transition { time: TransitionTime(0x46), delay: Duration::from_millis(100) }),
+            }),
+            OnOffMessage::Status(OnOffStatus { present: false, target: None }),
+            OnOffMessage::Status(OnOffStatus { present: false, target: Some((true, TransitionTime(0x0a))) }),
+        ];
+        for msg in msgs {
+            let parameters = emit(&msg);
+            assert_eq!(OnOffMessage::parse(&msg.opcode(), &parameters), Ok(Some(msg)));
+        }
+
+        let set = OnOffMessage::SetUnacknowledged(OnOffSet {
+            on_off: true,
+            tid: 6,
+            transition: Some(Transition { time: TransitionTime(0x46), delay: Duration::from_millis(100) }),
+        });
+        assert_eq!(emit(&set), [0x01, 0x06, 0x46, 0x14]);
+    }
+
+    #[test]
+    fn on_off_invalid() {
+        assert_eq!(OnOffMessage::parse(&ONOFF_GET, &[0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(OnOffMessage::parse(&ONOFF_SET, &[0x02, 0x00]), Err(ParseError::InvalidValue));
+        assert_eq!(OnOffMessage::parse(&ONOFF_SET, &[0x01]), Err(ParseError::InvalidLength));
+        assert_eq!(OnOffMessage::parse(&ONOFF_SET, &[0x01, 0x00, 0x46]), Err(ParseError::InvalidLength));
+        assert_eq!(OnOffMessage::parse(&ONOFF_STATUS, &[0x01, 0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(OnOffMessage::parse(&LEVEL_GET, &[]), Ok(None));
+    }
+
+    #[test]
+    fn level_round_trip() {
+        let msgs = [
+            LevelMessage::Get,
+            LevelMessage::Set(LevelSet { level: -2, tid: 1, transition: None }),
+            LevelMessage::SetUnacknowledged(LevelSet {
+                level: i16::MAX,
+                tid: 2,
+                transition: Some(Transition { time: TransitionTime::IMMEDIATE, delay: Duration::from_millis(5) }),
+            }),
+            LevelMessage::Status(LevelStatus { present: i16::MIN, target: None }),
+            LevelMessage::Status(LevelStatus { present: 0, target: Some((-300, TransitionTime::UNKNOWN)) }),
+        ];
+        for msg in msgs {
+            let parameters = emit(&msg);
+            assert_eq!(LevelMessage::parse(&msg.opcode(), &parameters), Ok(Some(msg)));
+        }
+
+        let set = LevelMessage::Set(LevelSet { level: -2, tid: 1, transition: None });
+        assert_eq!(emit(&set), [0xfe, 0xff, 0x01]);
+    }
+
+    #[test]
+    fn level_invalid() {
+        assert_eq!(LevelMessage::parse(&LEVEL_SET, &[0x00, 0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(LevelMessage::parse(&LEVEL_STATUS, &[0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(LevelMessage::parse(&LEVEL_STATUS, &[0x00, 0x00, 0x01, 0x3f]), Err(ParseError::InvalidLength));
+        assert_eq!(LevelMessage::parse(&ONOFF_GET, &[]), Ok(None));
+    }
+}
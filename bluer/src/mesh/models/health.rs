@@ -0,0 +1,831 @@
+//! Health Server and Health Client models.
+//!
+//! Every node must contain a Health Server model on its primary element.
+//! It reports the faults detected by the node and lets a client draw
+//! attention to the device, for example by blinking a LED.
+
+use dbus::Path;
+use futures::{future, stream, Stream};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{watch, Notify},
+    time::sleep_until,
+};
+
+use super::StatusWaiters;
+use crate::mesh::{
+    dispatcher::{HandlerFn, Reply, Request, TypedModel},
+    node::{Node, SendOptions},
+    CompanyIdentifier, Destination, ElementConfiguration, Message, Model, ModelIdentifier, Opcode, ParseError,
+};
+
+/// Identifier of the Health Server model.
+pub const HEALTH_SERVER: ModelIdentifier = ModelIdentifier::SIG(0x0002);
+/// Identifier of the Health Client model.
+pub const HEALTH_CLIENT: ModelIdentifier = ModelIdentifier::SIG(0x0003);
+
+const CURRENT_STATUS: Opcode = Opcode::OneOctet(0x04);
+const FAULT_STATUS: Opcode = Opcode::OneOctet(0x05);
+const ATTENTION_GET: Opcode = Opcode::TwoOctet(0x80, 0x04);
+const ATTENTION_SET: Opcode = Opcode::TwoOctet(0x80, 0x05);
+const ATTENTION_SET_UNACKNOWLEDGED: Opcode = Opcode::TwoOctet(0x80, 0x06);
+const ATTENTION_STATUS: Opcode = Opcode::TwoOctet(0x80, 0x07);
+const FAULT_CLEAR: Opcode = Opcode::TwoOctet(0x80, 0x2f);
+const FAULT_CLEAR_UNACKNOWLEDGED: Opcode = Opcode::TwoOctet(0x80, 0x30);
+const FAULT_GET: Opcode = Opcode::TwoOctet(0x80, 0x31);
+const FAULT_TEST: Opcode = Opcode::TwoOctet(0x80, 0x32);
+const FAULT_TEST_UNACKNOWLEDGED: Opcode = Opcode::TwoOctet(0x80, 0x33);
+const PERIOD_GET: Opcode = Opcode::TwoOctet(0x80, 0x34);
+const PERIOD_SET: Opcode = Opcode::TwoOctet(0x80, 0x35);
+const PERIOD_SET_UNACKNOWLEDGED: Opcode = Opcode::TwoOctet(0x80, 0x36);
+const PERIOD_STATUS: Opcode = Opcode::TwoOctet(0x80, 0x37);
+
+/// Largest valid fast period divisor.
+const MAX_FAST_PERIOD_DIVISOR: u8 = 15;
+
+/// Test identifier of the standard self-test.
+pub const STANDARD_TEST: u8 = 0x00;
+
+/// Fault state of a node for a company.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaultStatus {
+    /// Identifier of the most recently performed test.
+    pub test_id: u8,
+    /// Company the fault codes are defined by.
+    pub company_id: CompanyIdentifier,
+    /// Fault codes.
+    pub faults: Vec<u8>,
+}
+
+impl FaultStatus {
+    fn parse(parameters: &[u8]) -> Result<Self, ParseError> {
+        match parameters {
+            [test_id, c0, c1, faults @ ..] => Ok(Self {
+                test_id: *test_id,
+                company_id: CompanyIdentifier(u16::from_le_bytes([*c0, *c1])),
+                faults: faults.to_vec(),
+            }),
+            _ => Err(ParseError::InvalidLength),
+        }
+    }
+
+    fn emit(&self, xmit: &mut Vec<u8>) {
+        xmit.push(self.test_id);
+        xmit.extend_from_slice(&self.company_id.0.to_le_bytes());
+        xmit.extend_from_slice(&self.faults);
+    }
+}
+
+/// Health message.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HealthMessage {
+    /// Currently present faults, usually published periodically.
+    CurrentStatus(FaultStatus),
+    /// Registered faults.
+    FaultStatus(FaultStatus),
+    /// Get the registered faults of a company.
+    FaultGet(CompanyIdentifier),
+    /// Clear the registered faults of a company and reply with the fault status.
+    FaultClear(CompanyIdentifier),
+    /// Clear the registered faults of a company without reply.
+    FaultClearUnacknowledged(CompanyIdentifier),
+    /// Perform a self-test and reply with the fault status.
+    FaultTest {
+        /// Identifier of the test.
+        test_id: u8,
+        /// Company the test is defined by.
+        company_id: CompanyIdentifier,
+    },
+    /// Perform a self-test without reply.
+    FaultTestUnacknowledged {
+        /// Identifier of the test.
+        test_id: u8,
+        /// Company the test is defined by.
+        company_id: CompanyIdentifier,
+    },
+    /// Get the fast period divisor.
+    PeriodGet,
+    /// Set the fast period divisor and reply with the period status.
+    PeriodSet(u8),
+    /// Set the fast period divisor without reply.
+    PeriodSetUnacknowledged(u8),
+    /// Fast period divisor of a server.
+    PeriodStatus(u8),
+    /// Get the attention timer.
+    AttentionGet,
+    /// Set the attention timer in seconds and reply with the attention status.
+    AttentionSet(u8),
+    /// Set the attention timer in seconds without reply.
+    AttentionSetUnacknowledged(u8),
+    /// Remaining attention time of a server in seconds.
+    AttentionStatus(u8),
+}
+
+impl HealthMessage {
+    /// Parses a health message.
+    ///
+    /// Returns [None] if the opcode does not belong to a health message.
+    pub fn parse(opcode: &Opcode, parameters: &[u8]) -> Result<Option<Self>, ParseError> {
+        let company = |parameters: &[u8]| match parameters {
+            [c0, c1] => Ok(CompanyIdentifier(u16::from_le_bytes([*c0, *c1]))),
+            _ => Err(ParseError::InvalidLength),
+        };
+        let test = |parameters: &[u8]| match parameters {
+            [test_id, company_id @ ..] => Ok((*test_id, company(company_id)?)),
+            _ => Err(ParseError::InvalidLength),
+        };
+        let divisor = |parameters: &[u8]| match parameters {
+            [divisor] if *divisor <= MAX_FAST_PERIOD_DIVISOR => Ok(*divisor),
+            [_] => Err(ParseError::InvalidValue),
+            _ => Err(ParseError::InvalidLength),
+        };
+        let attention = |parameters: &[u8]| match parameters {
+            [attention] => Ok(*attention),
+            _ => Err(ParseError::InvalidLength),
+        };
+        let empty = |msg: Self| if parameters.is_empty() { Ok(msg) } else { Err(ParseError::InvalidLength) };
+
+        let msg = match *opcode {
+            CURRENT_STATUS => Self::CurrentStatus(FaultStatus::parse(parameters)?),
+            FAULT_STATUS => Self::FaultStatus(FaultStatus::parse(parameters)?),
+            FAULT_GET => Self::FaultGet(company(parameters)?),
+            FAULT_CLEAR => Self::FaultClear(company(parameters)?),
+            FAULT_CLEAR_UNACKNOWLEDGED => Self::FaultClearUnacknowledged(company(parameters)?),
+            FAULT_TEST => {
+                let (test_id, company_id) = test(parameters)?;
+                Self::FaultTest { test_id, company_id }
+            }
+            FAULT_TEST_UNACKNOWLEDGED => {
+                let (test_id, company_id) = test(parameters)?;
+                Self::FaultTestUnacknowledged { test_id, company_id }
+            }
+            PERIOD_GET => empty(Self::PeriodGet)?,
+            PERIOD_SET => Self::PeriodSet(divisor(parameters)?),
+            PERIOD_SET_UNACKNOWLEDGED => Self::PeriodSetUnacknowledged(divisor(parameters)?),
+            PERIOD_STATUS => Self::PeriodStatus(divisor(parameters)?),
+            ATTENTION_GET => empty(Self::AttentionGet)?,
+            ATTENTION_SET => Self::AttentionSet(attention(parameters)?),
+            ATTENTION_SET_UNACKNOWLEDGED => Self::AttentionSetUnacknowledged(attention(parameters)?),
+            ATTENTION_STATUS => Self::AttentionStatus(attention(parameters)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(msg))
+    }
+}
+
+impl Message for HealthMessage {
+    fn opcode(&self) -> Opcode {
+        match self {
+            Self::CurrentStatus(_) => CURRENT_STATUS,
+            Self::FaultStatus(_) => FAULT_STATUS,
+            Self::FaultGet(_) => FAULT_GET,
+            Self::FaultClear(_) => FAULT_CLEAR,
+            Self::FaultClearUnacknowledged(_) => FAULT_CLEAR_UNACKNOWLEDGED,
+            Self::FaultTest { .. } => FAULT_TEST,
+            Self::FaultTestUnacknowledged { .. } => FAULT_TEST_UNACKNOWLEDGED,
+            Self::PeriodGet => PERIOD_GET,
+            Self::PeriodSet(_) => PERIOD_SET,
+            Self::PeriodSetUnacknowledged(_) => PERIOD_SET_UNACKNOWLEDGED,
+            Self::PeriodStatus(_) => PERIOD_STATUS,
+            Self::AttentionGet => ATTENTION_GET,
+            Self::AttentionSet(_) => ATTENTION_SET,
+            Self::AttentionSetUnacknowledged(_) => ATTENTION_SET_UNACKNOWLEDGED,
+            Self::AttentionStatus(_) => ATTENTION_STATUS,
+        }
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        match self {
+            Self::CurrentStatus(status) | Self::FaultStatus(status) => status.emit(xmit),
+            Self::FaultGet(company_id)
+            | Self::FaultClear(company_id)
+            | Self::FaultClearUnacknowledged(company_id) => xmit.extend_from_slice(&company_id.0.to_le_bytes()),
+            Self::FaultTest { test_id, company_id } | Self::FaultTestUnacknowledged { test_id, company_id } => {
+                xmit.push(*test_id);
+                xmit.extend_from_slice(&company_id.0.to_le_bytes());
+            }
+            Self::PeriodGet | Self::AttentionGet => (),
+            Self::PeriodSet(value)
+            | Self::PeriodSetUnacknowledged(value)
+            | Self::PeriodStatus(value)
+            | Self::AttentionSet(value)
+            | Self::AttentionSetUnacknowledged(value)
+            | Self::AttentionStatus(value) => xmit.push(*value),
+        }
+    }
+}
+
+/// Fault and timer state of a health server.
+#[derive(Debug)]
+struct HealthState {
+    current: Vec<u8>,
+    registered: Vec<u8>,
+    test_id: u8,
+    fast_period_divisor: u8,
+    attention_until: Option<Instant>,
+}
+
+impl HealthState {
+    fn attention_remaining(&self) -> u8 {
+        let remaining = self.attention_until.map(|until| until.saturating_duration_since(Instant::now()));
+        remaining.map(|remaining| remaining.as_secs_f32().ceil() as u8).unwrap_or_default()
+    }
+}
+
+/// Health Server model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the primary element and report faults using [add_fault](Self::add_fault)
+/// and [remove_fault](Self::remove_fault).
+///
+/// Only the [standard self-test](STANDARD_TEST) is supported, which does not
+/// change the fault state.
+/// Fault requests for other companies or tests are answered with an empty fault status.
+#[derive(Clone, Debug)]
+pub struct HealthServer {
+    company_id: CompanyIdentifier,
+    state: Arc<Mutex<HealthState>>,
+    attention: Arc<watch::Sender<Duration>>,
+    /// Notified when the publication interval may have changed.
+    interval_changed: Arc<Notify>,
+}
+
+impl HealthServer {
+    /// Creates a health server reporting faults defined by the specified company.
+    pub fn new(company_id: CompanyIdentifier) -> Self {
+        let state = HealthState {
+            current: Vec::new(),
+            registered: Vec::new(),
+            test_id: STANDARD_TEST,
+            fast_period_divisor: 0,
+            attention_until: None,
+        };
+        let (attention, _) = watch::channel(Duration::ZERO);
+        Self {
+            company_id,
+            state: Arc::new(Mutex::new(state)),
+            attention: Arc::new(attention),
+            interval_changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Reports that a fault has occurred.
+    ///
+    /// The fault is added to the current and the registered fault list.
+    pub fn add_fault(&self, fault: u8) {
+        let mut state = self.state.lock().unwrap();
+        if !state.registered.contains(&fault) {
+            state.registered.push(fault);
+        }
+        if !state.current.contains(&fault) {
+            state.current.push(fault);
+            self.interval_changed.notify_one();
+        }
+    }
+
+    /// Reports that a fault is no longer present.
+    ///
+    /// The fault is removed from the current fault list only.
+    /// It stays registered until it is cleared by a client.
+    pub fn remove_fault(&self, fault: u8) {
+        let mut state = self.state.lock().unwrap();
+        if state.current.contains(&fault) {
+            state.current.retain(|f| *f != fault);
+            self.interval_changed.notify_one();
+        }
+    }
+
+    /// Currently present faults.
+    pub fn current_faults(&self) -> Vec<u8> {
+        self.state.lock().unwrap().current.clone()
+    }
+
+    /// Registered faults.
+    pub fn registered_faults(&self) -> Vec<u8> {
+        self.state.lock().unwrap().registered.clone()
+    }
+
+    /// Current fault status, as published periodically.
+    pub fn current_status(&self) -> FaultStatus {
+        let state = self.state.lock().unwrap();
+        FaultStatus { test_id: state.test_id, company_id: self.company_id, faults: state.current.clone() }
+    }
+
+    /// Registered fault status.
+    pub fn fault_status(&self) -> FaultStatus {
+        let state = self.state.lock().unwrap();
+        FaultStatus { test_id: state.test_id, company_id: self.company_id, faults: state.registered.clone() }
+    }
+
+    /// Stream of attention timer changes.
+    ///
+    /// Each item is the time the device should attract attention for,
+    /// starting immediately.
+    /// A zero duration means that it should stop attracting attention,
+    /// either because a client requested so or because the attention timer expired.
+    pub fn attention(&self) -> impl Stream<Item = Duration> {
+        stream::unfold((self.attention.subscribe(), None), |(mut rx, until)| async move {
+            let expired = async {
+                match until {
+                    Some(until) => sleep_until(until).await,
+                    None => future::pending().await,
+                }
+            };
+            tokio::select! {
+                res = rx.changed() => {
+                    res.ok()?;
+                    let duration = *rx.borrow();
+                    let until = (!duration.is_zero()).then(|| tokio::time::Instant::now() + duration);
+                    Some((duration, (rx, until)))
+                }
+                () = expired => Some((Duration::ZERO, (rx, None))),
+            }
+        })
+    }
+
+    /// Handler answering fault, period and attention requests.
+    pub fn handler(&self) -> HandlerFn<HealthMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<HealthMessage>| {
+            let reply = this.handle(req.message);
+            Box::pin(async move { reply })
+        })
+    }
+
+    fn handle(&self, msg: HealthMessage) -> Option<Reply> {
+        let reply = match msg {
+            HealthMessage::FaultGet(company_id) if company_id == self.company_id => {
+                HealthMessage::FaultStatus(self.fault_status())
+            }
+            HealthMessage::FaultClear(company_id) | HealthMessage::FaultClearUnacknowledged(company_id)
+                if company_id == self.company_id =>
+            {
+                self.state.lock().unwrap().registered.clear();
+                match msg {
+                    HealthMessage::FaultClear(_) => HealthMessage::FaultStatus(self.fault_status()),
+                    _ => return None,
+                }
+            }
+            HealthMessage::FaultTest { test_id, company_id }
+            | HealthMessage::FaultTestUnacknowledged { test_id, company_id }
+                if company_id == self.company_id && test_id == STANDARD_TEST =>
+            {
+                self.state.lock().unwrap().test_id = test_id;
+                match msg {
+                    HealthMessage::FaultTest { .. } => HealthMessage::FaultStatus(self.fault_status()),
+                    _ => return None,
+                }
+            }
+            HealthMessage::FaultGet(company_id) | HealthMessage::FaultClear(company_id) => {
+                HealthMessage::FaultStatus(FaultStatus { test_id: STANDARD_TEST, company_id, faults: Vec::new() })
+            }
+            HealthMessage::FaultTest { test_id, company_id } => {
+                HealthMessage::FaultStatus(FaultStatus { test_id, company_id, faults: Vec::new() })
+            }
+            HealthMessage::PeriodGet => {
+                HealthMessage::PeriodStatus(self.state.lock().unwrap().fast_period_divisor)
+            }
+            HealthMessage::PeriodSet(divisor) | HealthMessage::PeriodSetUnacknowledged(divisor) => {
+                self.state.lock().unwrap().fast_period_divisor = divisor;
+                self.interval_changed.notify_one();
+                match msg {
+                    HealthMessage::PeriodSet(_) => HealthMessage::PeriodStatus(divisor),
+                    _ => return None,
+                }
+            }
+            HealthMessage::AttentionGet => {
+                HealthMessage::AttentionStatus(self.state.lock().unwrap().attention_remaining())
+            }
+            HealthMessage::AttentionSet(seconds) | HealthMessage::AttentionSetUnacknowledged(seconds) => {
+                let duration = Duration::from_secs(seconds.into());
+                self.state.lock().unwrap().attention_until = Some(Instant::now() + duration);
+                self.attention.send_replace(duration);
+                match msg {
+                    HealthMessage::AttentionSet(_) => HealthMessage::AttentionStatus(seconds),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(Box::new(reply))
+    }
+
+    /// Interval between publications of the current fault status.
+    ///
+    /// While faults are present, the publication period is divided
+    /// by two to the power of the fast period divisor.
+    fn publication_interval(&self, period: Duration) -> Duration {
+        let state = self.state.lock().unwrap();
+        if state.current.is_empty() {
+            period
+        } else {
+            period / 2u32.pow(state.fast_period_divisor.into())
+        }
+    }
+
+    /// Periodically publishes the current fault status.
+    ///
+    /// `path` is the path of the element the server belongs to and `config`
+    /// the configuration watch of that element, obtained from
+    /// [ElementControl::configuration_watch](crate::mesh::ElementControl::configuration_watch).
+    /// Publication follows the publication period set by the configuration client
+    /// and stops when the element is unregistered.
+    /// The time of the next publication is recomputed when faults are added or removed
+    /// and when the publication period or the fast period divisor changes.
+    pub async fn publish_periodically(
+        &self, node: &Node, path: Path<'_>, mut config: watch::Receiver<ElementConfiguration>,
+    ) -> crate::Result<()> {
+        let publication_period = |config: &watch::Receiver<ElementConfiguration>| {
+            config.borrow().model(&HEALTH_SERVER).and_then(|model| model.publication_period)
+        };
+        let mut period = publication_period(&config);
+        let mut last = tokio::time::Instant::now();
+        loop {
+            let deadline = period.map(|period| last + self.publication_interval(period));
+            let deadline = async move {
+                match deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => future::pending().await,
+                }
+            };
+            tokio::select! {
+                () = deadline => {
+                    let status = HealthMessage::CurrentStatus(self.current_status());
+                    node.publish(path.clone(), HEALTH_SERVER, SendOptions::default(), &status).await?;
+                    last = tokio::time::Instant::now();
+                }
+                () = self.interval_changed.notified() => (),
+                res = config.changed() => {
+                    if res.is_err() {
+                        return Ok(());
+                    }
+                    let new_period = publication_period(&config);
+                    if period.is_none() {
+                        last = tokio::time::Instant::now();
+                    }
+                    period = new_period;
+                }
+            }
+        }
+    }
+}
+
+impl Model for HealthServer {
+    fn identifier(&self) -> ModelIdentifier {
+        HEALTH_SERVER
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(HealthMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for HealthServer {
+    type Message = HealthMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<HealthMessage>, ParseError> {
+        HealthMessage::parse(opcode, parameters)
+    }
+}
+
+/// Health Client model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to, so that status replies are received.
+#[derive(Clone, Debug, Default)]
+pub struct HealthClient {
+    faults: Arc<StatusWaiters<FaultStatus>>,
+    periods: Arc<StatusWaiters<u8>>,
+    attentions: Arc<StatusWaiters<u8>>,
+}
+
+impl HealthClient {
+    /// Creates a client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handler receiving status replies.
+    pub fn handler(&self) -> HandlerFn<HealthMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<HealthMessage>| {
+            let unsolicited = match req.message {
+                HealthMessage::FaultStatus(status) => {
+                    this.faults.deliver(req.src, req.dest, status).map(HealthMessage::FaultStatus)
+                }
+                HealthMessage::PeriodStatus(divisor) => {
+                    this.periods.deliver(req.src, req.dest, divisor).map(HealthMessage::PeriodStatus)
+                }
+                HealthMessage::AttentionStatus(seconds) => {
+                    this.attentions.deliver(req.src, req.dest, seconds).map(HealthMessage::AttentionStatus)
+                }
+                msg => Some(msg),
+            };
+            if let Some(msg) = unsolicited {
+                log::trace!("Unsolicited health message {:?} from {:04x}", msg, req.src);
+            }
+            Box::pin(async { None })
+        })
+    }
+
+    /// Gets the registered faults of the server at `dest` for a company.
+    ///
+    /// `path` is the path of the element the client belongs to and
+    /// `app_key` is the index of the application key used to encrypt the request.
+    pub async fn fault_get(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, company_id: CompanyIdentifier,
+    ) -> crate::Result<FaultStatus> {
+        self.faults
+            .request(node, path, dest, app_key, &HealthMessage::FaultGet(company_id), move |status| {
+                status.company_id == company_id
+            })
+            .await
+    }
+
+    /// Clears the registered faults of the server at `dest` for a company
+    /// and returns its new fault status.
+    pub async fn fault_clear(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, company_id: CompanyIdentifier,
+    ) -> crate::Result<FaultStatus> {
+        self.faults
+            .request(node, path, dest, app_key, &HealthMessage::FaultClear(company_id), move |status| {
+                status.company_id == company_id
+            })
+            .await
+    }
+
+    /// Runs a self-test on the server at `dest` and returns its fault status.
+    pub async fn fault_test(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, test_id: u8, company_id: CompanyIdentifier,
+    ) -> crate::Result<FaultStatus> {
+        self.faults
+            .request(
+                node,
+                path,
+                dest,
+                app_key,
+                &HealthMessage::FaultTest { test_id, company_id },
+                move |status| status.company_id == company_id,
+            )
+            .await
+    }
+
+    /// Gets the fast period divisor of the server at `dest`.
+    pub async fn period_get(&self, node: &Node, path: Path<'_>, dest: u16, app_key: u16) -> crate::Result<u8> {
+        self.periods.request(node, path, dest, app_key, &HealthMessage::PeriodGet, |_| true).await
+    }
+
+    /// Sets the fast period divisor of the server at `dest` and returns its new value.
+    pub async fn period_set(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, divisor: u8,
+    ) -> crate::Result<u8> {
+        self.periods.request(node, path, dest, app_key, &HealthMessage::PeriodSet(divisor), |_| true).await
+    }
+
+    /// Gets the remaining attention time of the server at `dest` in seconds.
+    pub async fn attention_get(&self, node: &Node, path: Path<'_>, dest: u16, app_key: u16) -> crate::Result<u8> {
+        self.attentions.request(node, path, dest, app_key, &HealthMessage::AttentionGet, |_| true).await
+    }
+
+    /// Sets the attention timer of the server at `dest` in seconds
+    /// and returns its new value.
+    pub async fn attention_set(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, seconds: u8,
+    ) -> crate::Result<u8> {
+        self.attentions.request(node, path, dest, app_key, &HealthMessage::AttentionSet(seconds), |_| true).await
+    }
+
+    /// Sets the attention timer of the servers at `dest` in seconds without waiting for a reply.
+    pub async fn attention_set_unacknowledged(
+        &self, node: &Node, path: Path<'_>, dest: Destination, app_key: u16, seconds: u8,
+    ) -> crate::Result<()> {
+        let msg = HealthMessage::AttentionSetUnacknowledged(seconds);
+        node.send(path, dest, app_key, SendOptions::default(), &msg).await
+    }
+}
+
+impl Model for HealthClient {
+    fn identifier(&self) -> ModelIdentifier {
+        HEALTH_CLIENT
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(HealthMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for HealthClient {
+    type Message = HealthMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<HealthMessage>, ParseError> {
+        HealthMessage::parse(opcode, parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPANY: CompanyIdentifier = CompanyIdentifier(0x05f1);
+
+    fn emit(msg: &dyn Message) -> Vec<u8> {
+        let mut xmit = Vec::new();
+        msg.emit_parameters(&mut xmit);
+        xmit
+    }
+
+    fn reply(server: &HealthServer, msg: HealthMessage) -> Option<HealthMessage> {
+        server.handle(msg).map(|reply| HealthMessage::parse(&reply.opcode(), &emit(&*reply)).unwrap().unwrap())
+    }
+
+    #[test]
+    fn round_trip() {
+        let status = FaultStatus { test_id: STANDARD_TEST, company_id: COMPANY, faults: vec![0x01, 0xa2] };
+        let msgs = [
+            HealthMessage::CurrentStatus(status.clone()),
+            HealthMessage::FaultStatus(FaultStatus { faults: Vec::new(), ..status }),
+            HealthMessage::FaultGet(COMPANY),
+            HealthMessage::FaultClear(COMPANY),
+            HealthMessage::FaultClearUnacknowledged(COMPANY),
+            HealthMessage::FaultTest { test_id: 0x01, company_id: COMPANY },
+            HealthMessage::FaultTestUnacknowledged { test_id: 0x02, company_id: COMPANY },
+            HealthMessage::PeriodGet,
+            HealthMessage::PeriodSet(MAX_FAST_PERIOD_DIVISOR),
+            HealthMessage::PeriodSetUnacknowledged(1),
+            HealthMessage::PeriodStatus(0),
+            HealthMessage::AttentionGet,
+            HealthMessage::AttentionSet(10),
+            HealthMessage::AttentionSetUnacknowledged(0),
+            HealthMessage::AttentionStatus(255),
+        ];
+        for msg in msgs {
+            assert_eq!(HealthMessage::parse(&msg.opcode(), &emit(&msg)), Ok(Some(msg)));
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let status = FaultStatus { test_id: 0x00, company_id: COMPANY, faults: vec![0x01, 0xa2] };
+        assert_eq!(emit(&HealthMessage::CurrentStatus(status)), [0x00, 0xf1, 0x05, 0x01, 0xa2]);
+        assert_eq!(emit(&HealthMessage::FaultTest { test_id: 0x01, company_id: COMPANY }), [0x01, 0xf1, 0x05]);
+        assert_eq!(HealthMessage::FaultGet(COMPANY).opcode(), Opcode::TwoOctet(0x80, 0x31));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(HealthMessage::parse(&FAULT_STATUS, &[0x00, 0xf1]), Err(ParseError::InvalidLength));
+        assert_eq!(HealthMessage::parse(&FAULT_GET, &[0xf1]), Err(ParseError::InvalidLength));
+        assert_eq!(HealthMessage::parse(&FAULT_TEST, &[0x00, 0xf1, 0x05, 0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(
+            HealthMessage::parse(&PERIOD_SET, &[MAX_FAST_PERIOD_DIVISOR + 1]),
+            Err(ParseError::InvalidValue)
+        );
+        assert_eq!(HealthMessage::parse(&PERIOD_GET, &[0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(HealthMessage::parse(&ATTENTION_SET, &[]), Err(ParseError::InvalidLength));
+        assert_eq!(HealthMessage::parse(&Opcode::TwoOctet(0x82, 0x01), &[]), Ok(None));
+    }
+
+    #[test]
+    fn publication_interval() {
+        let server = HealthServer::new(COMPANY);
+        let period = Duration::from_secs(16);
+        server.state.lock().unwrap().fast_period_divisor = 2;
+        assert_eq!(server.publication_interval(period), period);
+
+        server.add_fault(0x01);
+        assert_eq!(server.publication_interval(period), Duration::from_secs(4));
+        server.state.lock().unwrap().fast_period_divisor = 0;
+        assert_eq!(server.publication_interval(period), period);
+        server.state.lock().unwrap().fast_period_divisor = MAX_FAST_PERIOD_DIVISOR;
+        assert_eq!(server.publication_interval(period), Duration::from_nanos(488_281));
+
+        server.remove_fault(0x01);
+        assert_eq!(server.publication_interval(period), period);
+    }
+
+    #[test]
+    fn server_faults() {
+        let server = HealthServer::new(COMPANY);
+        server.add_fault(0x01);
+        server.remove_fault(0x01);
+
+        let status = FaultStatus { test_id: STANDARD_TEST, company_id: COMPANY, faults: vec![0x01] };
+        assert_eq!(
+            reply(&server, HealthMessage::FaultGet(COMPANY)),
+            Some(HealthMessage::FaultStatus(status.clone()))
+        );
+        assert_eq!(
+            reply(&server, HealthMessage::FaultTest { test_id: STANDARD_TEST, company_id: COMPANY }),
+            Some(HealthMessage::FaultStatus(status.clone()))
+        );
+        assert_eq!(
+            reply(&server, HealthMessage::FaultClear(COMPANY)),
+            Some(HealthMessage::FaultStatus(FaultStatus { faults: Vec::new(), ..status }))
+        );
+        assert!(server.registered_faults().is_empty());
+    }
+
+    #[test]
+    fn server_unknown_company_and_test() {
+        let server = HealthServer::new(COMPANY);
+        server.add_fault(0x01);
+        let other = CompanyIdentifier(0x0059);
+        let empty = |test_id, company_id| {
+            Some(HealthMessage::FaultStatus(FaultStatus { test_id, company_id, faults: Vec::new() }))
+        };
+
+        assert_eq!(reply(&server, HealthMessage::FaultGet(other)), empty(STANDARD_TEST, other));
+        assert_eq!(reply(&server, HealthMessage::FaultClear(other)), empty(STANDARD_TEST, other));
+        assert_eq!(
+            reply(&server, HealthMessage::FaultTest { test_id: 0x00, company_id: other }),
+            empty(0x00, other)
+        );
+        assert_eq!(
+            reply(&server, HealthMessage::FaultTest { test_id: 0x01, company_id: COMPANY }),
+            empty(0x01, COMPANY)
+        );
+        assert_eq!(reply(&server, HealthMessage::FaultClearUnacknowledged(other)), None);
+        assert_eq!(
+            reply(&server, HealthMessage::FaultTestUnacknowledged { test_id: 0x01, company_id: COMPANY }),
+            None
+        );
+        assert_eq!(server.registered_faults(), [0x01]);
+    }
+
+    #[test]
+    fn server_period_and_attention() {
+        let server = HealthServer::new(COMPANY);
+        assert_eq!(reply(&server, HealthMessage::PeriodSet(3)), Some(HealthMessage::PeriodStatus(3)));
+        assert_eq!(reply(&server, HealthMessage::PeriodGet), Some(HealthMessage::PeriodStatus(3)));
+        assert_eq!(reply(&server, HealthMessage::AttentionSet(10)), Some(HealthMessage::AttentionStatus(10)));
+        assert_eq!(reply(&server, HealthMessage::AttentionGet), Some(HealthMessage::AttentionStatus(10)));
+        assert_eq!(reply(&server, HealthMessage::AttentionSetUnacknowledged(0)), None);
+        assert_eq!(reply(&server, HealthMessage::AttentionGet), Some(HealthMessage::AttentionStatus(0)));
+        assert_eq!(reply(&server, HealthMessage::AttentionStatus(5)), None);
+    }
+
+    #[test]
+    fn server_notifies_interval_changes() {
+        use futures::FutureExt;
+
+        let server = HealthServer::new(COMPANY);
+        assert!(server.interval_changed.notified().now_or_never().is_none());
+        server.add_fault(0x01);
+        assert!(server.interval_changed.notified().now_or_never().is_some());
+        server.add_fault(0x01);
+        assert!(server.interval_changed.notified().now_or_never().is_none());
+        server.remove_fault(0x01);
+        assert!(server.interval_changed.notified().now_or_never().is_some());
+        server.remove_fault(0x01);
+        assert!(server.interval_changed.notified().now_or_never().is_none());
+        reply(&server, HealthMessage::PeriodSetUnacknowledged(1));
+        assert!(server.interval_changed.notified().now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn server_attention_expires() {
+        use futures::StreamExt;
+
+        let server = HealthServer::new(COMPANY);
+        let attention = server.attention();
+        futures::pin_mut!(attention);
+
+        reply(&server, HealthMessage::AttentionSetUnacknowledged(1));
+        assert_eq!(attention.next().await, Some(Duration::from_secs(1)));
+        let expired = tokio::time::timeout(Duration::from_secs(3), attention.next()).await;
+        assert_eq!(expired, Ok(Some(Duration::ZERO)));
+        assert_eq!(reply(&server, HealthMessage::AttentionGet), Some(HealthMessage::AttentionStatus(0)));
+
+        reply(&server, HealthMessage::AttentionSetUnacknowledged(10));
+        assert_eq!(attention.next().await, Some(Duration::from_secs(10)));
+        reply(&server, HealthMessage::AttentionSetUnacknowledged(0));
+        assert_eq!(attention.next().await, Some(Duration::ZERO));
+    }
+}
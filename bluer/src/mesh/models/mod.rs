@@ -0,0 +1,188 @@
+//! Built-in Bluetooth mesh models.
+//!
+//! The server models keep their state and answer requests on their own once
+//! their [handler](generic::GenericOnOffServer::handler) is added to a
+//! [Dispatcher](super::dispatcher::Dispatcher).
+//! The client models send requests and await the matching status reply,
+//! which is also delivered through a [Dispatcher](super::dispatcher::Dispatcher).
+
+use dbus::Path;
+use futures::channel::oneshot;
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::timeout;
+
+use crate::{
+    mesh::{
+        node::{Node, SendOptions},
+        Destination, Message,
+    },
+    Error, ErrorKind, Result,
+};
+
+pub mod config;
+pub mod generic;
+pub mod health;
+pub mod sensor;
+
+/// Time to wait for the status reply to a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time during which a message with the same transaction identifier
+/// from the same source is treated as a retransmission.
+const TID_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// A request awaiting a status reply.
+struct Waiter<T> {
+    /// Address of the node the reply is expected from.
+    src: u16,
+    /// Selects the status that answers the request.
+    filter: Box<dyn Fn(&T) -> bool + Send>,
+    tx: oneshot::Sender<T>,
+}
+
+/// Requests awaiting a status reply from a specific node.
+pub(crate) struct StatusWaiters<T> {
+    waiters: Mutex<Vec<Waiter<T>>>,
+}
+
+impl<T> fmt::Debug for StatusWaiters<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StatusWaiters {{ {} waiting }}", self.waiters.lock().unwrap().len())
+    }
+}
+
+impl<T> Default for StatusWaiters<T> {
+    fn default() -> Self {
+        Self { waiters: Mutex::new(Vec::new()) }
+    }
+}
+
+impl<T> StatusWaiters<T> {
+    /// Registers interest in the next status sent by `src` for which `filter` returns true.
+    fn expect(&self, src: u16, filter: impl Fn(&T) -> bool + Send + 'static) -> oneshot::Receiver<T> {
+        let (tx, rx) = oneshot::channel();
+        let mut waiters = self.waiters.lock().unwrap();
+        waiters.retain(|waiter| !waiter.tx.is_canceled());
+        waiters.push(Waiter { src, filter: Box::new(filter), tx });
+        rx
+    }
+
+    /// Passes a status received from `src` to the oldest request waiting for it.
+    ///
+    /// Statuses that were not sent to a unicast address of the local node,
+    /// for example published ones, are never passed to a request.
+    /// Returns the status if no request is waiting for it.
+    pub(crate) fn deliver(&self, src: u16, dest: Option<Destination>, status: T) -> Option<T> {
+        if !matches!(dest, None | Some(Destination::Unicast(_))) {
+            return Some(status);
+        }
+
+        let mut waiters = self.waiters.lock().unwrap();
+        waiters.retain(|waiter| !waiter.tx.is_canceled());
+        match waiters.iter().position(|waiter| waiter.src == src && (waiter.filter)(&status)) {
+            Some(idx) => waiters.remove(idx).tx.send(status).err(),
+            None => Some(status),
+        }
+    }
+
+    /// Sends a request to the element at `dest` and waits for its status reply
+    /// for which `filter` returns true.
+    pub(crate) async fn request(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, message: &dyn Message,
+        filter: impl Fn(&T) -> bool + Send + 'static,
+    ) -> Result<T> {
+        let rx = self.expect(dest, filter);
+        node.send(path, Destination::Unicast(dest), app_key, SendOptions::default(), message).await?;
+
+        match timeout(RESPONSE_TIMEOUT, rx).await {
+            Ok(Ok(status)) => Ok(status),
+            _ => Err(Error::new(ErrorKind::NoResponse)),
+        }
+    }
+}
+
+/// Detects retransmissions of messages carrying a transaction identifier.
+#[derive(Debug, Default)]
+pub(crate) struct TidCache {
+    last: Mutex<Option<(u16, u8, Instant)>>,
+}
+
+impl TidCache {
+    /// Returns whether the message with transaction identifier `tid` from `src`
+    /// starts a new transaction and records it.
+    pub(crate) fn is_new(&self, src: u16, tid: u8) -> bool {
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap();
+        let new = match *last {
+            Some((last_src, last_tid, time)) => {
+                last_src != src || last_tid != tid || now.duration_since(time) >= TID_TIMEOUT
+            }
+            None => true,
+        };
+        *last = Some((src, tid, now));
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_waiters_match_source_and_filter() {
+        let waiters = StatusWaiters::<u8>::default();
+        let mut even = waiters.expect(0x0100, |status| status % 2 == 0);
+        let mut odd = waiters.expect(0x0100, |status| status % 2 == 1);
+
+        assert_eq!(waiters.deliver(0x0200, Some(Destination::Unicast(0x0001)), 2), Some(2));
+        assert_eq!(waiters.deliver(0x0100, Some(Destination::Unicast(0x0001)), 3), None);
+        assert_eq!(odd.try_recv().unwrap(), Some(3));
+        assert_eq!(even.try_recv().unwrap(), None);
+        assert_eq!(waiters.deliver(0x0100, None, 4), None);
+        assert_eq!(even.try_recv().unwrap(), Some(4));
+    }
+
+    #[test]
+    fn status_waiters_ignore_published_statuses() {
+        let waiters = StatusWaiters::<u8>::default();
+        let mut rx = waiters.expect(0x0100, |_| true);
+
+        assert_eq!(waiters.deliver(0x0100, Some(Destination::Group(0xc000)), 1), Some(1));
+        assert_eq!(rx.try_recv().unwrap(), None);
+        assert_eq!(waiters.deliver(0x0100, Some(Destination::Unicast(0x0001)), 2), None);
+        assert_eq!(rx.try_recv().unwrap(), Some(2));
+    }
+
+    #[test]
+    fn status_waiters_skip_cancelled_requests() {
+        let waiters = StatusWaiters::<u8>::default();
+        drop(waiters.expect(0x0100, |_| true));
+
+        assert_eq!(waiters.deliver(0x0100, None, 1), Some(1));
+        assert!(waiters.waiters.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tid_cache_detects_retransmissions() {
+        let tids = TidCache::default();
+        assert!(tids.is_new(0x0100, 1));
+        assert!(!tids.is_new(0x0100, 1));
+        assert!(tids.is_new(0x0100, 2));
+        assert!(tids.is_new(0x0200, 2));
+        assert!(tids.is_new(0x0100, 2));
+    }
+
+    #[test]
+    fn tid_cache_expires() {
+        let tids = TidCache::default();
+        assert!(tids.is_new(0x0100, 1));
+        let (src, tid, time) = tids.last.lock().unwrap().unwrap();
+        *tids.last.lock().unwrap() = Some((src, tid, time - TID_TIMEOUT));
+        assert!(tids.is_new(0x0100, 1));
+        assert!(!tids.is_new(0x0100, 1));
+    }
+}
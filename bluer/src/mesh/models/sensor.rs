@@ -0,0 +1,1284 @@
+//! Sensor models and the registry of mesh device properties.
+//!
+//! Sensor values are transmitted in the characteristic format of their
+//! [property](PropertyId).
+//! The [registry](Property::lookup) maps the properties to their [Format],
+//! which converts between the raw values and physical values in a [Unit].
+
+use dbus::Path;
+use futures::{stream, Stream};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+use super::StatusWaiters;
+use crate::mesh::{
+    dispatcher::{HandlerFn, Reply, Request, TypedModel},
+    node::Node,
+    Message, Model, ModelIdentifier, Opcode, ParseError,
+};
+
+/// Identifier of the Sensor Server model.
+pub const SENSOR_SERVER: ModelIdentifier = ModelIdentifier::SIG(0x1100);
+/// Identifier of the Sensor Setup Server model.
+pub const SENSOR_SETUP_SERVER: ModelIdentifier = ModelIdentifier::SIG(0x1101);
+/// Identifier of the Sensor Client model.
+pub const SENSOR_CLIENT: ModelIdentifier = ModelIdentifier::SIG(0x1102);
+
+const DESCRIPTOR_GET: Opcode = Opcode::TwoOctet(0x82, 0x30);
+const DESCRIPTOR_STATUS: Opcode = Opcode::OneOctet(0x51);
+const GET: Opcode = Opcode::TwoOctet(0x82, 0x31);
+const STATUS: Opcode = Opcode::OneOctet(0x52);
+const SERIES_GET: Opcode = Opcode::TwoOctet(0x82, 0x33);
+const SERIES_STATUS: Opcode = Opcode::OneOctet(0x54);
+const CADENCE_GET: Opcode = Opcode::TwoOctet(0x82, 0x34);
+const CADENCE_SET: Opcode = Opcode::OneOctet(0x55);
+const CADENCE_SET_UNACKNOWLEDGED: Opcode = Opcode::OneOctet(0x56);
+const CADENCE_STATUS: Opcode = Opcode::OneOctet(0x57);
+
+/// Length of a sensor descriptor.
+const DESCRIPTOR_LEN: usize = 8;
+
+/// Length field of a marshalled sensor value denoting an empty value.
+const EMPTY_LEN: u8 = 0x7f;
+
+/// Maximum length of a marshalled sensor value.
+const MAX_VALUE_LEN: usize = 0x7f;
+
+/// Resolution of a percentage status trigger delta.
+const TRIGGER_PERCENT_RESOLUTION: f64 = 0.01;
+
+/// Identifier of a mesh device property.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PropertyId(pub u16);
+
+impl PropertyId {
+    /// Motion Sensed.
+    pub const MOTION_SENSED: Self = Self(0x0042);
+    /// People Count.
+    pub const PEOPLE_COUNT: Self = Self(0x004c);
+    /// Presence Detected.
+    pub const PRESENCE_DETECTED: Self = Self(0x004d);
+    /// Present Ambient Light Level.
+    pub const PRESENT_AMBIENT_LIGHT_LEVEL: Self = Self(0x004e);
+    /// Present Ambient Temperature.
+    pub const PRESENT_AMBIENT_TEMPERATURE: Self = Self(0x004f);
+    /// Present Device Operating Temperature.
+    pub const PRESENT_DEVICE_OPERATING_TEMPERATURE: Self = Self(0x0054);
+    /// Present Indoor Ambient Temperature.
+    pub const PRESENT_INDOOR_AMBIENT_TEMPERATURE: Self = Self(0x0056);
+    /// Present Input Current.
+    pub const PRESENT_INPUT_CURRENT: Self = Self(0x0057);
+    /// Present Input Voltage.
+    pub const PRESENT_INPUT_VOLTAGE: Self = Self(0x0059);
+    /// Present Outdoor Ambient Temperature.
+    pub const PRESENT_OUTDOOR_AMBIENT_TEMPERATURE: Self = Self(0x005b);
+    /// Present Ambient Relative Humidity.
+    pub const PRESENT_AMBIENT_RELATIVE_HUMIDITY: Self = Self(0x0076);
+
+    /// Registered property with this identifier.
+    pub fn property(&self) -> Option<&'static Property> {
+        Property::lookup(*self)
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        match data {
+            [p0, p1] => Ok(Self(u16::from_le_bytes([*p0, *p1]))),
+            _ => Err(ParseError::InvalidLength),
+        }
+    }
+}
+
+impl fmt::Display for PropertyId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.property() {
+            Some(property) => write!(f, "{}", property.name),
+            None => write!(f, "{:04x}", self.0),
+        }
+    }
+}
+
+/// Unit of a physical value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Unit {
+    /// Unitless number or boolean value.
+    Unitless,
+    /// Electric current in ampere.
+    Ampere,
+    /// Temperature in degrees Celsius.
+    Celsius,
+    /// Illuminance in lux.
+    Lux,
+    /// Percentage.
+    Percent,
+    /// Electric potential in volt.
+    Volt,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Self::Unitless => "",
+            Self::Ampere => "A",
+            Self::Celsius => "°C",
+            Self::Lux => "lx",
+            Self::Percent => "%",
+            Self::Volt => "V",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Characteristic format of a property value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Format {
+    /// Boolean, encoded as 0 or 1.
+    Boolean,
+    /// Count with a range of 0 to 65534.
+    Count16,
+    /// Electric current with a resolution of 0.01 A.
+    ElectricCurrent,
+    /// Relative humidity with a resolution of 0.01 %.
+    Humidity,
+    /// Illuminance with a resolution of 0.01 lx.
+    Illuminance,
+    /// Percentage with a resolution of 0.5 %.
+    Percentage8,
+    /// Temperature with a resolution of 0.01 °C.
+    Temperature,
+    /// Temperature with a resolution of 0.5 °C and a range of -64 to 63 °C.
+    Temperature8,
+    /// Voltage with a resolution of 1/64 V.
+    Voltage,
+}
+
+/// Encoding of a characteristic format as a scaled integer.
+struct Encoding {
+    size: usize,
+    signed: bool,
+    resolution: f64,
+    min: i64,
+    max: i64,
+    /// Raw value denoting an unknown value.
+    unknown: Option<i64>,
+}
+
+impl Format {
+    fn encoding(&self) -> Encoding {
+        let (size, signed, resolution, min, max, unknown) = match self {
+            Self::Boolean => (1, false, 1.0, 0, 1, None),
+            Self::Count16 => (2, false, 1.0, 0, 0xfffe, Some(0xffff)),
+            Self::ElectricCurrent => (2, false, 0.01, 0, 0xfffe, Some(0xffff)),
+            Self::Humidity => (2, false, 0.01, 0, 10_000, Some(0xffff)),
+            Self::Illuminance => (3, false, 0.01, 0, 0xff_fffe, Some(0xff_ffff)),
+            Self::Percentage8 => (1, false, 0.5, 0, 200, Some(0xff)),
+            Self::Temperature => (2, true, 0.01, -27_315, 32_767, Some(-32_768)),
+            Self::Temperature8 => (1, true, 0.5, -128, 126, Some(0x7f)),
+            Self::Voltage => (2, false, 1.0 / 64.0, 0, 65_408, Some(0xffff)),
+        };
+        Encoding { size, signed, resolution, min, max, unknown }
+    }
+
+    /// Size of an encoded value in bytes.
+    pub fn size(&self) -> usize {
+        self.encoding().size
+    }
+
+    /// Unit of the physical value.
+    pub fn unit(&self) -> Unit {
+        match self {
+            Self::Boolean | Self::Count16 => Unit::Unitless,
+            Self::ElectricCurrent => Unit::Ampere,
+            Self::Humidity | Self::Percentage8 => Unit::Percent,
+            Self::Illuminance => Unit::Lux,
+            Self::Temperature | Self::Temperature8 => Unit::Celsius,
+            Self::Voltage => Unit::Volt,
+        }
+    }
+
+    /// Decodes a raw value.
+    ///
+    /// Returns [None] if the value is unknown.
+    pub fn decode(&self, raw: &[u8]) -> Result<Option<f64>, ParseError> {
+        let encoding = self.encoding();
+        if raw.len() != encoding.size {
+            return Err(ParseError::InvalidLength);
+        }
+
+        let mut buf = [0; 8];
+        buf[..encoding.size].copy_from_slice(raw);
+        let mut value = i64::from_le_bytes(buf);
+        if encoding.signed {
+            let shift = 64 - 8 * encoding.size;
+            value = value << shift >> shift;
+        }
+
+        if Some(value) == encoding.unknown {
+            Ok(None)
+        } else if value < encoding.min || value > encoding.max {
+            Err(ParseError::InvalidValue)
+        } else {
+            Ok(Some(value as f64 * encoding.resolution))
+        }
+    }
+
+    /// Encodes a value, appending it to `xmit`.
+    ///
+    /// The value is rounded to the resolution of the format and saturated to its range.
+    /// [None] encodes an unknown value or, if the format cannot represent it,
+    /// the smallest value.
+    pub fn encode(&self, value: Option<f64>, xmit: &mut Vec<u8>) {
+        let encoding = self.encoding();
+        let raw = match value {
+            Some(value) if !value.is_nan() => {
+                ((value / encoding.resolution).round() as i64).clamp(encoding.min, encoding.max)
+            }
+            _ => encoding.unknown.unwrap_or(encoding.min),
+        };
+        xmit.extend_from_slice(&raw.to_le_bytes()[..encoding.size]);
+    }
+
+    /// Decodes a raw value that must be known.
+    fn decode_known(&self, raw: &[u8]) -> Result<f64, ParseError> {
+        self.decode(raw)?.ok_or(ParseError::InvalidValue)
+    }
+}
+
+/// Registered mesh device property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Property {
+    /// Property identifier.
+    pub id: PropertyId,
+    /// Name of the property.
+    pub name: &'static str,
+    /// Characteristic format of its value.
+    pub format: Format,
+}
+
+impl Property {
+    /// Properties known to the registry.
+    pub const ALL: &'static [Property] = &[
+        Property { id: PropertyId::MOTION_SENSED, name: "Motion Sensed", format: Format::Percentage8 },
+        Property { id: PropertyId::PEOPLE_COUNT, name: "People Count", format: Format::Count16 },
+        Property { id: PropertyId::PRESENCE_DETECTED, name: "Presence Detected", format: Format::Boolean },
+        Property {
+            id: PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL,
+            name: "Present Ambient Light Level",
+            format: Format::Illuminance,
+        },
+        Property {
+            id: PropertyId::PRESENT_AMBIENT_TEMPERATURE,
+            name: "Present Ambient Temperature",
+            format: Format::Temperature8,
+        },
+        Property {
+            id: PropertyId::PRESENT_DEVICE_OPERATING_TEMPERATURE,
+            name: "Present Device Operating Temperature",
+            format: Format::Temperature,
+        },
+        Property {
+            id: PropertyId::PRESENT_INDOOR_AMBIENT_TEMPERATURE,
+            name: "Present Indoor Ambient Temperature",
+            format: Format::Temperature8,
+        },
+        Property {
+            id: PropertyId::PRESENT_INPUT_CURRENT,
+            name: "Present Input Current",
+            format: Format::ElectricCurrent,
+        },
+        Property {
+            id: PropertyId::PRESENT_INPUT_VOLTAGE,
+            name: "Present Input Voltage",
+            format: Format::Voltage,
+        },
+        Property {
+            id: PropertyId::PRESENT_OUTDOOR_AMBIENT_TEMPERATURE,
+            name: "Present Outdoor Ambient Temperature",
+            format: Format::Temperature8,
+        },
+        Property {
+            id: PropertyId::PRESENT_AMBIENT_RELATIVE_HUMIDITY,
+            name: "Present Ambient Relative Humidity",
+            format: Format::Humidity,
+        },
+    ];
+
+    /// Looks up a property in the registry.
+    pub fn lookup(id: PropertyId) -> Option<&'static Property> {
+        Self::ALL.iter().find(|property| property.id == id)
+    }
+
+    /// Format of a registered property.
+    fn format_of(id: PropertyId) -> Result<Format, ParseError> {
+        Self::lookup(id).map(|property| property.format).ok_or(ParseError::InvalidValue)
+    }
+}
+
+/// Value of a sensor property.
+///
+/// The value is kept in its raw encoding, so that values of properties
+/// not known to the registry can be received.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorValue {
+    /// Property of the value.
+    pub property: PropertyId,
+    /// Raw value in the characteristic format of the property.
+    ///
+    /// This is empty if the server does not support the property.
+    /// Values longer than 127 bytes are truncated when sent.
+    pub raw: Vec<u8>,
+}
+
+impl SensorValue {
+    /// Encodes the value of a registered property.
+    ///
+    /// [None] denotes an unknown value.
+    pub fn new(property: PropertyId, value: Option<f64>) -> Result<Self, ParseError> {
+        let mut raw = Vec::new();
+        Property::format_of(property)?.encode(value, &mut raw);
+        Ok(Self { property, raw })
+    }
+
+    /// Decodes the value using the registry.
+    ///
+    /// Returns [None] if the value is unknown or the server does not support the property.
+    pub fn value(&self) -> Result<Option<f64>, ParseError> {
+        if self.raw.is_empty() {
+            return Ok(None);
+        }
+        Property::format_of(self.property)?.decode(&self.raw)
+    }
+
+    /// Unit of the value, if its property is registered.
+    pub fn unit(&self) -> Option<Unit> {
+        self.property.property().map(|property| property.format.unit())
+    }
+
+    fn parse_all(mut data: &[u8]) -> Result<Vec<Self>, ParseError> {
+        let mut values = Vec::new();
+        while !data.is_empty() {
+            let (len, property, rest) = if data[0] & 0x01 == 0 {
+                if data.len() < 2 {
+                    return Err(ParseError::InvalidLength);
+                }
+                let header = u16::from_le_bytes([data[0], data[1]]);
+                (usize::from(header >> 1 & 0x0f) + 1, PropertyId(header >> 5), &data[2..])
+            } else {
+                if data.len() < 3 {
+                    return Err(ParseError::InvalidLength);
+                }
+                let len = match data[0] >> 1 {
+                    EMPTY_LEN => 0,
+                    len => usize::from(len) + 1,
+                };
+                (len, PropertyId::parse(&data[1..3])?, &data[3..])
+            };
+            if rest.len() < len {
+                return Err(ParseError::InvalidLength);
+            }
+            values.push(Self { property, raw: rest[..len].to_vec() });
+            data = &rest[len..];
+        }
+        Ok(values)
+    }
+
+    fn emit(&self, xmit: &mut Vec<u8>) {
+        let raw = &self.raw[..self.raw.len().min(MAX_VALUE_LEN)];
+        if raw.len() < self.raw.len() {
+            log::warn!("Truncating value of sensor property {} to {} bytes", self.property, MAX_VALUE_LEN);
+        }
+
+        let len = raw.len();
+        if (1..=16).contains(&len) && self.property.0 < 0x0800 {
+            let header = self.property.0 << 5 | ((len - 1) as u16) << 1;
+            xmit.extend_from_slice(&header.to_le_bytes());
+        } else {
+            let len = if len == 0 { EMPTY_LEN } else { (len - 1) as u8 };
+            xmit.push(len << 1 | 0x01);
+            xmit.extend_from_slice(&self.property.0.to_le_bytes());
+        }
+        xmit.extend_from_slice(raw);
+    }
+}
+
+/// Descriptor of a sensor property provided by a server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorDescriptor {
+    /// Property of the sensor.
+    pub property: PropertyId,
+    /// Positive tolerance as a 12-bit value, 0 if unspecified.
+    pub positive_tolerance: u16,
+    /// Negative tolerance as a 12-bit value, 0 if unspecified.
+    pub negative_tolerance: u16,
+    /// Sampling function, 0 if unspecified.
+    pub sampling_function: u8,
+    /// Encoded measurement period, 0 if not applicable.
+    pub measurement_period: u8,
+    /// Encoded update interval, 0 if not applicable.
+    pub update_interval: u8,
+}
+
+impl SensorDescriptor {
+    /// Descriptor of a property with unspecified tolerance and sampling.
+    pub const fn new(property: PropertyId) -> Self {
+        Self {
+            property,
+            positive_tolerance: 0,
+            negative_tolerance: 0,
+            sampling_function: 0,
+            measurement_period: 0,
+            update_interval: 0,
+        }
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        match data {
+            [p0, p1, t0, t1, t2, sampling_function, measurement_period, update_interval] => Ok(Self {
+                property: PropertyId(u16::from_le_bytes([*p0, *p1])),
+                positive_tolerance: u16::from(*t0) | u16::from(*t1 & 0x0f) << 8,
+                negative_tolerance: u16::from(*t1 >> 4) | u16::from(*t2) << 4,
+                sampling_function: *sampling_function,
+                measurement_period: *measurement_period,
+                update_interval: *update_interval,
+            }),
+            _ => Err(ParseError::InvalidLength),
+        }
+    }
+
+    fn emit(&self, xmit: &mut Vec<u8>) {
+        let positive = self.positive_tolerance & 0x0fff;
+        let negative = self.negative_tolerance & 0x0fff;
+        xmit.extend_from_slice(&self.property.0.to_le_bytes());
+        xmit.extend_from_slice(&[
+            positive as u8,
+            (positive >> 8) as u8 | (negative << 4) as u8,
+            (negative >> 4) as u8,
+            self.sampling_function,
+            self.measurement_period,
+            self.update_interval,
+        ]);
+    }
+}
+
+/// Change of a sensor value that triggers its publication.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatusTrigger {
+    /// Change in the unit of the property.
+    Value {
+        /// Decrease triggering publication.
+        down: f64,
+        /// Increase triggering publication.
+        up: f64,
+    },
+    /// Change in percent of the value, with a resolution of 0.01 %.
+    Percent {
+        /// Decrease triggering publication.
+        down: f64,
+        /// Increase triggering publication.
+        up: f64,
+    },
+}
+
+/// Publication cadence of a sensor property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cadence {
+    /// Base-2 logarithm of the divisor of the publish period
+    /// while the value is inside the fast cadence range.
+    pub fast_period_divisor: u8,
+    /// Change of the value triggering its publication.
+    pub trigger: StatusTrigger,
+    /// Base-2 logarithm of the minimum publication interval in milliseconds.
+    pub min_interval: u8,
+    /// Low bound of the fast cadence range.
+    pub fast_low: f64,
+    /// High bound of the fast cadence range.
+    pub fast_high: f64,
+}
+
+impl Cadence {
+    fn parse(format: Format, data: &[u8]) -> Result<Self, ParseError> {
+        let size = format.size();
+        let (divisor, data) = data.split_first().ok_or(ParseError::InvalidLength)?;
+        let percent = divisor & 0x80 != 0;
+        let trigger_size = if percent { 2 } else { size };
+        if data.len() != 2 * trigger_size + 1 + 2 * size {
+            return Err(ParseError::InvalidLength);
+        }
+
+        let (down, data) = data.split_at(trigger_size);
+        let (up, data) = data.split_at(trigger_size);
+        let trigger = if percent {
+            StatusTrigger::Percent {
+                down: f64::from(u16::from_le_bytes([down[0], down[1]])) * TRIGGER_PERCENT_RESOLUTION,
+                up: f64::from(u16::from_le_bytes([up[0], up[1]])) * TRIGGER_PERCENT_RESOLUTION,
+            }
+        } else {
+            StatusTrigger::Value { down: format.decode_known(down)?, up: format.decode_known(up)? }
+        };
+
+        let (min_interval, data) = data.split_first().ok_or(ParseError::InvalidLength)?;
+        let (low, high) = data.split_at(size);
+        Ok(Self {
+            fast_period_divisor: divisor & 0x7f,
+            trigger,
+            min_interval: *min_interval,
+            fast_low: format.decode_known(low)?,
+            fast_high: format.decode_known(high)?,
+        })
+    }
+
+    fn emit(&self, format: Format, xmit: &mut Vec<u8>) {
+        match self.trigger {
+            StatusTrigger::Value { down, up } => {
+                xmit.push(self.fast_period_divisor & 0x7f);
+                format.encode(Some(down), xmit);
+                format.encode(Some(up), xmit);
+            }
+            StatusTrigger::Percent { down, up } => {
+                xmit.push(self.fast_period_divisor | 0x80);
+                for percent in [down, up] {
+                    let raw = (percent / TRIGGER_PERCENT_RESOLUTION).round().clamp(0.0, u16::MAX.into()) as u16;
+                    xmit.extend_from_slice(&raw.to_le_bytes());
+                }
+            }
+        }
+        xmit.push(self.min_interval);
+        format.encode(Some(self.fast_low), xmit);
+        format.encode(Some(self.fast_high), xmit);
+    }
+}
+
+/// Column of a sensor series.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeriesColumn {
+    /// Start of the column.
+    pub x: f64,
+    /// Width of the column.
+    pub width: f64,
+    /// Value of the column, [None] if unknown.
+    pub y: Option<f64>,
+}
+
+/// Sensor message.
+///
+/// Cadence and series messages can only be encoded and parsed for
+/// properties known to the [registry](Property::lookup).
+/// When emitting such a message for an unknown property, its values are omitted.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SensorMessage {
+    /// Get the descriptor of a property or of all properties.
+    DescriptorGet(Option<PropertyId>),
+    /// Descriptors of properties.
+    DescriptorStatus(Vec<SensorDescriptor>),
+    /// The requested property is not supported.
+    DescriptorUnsupported(PropertyId),
+    /// Get the value of a property or of all properties.
+    Get(Option<PropertyId>),
+    /// Values of properties.
+    Status(Vec<SensorValue>),
+    /// Get the columns of a series, optionally with a start
+    /// between the specified bounds.
+    SeriesGet {
+        /// Property of the series.
+        property: PropertyId,
+        /// Inclusive bounds of the column start.
+        range: Option<(f64, f64)>,
+    },
+    /// Columns of a series.
+    SeriesStatus {
+        /// Property of the series.
+        property: PropertyId,
+        /// Columns.
+        columns: Vec<SeriesColumn>,
+    },
+    /// Get the cadence of a property.
+    CadenceGet(PropertyId),
+    /// Set the cadence of a property and reply with the status.
+    CadenceSet {
+        /// Property.
+        property: PropertyId,
+        /// Cadence.
+        cadence: Cadence,
+    },
+    /// Set the cadence of a property without reply.
+    CadenceSetUnacknowledged {
+        /// Property.
+        property: PropertyId,
+        /// Cadence.
+        cadence: Cadence,
+    },
+    /// Cadence of a property.
+    CadenceStatus {
+        /// Property.
+        property: PropertyId,
+        /// Cadence, [None] if the property does not support a cadence.
+        cadence: Option<Cadence>,
+    },
+}
+
+impl SensorMessage {
+    /// Parses a sensor message.
+    ///
+    /// Returns [None] if the opcode does not belong to a sensor message.
+    pub fn parse(opcode: &Opcode, parameters: &[u8]) -> Result<Option<Self>, ParseError> {
+        let parse_optional_property = |parameters: &[u8]| match parameters {
+            [] => Ok(None),
+            property => PropertyId::parse(property).map(Some),
+        };
+        let parse_cadence = |parameters: &[u8]| {
+            let property = PropertyId::parse(parameters.get(..2).ok_or(ParseError::InvalidLength)?)?;
+            Ok((property, Cadence::parse(Property::format_of(property)?, &parameters[2..])?))
+        };
+
+        let msg = match *opcode {
+            DESCRIPTOR_GET => Self::DescriptorGet(parse_optional_property(parameters)?),
+            DESCRIPTOR_STATUS if parameters.len() == 2 => {
+                Self::DescriptorUnsupported(PropertyId::parse(parameters)?)
+            }
+            DESCRIPTOR_STATUS if parameters.len() % DESCRIPTOR_LEN == 0 => Self::DescriptorStatus(
+                parameters.chunks(DESCRIPTOR_LEN).map(SensorDescriptor::parse).collect::<Result<_, _>>()?,
+            ),
+            DESCRIPTOR_STATUS => return Err(ParseError::InvalidLength),
+            GET => Self::Get(parse_optional_property(parameters)?),
+            STATUS => Self::Status(SensorValue::parse_all(parameters)?),
+            SERIES_GET => {
+                let property = PropertyId::parse(parameters.get(..2).ok_or(ParseError::InvalidLength)?)?;
+                let range = match &parameters[2..] {
+                    [] => None,
+                    bounds => {
+                        let format = Property::format_of(property)?;
+                        if bounds.len() != 2 * format.size() {
+                            return Err(ParseError::InvalidLength);
+                        }
+                        let (x1, x2) = bounds.split_at(format.size());
+                        Some((format.decode_known(x1)?, format.decode_known(x2)?))
+                    }
+                };
+                Self::SeriesGet { property, range }
+            }
+            SERIES_STATUS => {
+                let property = PropertyId::parse(parameters.get(..2).ok_or(ParseError::InvalidLength)?)?;
+                let columns = match &parameters[2..] {
+                    [] => Vec::new(),
+                    columns => {
+                        let format = Property::format_of(property)?;
+                        let size = format.size();
+                        if columns.len() % (3 * size) != 0 {
+                            return Err(ParseError::InvalidLength);
+                        }
+                        columns
+                            .chunks(3 * size)
+                            .map(|column| {
+                                Ok(SeriesColumn {
+                                    x: format.decode_known(&column[..size])?,
+                                    width: format.decode_known(&column[size..2 * size])?,
+                                    y: format.decode(&column[2 * size..])?,
+                                })
+                            })
+                            .collect::<Result<_, ParseError>>()?
+                    }
+                };
+                Self::SeriesStatus { property, columns }
+            }
+            CADENCE_GET => Self::CadenceGet(PropertyId::parse(parameters)?),
+            CADENCE_SET => {
+                let (property, cadence) = parse_cadence(parameters)?;
+                Self::CadenceSet { property, cadence }
+            }
+            CADENCE_SET_UNACKNOWLEDGED => {
+                let (property, cadence) = parse_cadence(parameters)?;
+                Self::CadenceSetUnacknowledged { property, cadence }
+            }
+            CADENCE_STATUS if parameters.len() == 2 => {
+                Self::CadenceStatus { property: PropertyId::parse(parameters)?, cadence: None }
+            }
+            CADENCE_STATUS => {
+                let (property, cadence) = parse_cadence(parameters)?;
+                Self::CadenceStatus { property, cadence: Some(cadence) }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(msg))
+    }
+}
+
+impl Message for SensorMessage {
+    fn opcode(&self) -> Opcode {
+        match self {
+            Self::DescriptorGet(_) => DESCRIPTOR_GET,
+            Self::DescriptorStatus(_) | Self::DescriptorUnsupported(_) => DESCRIPTOR_STATUS,
+            Self::Get(_) => GET,
+            Self::Status(_) => STATUS,
+            Self::SeriesGet { .. } => SERIES_GET,
+            Self::SeriesStatus { .. } => SERIES_STATUS,
+            Self::CadenceGet(_) => CADENCE_GET,
+            Self::CadenceSet { .. } => CADENCE_SET,
+            Self::CadenceSetUnacknowledged { .. } => CADENCE_SET_UNACKNOWLEDGED,
+            Self::CadenceStatus { .. } => CADENCE_STATUS,
+        }
+    }
+
+    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
+        match self {
+            Self::DescriptorGet(property) | Self::Get(property) => {
+                if let Some(property) = property {
+                    xmit.extend_from_slice(&property.0.to_le_bytes());
+                }
+            }
+            Self::DescriptorStatus(descriptors) => {
+                for descriptor in descriptors {
+                    descriptor.emit(xmit);
+                }
+            }
+            Self::DescriptorUnsupported(property) | Self::CadenceGet(property) => {
+                xmit.extend_from_slice(&property.0.to_le_bytes())
+            }
+            Self::Status(values) => {
+                for value in values {
+                    value.emit(xmit);
+                }
+            }
+            Self::SeriesGet { property, range } => {
+                xmit.extend_from_slice(&property.0.to_le_bytes());
+                if let (Some((x1, x2)), Ok(format)) = (range, Property::format_of(*property)) {
+                    format.encode(Some(*x1), xmit);
+                    format.encode(Some(*x2), xmit);
+                }
+            }
+            Self::SeriesStatus { property, columns } => {
+                xmit.extend_from_slice(&property.0.to_le_bytes());
+                if let Ok(format) = Property::format_of(*property) {
+                    for column in columns {
+                        format.encode(Some(column.x), xmit);
+                        format.encode(Some(column.width), xmit);
+                        format.encode(column.y, xmit);
+                    }
+                }
+            }
+            Self::CadenceSet { property, cadence } | Self::CadenceSetUnacknowledged { property, cadence } => {
+                xmit.extend_from_slice(&property.0.to_le_bytes());
+                if let Ok(format) = Property::format_of(*property) {
+                    cadence.emit(format, xmit);
+                }
+            }
+            Self::CadenceStatus { property, cadence } => {
+                xmit.extend_from_slice(&property.0.to_le_bytes());
+                if let (Some(cadence), Ok(format)) = (cadence, Property::format_of(*property)) {
+                    cadence.emit(format, xmit);
+                }
+            }
+        }
+    }
+}
+
+/// State of a sensor property of a server.
+#[derive(Debug)]
+struct Sensor {
+    descriptor: SensorDescriptor,
+    value: SensorValue,
+    series: Vec<SeriesColumn>,
+    cadence: Option<Cadence>,
+}
+
+/// Sensor Server model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to and update the sensor values using
+/// [set_value](Self::set_value).
+///
+/// The handler also answers the cadence messages of the Sensor Setup Server model.
+/// The cadence is stored but not applied; the application decides when to
+/// publish the [status](Self::status).
+#[derive(Clone, Debug)]
+pub struct SensorServer {
+    sensors: Arc<Mutex<Vec<Sensor>>>,
+}
+
+impl SensorServer {
+    /// Creates a server providing the properties with the specified descriptors.
+    ///
+    /// The values of all properties are initially unknown.
+    pub fn new(descriptors: impl IntoIterator<Item = SensorDescriptor>) -> Self {
+        let sensors = descriptors
+            .into_iter()
+            .map(|descriptor| Sensor {
+                descriptor,
+                value: SensorValue::new(descriptor.property, None)
+                    .unwrap_or(SensorValue { property: descriptor.property, raw: Vec::new() }),
+                series: Vec::new(),
+                cadence: None,
+            })
+            .collect();
+        Self { sensors: Arc::new(Mutex::new(sensors)) }
+    }
+
+    /// Sets the value of a registered property provided by the server.
+    ///
+    /// [None] denotes an unknown value.
+    pub fn set_value(&self, property: PropertyId, value: Option<f64>) -> Result<(), ParseError> {
+        let value = SensorValue::new(property, value)?;
+        self.with_sensor(property, |sensor| sensor.value = value)
+    }
+
+    /// Sets the value of a property provided by the server in its raw encoding.
+    pub fn set_raw_value(&self, value: SensorValue) -> Result<(), ParseError> {
+        self.with_sensor(value.property, |sensor| sensor.value = value)
+    }
+
+    /// Sets the series of a property provided by the server.
+    pub fn set_series(&self, property: PropertyId, columns: Vec<SeriesColumn>) -> Result<(), ParseError> {
+        self.with_sensor(property, |sensor| sensor.series = columns)
+    }
+
+    /// Cadence of a property set by a client.
+    pub fn cadence(&self, property: PropertyId) -> Option<Cadence> {
+        let sensors = self.sensors.lock().unwrap();
+        sensors.iter().find(|sensor| sensor.descriptor.property == property).and_then(|sensor| sensor.cadence)
+    }
+
+    /// Status message with the values of all properties, for publication.
+    pub fn status(&self) -> SensorMessage {
+        let sensors = self.sensors.lock().unwrap();
+        SensorMessage::Status(sensors.iter().map(|sensor| sensor.value.clone()).collect())
+    }
+
+    fn with_sensor(&self, property: PropertyId, f: impl FnOnce(&mut Sensor)) -> Result<(), ParseError> {
+        let mut sensors = self.sensors.lock().unwrap();
+        let sensor = sensors
+            .iter_mut()
+            .find(|sensor| sensor.descriptor.property == property)
+            .ok_or(ParseError::InvalidValue)?;
+        f(sensor);
+        Ok(())
+    }
+
+    /// Handler answering descriptor, value, series and cadence requests.
+    pub fn handler(&self) -> HandlerFn<SensorMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<SensorMessage>| {
+            let reply = this.handle(req.message);
+            Box::pin(async move { reply })
+        })
+    }
+
+    fn handle(&self, message: SensorMessage) -> Option<Reply> {
+        let mut sensors = self.sensors.lock().unwrap();
+        let find = |sensors: &mut Vec<Sensor>, property: PropertyId| {
+            sensors.iter_mut().position(|sensor| sensor.descriptor.property == property)
+        };
+
+        let reply = match message {
+            SensorMessage::DescriptorGet(None) => {
+                SensorMessage::DescriptorStatus(sensors.iter().map(|sensor| sensor.descriptor).collect())
+            }
+            SensorMessage::DescriptorGet(Some(property)) => match find(&mut sensors, property) {
+                Some(idx) => SensorMessage::DescriptorStatus(vec![sensors[idx].descriptor]),
+                None => SensorMessage::DescriptorUnsupported(property),
+            },
+            SensorMessage::Get(None) => {
+                SensorMessage::Status(sensors.iter().map(|sensor| sensor.value.clone()).collect())
+            }
+            SensorMessage::Get(Some(property)) => match find(&mut sensors, property) {
+                Some(idx) => SensorMessage::Status(vec![sensors[idx].value.clone()]),
+                None => SensorMessage::Status(vec![SensorValue { property, raw: Vec::new() }]),
+            },
+            SensorMessage::SeriesGet { property, range } => {
+                let columns = match find(&mut sensors, property) {
+                    Some(idx) => sensors[idx]
+                        .series
+                        .iter()
+                        .filter(|column| range.map(|(x1, x2)| x1 <= column.x && column.x <= x2).unwrap_or(true))
+                        .copied()
+                        .collect(),
+                    None => Vec::new(),
+                };
+                SensorMessage::SeriesStatus { property, columns }
+            }
+            SensorMessage::CadenceGet(property) => {
+                let cadence = find(&mut sensors, property).and_then(|idx| sensors[idx].cadence);
+                SensorMessage::CadenceStatus { property, cadence }
+            }
+            SensorMessage::CadenceSet { property, cadence } => {
+                let cadence = find(&mut sensors, property).map(|idx| {
+                    sensors[idx].cadence = Some(cadence);
+                    cadence
+                });
+                SensorMessage::CadenceStatus { property, cadence }
+            }
+            SensorMessage::CadenceSetUnacknowledged { property, cadence } => {
+                if let Some(idx) = find(&mut sensors, property) {
+                    sensors[idx].cadence = Some(cadence);
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        Some(Box::new(reply))
+    }
+}
+
+impl Model for SensorServer {
+    fn identifier(&self) -> ModelIdentifier {
+        SENSOR_SERVER
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(SensorMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for SensorServer {
+    type Message = SensorMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<SensorMessage>, ParseError> {
+        SensorMessage::parse(opcode, parameters)
+    }
+}
+
+/// Sensor Client model.
+///
+/// Add the [handler](Self::handler) to the [Dispatcher](crate::mesh::dispatcher::Dispatcher)
+/// of the element the model belongs to, so that status replies and
+/// published sensor values are received.
+#[derive(Clone, Debug)]
+pub struct SensorClient {
+    descriptors: Arc<StatusWaiters<(Option<PropertyId>, Vec<SensorDescriptor>)>>,
+    values: Arc<StatusWaiters<Vec<SensorValue>>>,
+    series: Arc<StatusWaiters<(PropertyId, Vec<SeriesColumn>)>>,
+    cadences: Arc<StatusWaiters<(PropertyId, Option<Cadence>)>>,
+    statuses: broadcast::Sender<(u16, Vec<SensorValue>)>,
+}
+
+impl Default for SensorClient {
+    fn default() -> Self {
+        Self {
+            descriptors: Default::default(),
+            values: Default::default(),
+            series: Default::default(),
+            cadences: Default::default(),
+            statuses: broadcast::channel(16).0,
+        }
+    }
+}
+
+impl SensorClient {
+    /// Creates a client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handler receiving status replies and published values.
+    pub fn handler(&self) -> HandlerFn<SensorMessage> {
+        let this = self.clone();
+        Box::new(move |req: Request<SensorMessage>| {
+            let unsolicited = match req.message {
+                SensorMessage::DescriptorStatus(descriptors) => this
+                    .descriptors
+                    .deliver(req.src, req.dest, (None, descriptors))
+                    .map(|(_, descriptors)| SensorMessage::DescriptorStatus(descriptors)),
+                SensorMessage::DescriptorUnsupported(property) => this
+                    .descriptors
+                    .deliver(req.src, req.dest, (Some(property), Vec::new()))
+                    .map(|_| SensorMessage::DescriptorUnsupported(property)),
+                SensorMessage::Status(values) => {
+                    let _ = this.statuses.send((req.src, values.clone()));
+                    let _ = this.values.deliver(req.src, req.dest, values);
+                    None
+                }
+                SensorMessage::SeriesStatus { property, columns } => this
+                    .series
+                    .deliver(req.src, req.dest, (property, columns))
+                    .map(|(property, columns)| SensorMessage::SeriesStatus { property, columns }),
+                SensorMessage::CadenceStatus { property, cadence } => this
+                    .cadences
+                    .deliver(req.src, req.dest, (property, cadence))
+                    .map(|(property, cadence)| SensorMessage::CadenceStatus { property, cadence }),
+                msg => Some(msg),
+            };
+            if let Some(msg) = unsolicited {
+                log::trace!("Unsolicited sensor message {:?} from {:04x}", msg, req.src);
+            }
+            Box::pin(async { None })
+        })
+    }
+
+    /// Stream of all received sensor values, including published values.
+    ///
+    /// Each item consists of the unicast address of the source and the values.
+    pub fn statuses(&self) -> impl Stream<Item = (u16, Vec<SensorValue>)> {
+        stream::unfold(self.statuses.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(status) => return Some((status, rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Gets the descriptors of the properties of the server at `dest`.
+    ///
+    /// If `property` is specified, only its descriptor is requested and
+    /// an empty list is returned if the server does not support it.
+    ///
+    /// `path` is the path of the element the client belongs to and
+    /// `app_key` is the index of the application key used to encrypt the request.
+    pub async fn descriptors(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, property: Option<PropertyId>,
+    ) -> crate::Result<Vec<SensorDescriptor>> {
+        let msg = SensorMessage::DescriptorGet(property);
+        let (_, descriptors) = self
+            .descriptors
+            .request(node, path, dest, app_key, &msg, move |(unsupported, descriptors)| {
+                match (property, unsupported) {
+                    (None, None) => true,
+                    (None, Some(_)) => false,
+                    (Some(property), None) => descriptors.iter().all(|desc| desc.property == property),
+                    (Some(property), Some(unsupported)) => *unsupported == property,
+                }
+            })
+            .await?;
+        Ok(descriptors)
+    }
+
+    /// Gets the values of the properties of the server at `dest`.
+    ///
+    /// If `property` is specified, only its value is requested.
+    pub async fn get(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, property: Option<PropertyId>,
+    ) -> crate::Result<Vec<SensorValue>> {
+        self.values
+            .request(node, path, dest, app_key, &SensorMessage::Get(property), move |values| match property {
+                Some(property) => values.iter().any(|value| value.property == property),
+                None => true,
+            })
+            .await
+    }
+
+    /// Gets the columns of a series of the server at `dest`,
+    /// optionally only those starting within `range`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn series(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, property: PropertyId,
+        range: Option<(f64, f64)>,
+    ) -> crate::Result<Vec<SeriesColumn>> {
+        let msg = SensorMessage::SeriesGet { property, range };
+        let (_, columns) =
+            self.series.request(node, path, dest, app_key, &msg, move |(status, _)| *status == property).await?;
+        Ok(columns)
+    }
+
+    /// Gets the cadence of a property of the server at `dest`.
+    ///
+    /// Returns [None] if the property does not support a cadence.
+    pub async fn cadence(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, property: PropertyId,
+    ) -> crate::Result<Option<Cadence>> {
+        let msg = SensorMessage::CadenceGet(property);
+        let (_, cadence) = self
+            .cadences
+            .request(node, path, dest, app_key, &msg, move |(status, _)| *status == property)
+            .await?;
+        Ok(cadence)
+    }
+
+    /// Sets the cadence of a property of the setup server at `dest` and returns its new value.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn set_cadence(
+        &self, node: &Node, path: Path<'_>, dest: u16, app_key: u16, property: PropertyId, cadence: Cadence,
+    ) -> crate::Result<Option<Cadence>> {
+        let msg = SensorMessage::CadenceSet { property, cadence };
+        let (_, cadence) = self
+            .cadences
+            .request(node, path, dest, app_key, &msg, move |(status, _)| *status == property)
+            .await?;
+        Ok(cadence)
+    }
+}
+
+impl Model for SensorClient {
+    fn identifier(&self) -> ModelIdentifier {
+        SENSOR_CLIENT
+    }
+
+    fn supports_subscription(&self) -> bool {
+        true
+    }
+
+    fn supports_publication(&self) -> bool {
+        true
+    }
+
+    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
+    where
+        Self: 'm,
+    {
+        Ok(SensorMessage::parse(&opcode, parameters)?.map(|msg| Box::new(msg) as Box<dyn Message>))
+    }
+}
+
+impl TypedModel for SensorClient {
+    type Message = SensorMessage;
+
+    fn parse_message(opcode: &Opcode, parameters: &[u8]) -> Result<Option<SensorMessage>, ParseError> {
+        SensorMessage::parse(opcode, parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(format: Format, value: Option<f64>) -> Vec<u8> {
+        let mut xmit = Vec::new();
+        format.encode(value, &mut xmit);
+        xmit
+    }
+
+    fn emit(value: &SensorValue) -> Vec<u8> {
+        let mut xmit = Vec::new();
+        value.emit(&mut xmit);
+        xmit
+    }
+
+    fn assert_decodes(format: Format, raw: &[u8], expected: f64) {
+        let value = format.decode(raw).unwrap().unwrap();
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "{:?} decoded {:x?} to {}, expected {}",
+            format,
+            raw,
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn decode_sign_extension() {
+        assert_eq!(Format::Temperature8.decode(&[0xfe]), Ok(Some(-1.0)));
+        assert_eq!(Format::Temperature8.decode(&[0x80]), Ok(Some(-64.0)));
+        assert_eq!(Format::Temperature8.decode(&[0x2b]), Ok(Some(21.5)));
+        assert_decodes(Format::Temperature, &[0x4d, 0x95], -273.15);
+        assert_decodes(Format::Temperature, &[0xff, 0x7f], 327.67);
+        assert_eq!(Format::Percentage8.decode(&[0xc8]), Ok(Some(100.0)));
+        assert_decodes(Format::Illuminance, &[0x10, 0x27, 0x00], 100.0);
+    }
+
+    #[test]
+    fn decode_unknown_and_range() {
+        assert_eq!(Format::Temperature8.decode(&[0x7f]), Ok(None));
+        assert_eq!(Format::Temperature.decode(&[0x00, 0x80]), Ok(None));
+        assert_eq!(Format::Count16.decode(&[0xff, 0xff]), Ok(None));
+        assert_eq!(Format::Illuminance.decode(&[0xff, 0xff, 0xff]), Ok(None));
+
+        assert_eq!(Format::Temperature.decode(&[0x4c, 0x95]), Err(ParseError::InvalidValue));
+        assert_eq!(Format::Humidity.decode(&[0x11, 0x27]), Err(ParseError::InvalidValue));
+        assert_eq!(Format::Percentage8.decode(&[0xc9]), Err(ParseError::InvalidValue));
+        assert_eq!(Format::Boolean.decode(&[0x02]), Err(ParseError::InvalidValue));
+        assert_eq!(Format::Temperature.decode(&[0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(Format::Boolean.decode(&[]), Err(ParseError::InvalidLength));
+    }
+
+    #[test]
+    fn encode_rounds_and_saturates() {
+        assert_eq!(encode(Format::Temperature8, Some(21.3)), [0x2b]);
+        assert_eq!(encode(Format::Temperature8, Some(-70.0)), [0x80]);
+        assert_eq!(encode(Format::Temperature8, Some(100.0)), [0x7e]);
+        assert_eq!(encode(Format::Temperature, Some(-300.0)), [0x4d, 0x95]);
+        assert_eq!(encode(Format::Voltage, Some(3.3)), [0xd3, 0x00]);
+        assert_eq!(encode(Format::Humidity, Some(150.0)), [0x10, 0x27]);
+        assert_eq!(encode(Format::Illuminance, Some(100.0)), [0x10, 0x27, 0x00]);
+
+        assert_eq!(encode(Format::Temperature8, None), [0x7f]);
+        assert_eq!(encode(Format::Temperature, Some(f64::NAN)), [0x00, 0x80]);
+        assert_eq!(encode(Format::Boolean, None), [0x00]);
+
+        for format in [Format::Count16, Format::Humidity, Format::Temperature, Format::Voltage] {
+            assert_eq!(encode(format, None).len(), format.size());
+        }
+    }
+
+    #[test]
+    fn value_format_a() {
+        let value = SensorValue::new(PropertyId::PRESENT_AMBIENT_TEMPERATURE, Some(21.5)).unwrap();
+        assert_eq!(emit(&value), [0xe0, 0x09, 0x2b]);
+        assert_eq!(SensorValue::parse_all(&emit(&value)), Ok(vec![value]));
+
+        let value = SensorValue { property: PropertyId(0x07ff), raw: vec![0xaa; 16] };
+        let xmit = emit(&value);
+        assert_eq!(xmit[..2], [0xfe, 0xff]);
+        assert_eq!(SensorValue::parse_all(&xmit), Ok(vec![value]));
+    }
+
+    #[test]
+    fn value_format_b() {
+        let value = SensorValue { property: PropertyId(0x0800), raw: vec![0x01, 0x02] };
+        assert_eq!(emit(&value), [0x03, 0x00, 0x08, 0x01, 0x02]);
+        assert_eq!(SensorValue::parse_all(&emit(&value)), Ok(vec![value]));
+
+        let value = SensorValue { property: PropertyId::PRESENCE_DETECTED, raw: Vec::new() };
+        assert_eq!(emit(&value), [0xff, 0x4d, 0x00]);
+        assert_eq!(SensorValue::parse_all(&emit(&value)), Ok(vec![value]));
+
+        let value = SensorValue { property: PropertyId(0x0042), raw: vec![0x55; 17] };
+        assert_eq!(emit(&value)[..3], [0x21, 0x42, 0x00]);
+        assert_eq!(SensorValue::parse_all(&emit(&value)), Ok(vec![value]));
+
+        let value = SensorValue { property: PropertyId(0x0042), raw: vec![0x55; MAX_VALUE_LEN] };
+        assert_eq!(emit(&value)[0], 0xfd);
+        assert_eq!(SensorValue::parse_all(&emit(&value)), Ok(vec![value]));
+    }
+
+    #[test]
+    fn value_too_long_is_truncated() {
+        let value = SensorValue { property: PropertyId(0x0042), raw: (0..=255).collect() };
+        let xmit = emit(&value);
+        assert_eq!(xmit.len(), 3 + MAX_VALUE_LEN);
+        assert_eq!(
+            SensorValue::parse_all(&xmit),
+            Ok(vec![SensorValue { property: value.property, raw: value.raw[..MAX_VALUE_LEN].to_vec() }])
+        );
+    }
+
+    #[test]
+    fn values_parse_all() {
+        let values = vec![
+            SensorValue::new(PropertyId::PRESENT_AMBIENT_TEMPERATURE, Some(21.5)).unwrap(),
+            SensorValue { property: PropertyId::PEOPLE_COUNT, raw: Vec::new() },
+            SensorValue::new(PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL, None).unwrap(),
+        ];
+        let mut xmit = Vec::new();
+        for value in &values {
+            value.emit(&mut xmit);
+        }
+        assert_eq!(SensorValue::parse_all(&xmit), Ok(values));
+
+        assert_eq!(SensorValue::parse_all(&[0xe0]), Err(ParseError::InvalidLength));
+        assert_eq!(SensorValue::parse_all(&[0xe2, 0x09, 0x2b]), Err(ParseError::InvalidLength));
+        assert_eq!(SensorValue::parse_all(&[0x03, 0x00]), Err(ParseError::InvalidLength));
+        assert_eq!(SensorValue::parse_all(&[]), Ok(Vec::new()));
+    }
+}
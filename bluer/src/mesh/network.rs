@@ -1,9 +1,10 @@
 //! Implement Network bluetooth mesh interface
 
-use crate::{Error, ErrorKind, InternalErrorKind, Result, SessionInner};
-use std::sync::Arc;
+use crate::{Error, ErrorKind, Result, SessionInner};
+use std::{collections::HashMap, fmt, sync::Arc};
 
 use dbus::{
+    arg::{AppendAll, RefArg, Variant},
     nonblock::{Proxy, SyncConnection},
     Path,
 };
@@ -11,6 +12,7 @@ use dbus::{
 use crate::mesh::{
     all_dbus_objects,
     application::{Application, ApplicationHandle, RegisteredApplication},
+    management::Key,
     node::Node,
     ElementConfig, PATH, SERVICE_NAME, TIMEOUT,
 };
@@ -18,6 +20,16 @@ use uuid::Uuid;
 
 pub(crate) const INTERFACE: &str = "org.bluez.mesh.Network1";
 
+/// Network state of a node being imported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportFlags {
+    /// The IV update procedure is in progress.
+    pub iv_update: bool,
+    /// The key refresh procedure is in progress.
+    pub key_refresh: bool,
+}
+
 /// Interface to a Bluetooth mesh network.
 #[derive(Clone)]
 pub struct Network {
@@ -40,42 +52,121 @@ impl Network {
         reg.register(root_path, self.inner.clone()).await
     }
 
-    /// Join mesh network
-    pub async fn join(&self, path: &str, uuid: Uuid) -> Result<()> {
-        let path_value =
-            Path::new(path).map_err(|_| Error::new(ErrorKind::Internal(InternalErrorKind::InvalidValue)))?;
+    /// Join mesh network as an unprovisioned device with the specified UUID.
+    ///
+    /// The application must have been registered at `path`.
+    ///
+    /// The returned future resolves once the device has been provisioned by a
+    /// remote provisioner and returns the token of the newly created node.
+    /// Store this token to [attach](Self::attach) to the network later.
+    /// If provisioning fails an error with [ErrorKind::ProvisioningFailed] is returned.
+    pub async fn join(&self, path: Path<'_>, uuid: Uuid) -> Result<u64> {
+        self.create_node(path.clone(), "Join", (path, uuid.as_bytes().to_vec())).await
+    }
+
+    /// Create a new mesh network with the local node as its first provisioner.
+    ///
+    /// The application must have been registered at `path`.
+    /// The node is created with the specified device UUID, a new network key
+    /// with index 0 and the primary unicast address 0x0001.
+    ///
+    /// Returns the token of the newly created node.
+    /// Store this token to [attach](Self::attach) to the network later.
+    pub async fn create_network(&self, path: Path<'_>, uuid: Uuid) -> Result<u64> {
+        self.create_node(path.clone(), "CreateNetwork", (path, uuid.as_bytes().to_vec())).await
+    }
 
-        self.call_method("Join", (path_value, uuid.as_bytes().to_vec())).await
+    /// Create a local node from the keys and address of an already provisioned node.
+    ///
+    /// The application must have been registered at `path`.
+    /// `dev_key` is the device key of the node, `net_key` the network key
+    /// with index `net_index`, `iv_index` the current IV index of the network
+    /// and `unicast` the address of the primary element of the node.
+    ///
+    /// Returns the token of the newly created node.
+    /// Store this token to [attach](Self::attach) to the network later.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn import(
+        &self, path: Path<'_>, uuid: Uuid, dev_key: Key, net_key: Key, net_index: u16, flags: ImportFlags,
+        iv_index: u32, unicast: u16,
+    ) -> Result<u64> {
+        let mut flags_dict: HashMap<&'static str, Variant<Box<dyn RefArg>>> = HashMap::new();
+        flags_dict.insert("IvUpdate", Variant(Box::new(flags.iv_update)));
+        flags_dict.insert("KeyRefresh", Variant(Box::new(flags.key_refresh)));
+
+        self.create_node(
+            path.clone(),
+            "Import",
+            (
+                path,
+                uuid.as_bytes().to_vec(),
+                dev_key.to_vec(),
+                net_key.to_vec(),
+                net_index,
+                flags_dict,
+                iv_index,
+                unicast,
+            ),
+        )
+        .await
     }
 
-    /// Attach to mesh network
-    pub async fn attach(&self, path: Path<'_>, token: &str) -> Result<Node> {
-        let token_int = u64::from_str_radix(token, 16)
-            .map_err(|_| Error::new(ErrorKind::Internal(InternalErrorKind::InvalidValue)))?;
+    /// Calls a method creating a node and waits for the token reported by `JoinComplete`.
+    async fn create_node<A>(&self, path: Path<'_>, method: &str, args: A) -> Result<u64>
+    where
+        A: AppendAll + fmt::Debug,
+    {
+        let app = RegisteredApplication::find(&self.inner, &path)
+            .await
+            .ok_or_else(|| Error::new(ErrorKind::NotRegistered))?;
+
+        let result_rx = app.expect_join();
+        if let Err(err) = self.call_method::<_, ()>(method, args).await {
+            app.forget_join();
+            return Err(err);
+        }
+
+        match result_rx.await {
+            Ok(Ok(token)) => Ok(token),
+            Ok(Err(reason)) => Err(Error::new(ErrorKind::ProvisioningFailed(reason))),
+            Err(_) => Err(Error::new(ErrorKind::NotRegistered)),
+        }
+    }
+
+    /// Attach to mesh network using the token of a node.
+    pub async fn attach(&self, path: Path<'_>, token: u64) -> Result<Node> {
+        let app = RegisteredApplication::find(&self.inner, &path)
+            .await
+            .ok_or_else(|| Error::new(ErrorKind::NotRegistered))?;
 
         let (node_path, config): (Path<'static>, Vec<(u8, Vec<(u16, ElementConfig)>)>) =
-            self.call_method("Attach", (path, token_int)).await?;
+            self.call_method("Attach", (path, token)).await?;
 
         log::info!("Attached app to {:?} with elements config {:?}", node_path, config);
 
-        let node = Node::new(node_path.clone(), self.inner.clone()).await?;
-        //let management = Management::new(node_path.clone(), self.inner.clone()).await?;
+        let node = Node::new(node_path.clone(), self.inner.clone(), app.provisioner.clone()).await?;
+
+        app.configure(&config);
+        app.attached(token);
 
-        // TODO configure elements and pass them node object
         Ok(node)
     }
 
     /// Cancel provisioning request
+    ///
+    /// A pending [join](Self::join) fails with [ProvisioningError::Aborted](crate::mesh::ProvisioningError::Aborted).
     pub async fn cancel(&self) -> Result<()> {
-        self.call_method("Cancel", ()).await
-    }
+        self.call_method::<_, ()>("Cancel", ()).await?;
 
-    /// Leave mesh network
-    pub async fn leave(&self, token: &str) -> Result<()> {
-        let token_int = u64::from_str_radix(token, 16)
-            .map_err(|_| Error::new(ErrorKind::Internal(InternalErrorKind::InvalidValue)))?;
+        for app in self.inner.mesh_applications.lock().await.values() {
+            app.abort_join();
+        }
+        Ok(())
+    }
 
-        self.call_method("Leave", (token_int,)).await
+    /// Leave mesh network, removing the node with the specified token.
+    pub async fn leave(&self, token: u64) -> Result<()> {
+        self.call_method("Leave", (token,)).await
     }
 
     /// Temprorary debug method to print the state of mesh
@@ -1,26 +1,27 @@
 //! Implements Node bluetooth mesh interface
 
-use crate::{Result, SessionInner};
+use crate::{Error, ErrorKind, Result, SessionInner};
+use futures::{stream, Stream, StreamExt};
 use std::{collections::HashMap, sync::Arc};
 
 use dbus::{
-    arg::{RefArg, Variant},
+    arg::{PropMap, RefArg, Variant},
     nonblock::{Proxy, SyncConnection},
     Path,
 };
 
 use crate::{
     mesh::{
+        management::Management, provisioner::RegisteredProvisioner, Destination, Message, ModelIdentifier,
         SERVICE_NAME, TIMEOUT,
-        management::Management,
     },
-    Error, ErrorKind,
+    session::Event,
 };
-use drogue_device::drivers::ble::mesh::model::{Message, Model, ModelIdentifier};
 
 pub(crate) const INTERFACE: &str = "org.bluez.mesh.Node1";
 
 /// Interface to a Bluetooth mesh node.
+#[derive(Clone)]
 pub struct Node {
     inner: Arc<SessionInner>,
     path: Path<'static>,
@@ -28,31 +29,202 @@ pub struct Node {
     pub management: Option<Management>,
 }
 
+/// Options for sending or publishing a message.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SendOptions {
+    /// Send the message as a segmented access message,
+    /// even if it would fit into a single unsegmented PDU.
+    pub force_segmented: bool,
+    #[doc(hidden)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub _non_exhaustive: (),
+}
+
+impl SendOptions {
+    fn to_dict(&self) -> HashMap<&'static str, Variant<Box<dyn RefArg>>> {
+        let mut dict: HashMap<&'static str, Variant<Box<dyn RefArg>>> = HashMap::new();
+        if self.force_segmented {
+            dict.insert("ForceSegmented", Variant(Box::new(true)));
+        }
+        dict
+    }
+}
+
+/// Features of a Bluetooth mesh node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeFeatures {
+    /// Friend feature is supported and enabled.
+    pub friend: bool,
+    /// Low power feature is supported and enabled.
+    pub low_power: bool,
+    /// Proxy feature is supported and enabled.
+    pub proxy: bool,
+    /// Relay feature is supported and enabled.
+    pub relay: bool,
+}
+
+impl NodeFeatures {
+    fn from_dict(dict: &PropMap) -> Self {
+        let feature = |name| dbus::arg::prop_cast::<bool>(dict, name).copied().unwrap_or_default();
+        Self {
+            friend: feature("Friend"),
+            low_power: feature("LowPower"),
+            proxy: feature("Proxy"),
+            relay: feature("Relay"),
+        }
+    }
+}
+
+/// Bluetooth mesh node event.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NodeEvent {
+    /// Property changed.
+    PropertyChanged(NodeProperty),
+}
+
 impl Node {
-    pub(crate) async fn new(path: Path<'static>, inner: Arc<SessionInner>) -> Result<Self> {
-        let management = Some(Management::new(path.clone(), inner.clone()).await?);
+    pub(crate) async fn new(
+        path: Path<'static>, inner: Arc<SessionInner>, provisioner: Option<RegisteredProvisioner>,
+    ) -> Result<Self> {
+        let management = Some(Management::new(path.clone(), inner.clone(), provisioner).await?);
         Ok(Self { inner, path, management })
     }
 
     /// Publish message to the mesh
-    pub async fn publish<'m, M: Model>(&self, message: M::Message<'m>, path: Path<'m>) -> Result<()> {
-        let model_id = match M::IDENTIFIER {
+    ///
+    /// The message is sent to the publication address of the model,
+    /// which may be a virtual label, as configured by the configuration client.
+    ///
+    /// `path` is the path of the element that the publishing model belongs to
+    /// and `model` is the identifier of the publishing model.
+    pub async fn publish(
+        &self, path: Path<'_>, model: ModelIdentifier, options: SendOptions, message: &dyn Message,
+    ) -> Result<()> {
+        let mut options = options.to_dict();
+        let model_id = match model {
             ModelIdentifier::SIG(id) => id,
-            ModelIdentifier::Vendor(_, id) => id,
+            ModelIdentifier::Vendor(vendor, id) => {
+                options.insert("Vendor", Variant(Box::new(vendor.0)));
+                id
+            }
+        };
+
+        let mut data = Vec::new();
+        message.opcode().emit(&mut data);
+        message.emit_parameters(&mut data);
+
+        log::trace!("Publishing message: {:?} {:?} {:?} {:?}", path, model_id, options, &data);
+        self.call_method("Publish", (path, model_id, options, data)).await?;
+
+        Ok(())
+    }
+
+    /// Send a message originated by a local model to a destination.
+    ///
+    /// `path` is the path of the element that the sending model belongs to and
+    /// `app_key` is the index of the application key used to encrypt the message.
+    ///
+    /// [Virtual](Destination::Virtual) destinations are not supported, since the
+    /// mesh daemon does not take the label UUID that is part of the encryption
+    /// of messages sent to a virtual address.
+    /// Configure a [publication](Self::publish) to the virtual address instead.
+    pub async fn send(
+        &self, path: Path<'_>, destination: Destination, app_key: u16, options: SendOptions,
+        message: &dyn Message,
+    ) -> Result<()> {
+        let address = match destination {
+            Destination::Unicast(address) | Destination::Group(address) => address,
+            Destination::Virtual(_) => return Err(Error::new(ErrorKind::InvalidArguments)),
         };
 
-        let mut data: heapless::Vec<u8, 384> = heapless::Vec::new();
-        message.opcode().emit(&mut data).map_err(|_| Error::new(ErrorKind::Failed))?;
-        message.emit_parameters(&mut data).map_err(|_| Error::new(ErrorKind::Failed))?;
+        let mut data = Vec::new();
+        message.opcode().emit(&mut data);
+        message.emit_parameters(&mut data);
+
+        let options = options.to_dict();
 
-        let options: HashMap<&'static str, Variant<Box<dyn RefArg>>> = HashMap::new();
+        log::trace!("Sending message: {:?} {:?} {:?} {:?} {:?}", path, destination, app_key, options, &data);
+        self.call_method("Send", (path, address, app_key, options, data)).await?;
 
-        log::trace!("Publishing message: {:?} {:?} {:?} {:?}", path, model_id, options, data.to_vec());
-        self.call_method("Publish", (path, model_id, options, data.to_vec())).await?;
+        Ok(())
+    }
+
+    /// Send a message originated by a local model encrypted with a device key.
+    ///
+    /// `path` is the path of the element that the sending model belongs to and
+    /// `destination` is the unicast address of the receiving node.
+    /// If `remote` is true, the device key of the destination node is used,
+    /// otherwise the device key of the local node.
+    /// `net_index` is the index of the network key used to send the message.
+    pub async fn dev_key_send(
+        &self, path: Path<'_>, destination: u16, remote: bool, net_index: u16, options: SendOptions,
+        message: &dyn Message,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        message.opcode().emit(&mut data);
+        message.emit_parameters(&mut data);
+
+        let options = options.to_dict();
+
+        log::trace!(
+            "Sending device key message: {:?} {:04x} {:?} {:?} {:?} {:?}",
+            path,
+            destination,
+            remote,
+            net_index,
+            options,
+            &data
+        );
+        self.call_method("DevKeySend", (path, destination, remote, net_index, options, data)).await?;
 
         Ok(())
     }
 
+    /// Send a network key to a remote node.
+    ///
+    /// The key with index `subnet_index` is sent to the node at `destination`,
+    /// encrypted with its device key, using the network key with index `net_index`.
+    /// If `update` is true, the key is sent as an update during key refresh,
+    /// otherwise as a new key.
+    ///
+    /// `path` is the path of the element that contains the configuration client model.
+    pub async fn add_net_key(
+        &self, path: Path<'_>, destination: u16, subnet_index: u16, net_index: u16, update: bool,
+    ) -> Result<()> {
+        self.call_method("AddNetKey", (path, destination, subnet_index, net_index, update)).await
+    }
+
+    /// Send an application key to a remote node.
+    ///
+    /// The key with index `app_index` is sent to the node at `destination`,
+    /// encrypted with its device key, using the network key with index `net_index`.
+    /// If `update` is true, the key is sent as an update during key refresh,
+    /// otherwise as a new key.
+    ///
+    /// `path` is the path of the element that contains the configuration client model.
+    pub async fn add_app_key(
+        &self, path: Path<'_>, destination: u16, app_index: u16, net_index: u16, update: bool,
+    ) -> Result<()> {
+        self.call_method("AddAppKey", (path, destination, app_index, net_index, update)).await
+    }
+
+    /// Streams node property changes.
+    pub async fn events(&self) -> Result<impl Stream<Item = NodeEvent>> {
+        let events = self.inner.events(self.path.clone(), false).await?;
+        let stream = events.flat_map(move |event| match event {
+            Event::PropertiesChanged { interface, changed, .. } if interface == INTERFACE => {
+                stream::iter(NodeProperty::from_prop_map(changed).into_iter().map(NodeEvent::PropertyChanged))
+                    .boxed()
+            }
+            _ => stream::empty().boxed(),
+        });
+
+        Ok(stream)
+    }
+
     fn proxy(&self) -> Proxy<'_, &SyncConnection> {
         Proxy::new(SERVICE_NAME, self.path.clone(), TIMEOUT, &*self.inner.connection)
     }
@@ -60,3 +232,59 @@ impl Node {
     dbus_interface!();
     dbus_default_interface!(INTERFACE);
 }
+
+define_properties!(
+    Node,
+    /// Bluetooth mesh node property.
+    pub NodeProperty => {
+        /// Features supported by the node and whether they are enabled.
+        property(
+            Features, NodeFeatures,
+            dbus: (INTERFACE, "Features", PropMap, MANDATORY),
+            get: (features, v => { NodeFeatures::from_dict(v) }),
+        );
+
+        /// Whether the node broadcasts secure network beacons.
+        property(
+            Beacon, bool,
+            dbus: (INTERFACE, "Beacon", bool, MANDATORY),
+            get: (is_beacon, v => { v.to_owned() }),
+        );
+
+        /// Whether the IV update procedure is in progress.
+        property(
+            IvUpdate, bool,
+            dbus: (INTERFACE, "IvUpdate", bool, MANDATORY),
+            get: (is_iv_update, v => { v.to_owned() }),
+        );
+
+        /// Current IV index of the mesh network.
+        property(
+            IvIndex, u32,
+            dbus: (INTERFACE, "IvIndex", u32, MANDATORY),
+            get: (iv_index, v => { v.to_owned() }),
+        );
+
+        /// Number of seconds since the last valid secure network beacon
+        /// or mesh message of any network was received.
+        property(
+            SecondsSinceLastHeard, u32,
+            dbus: (INTERFACE, "SecondsSinceLastHeard", u32, MANDATORY),
+            get: (seconds_since_last_heard, v => { v.to_owned() }),
+        );
+
+        /// Unicast addresses of the elements of the node.
+        property(
+            Addresses, Vec<u16>,
+            dbus: (INTERFACE, "Addresses", Vec<u16>, MANDATORY),
+            get: (addresses, v => { v.to_owned() }),
+        );
+
+        /// Sequence number that will be used for the next message sent by the node.
+        property(
+            SequenceNumber, u32,
+            dbus: (INTERFACE, "SequenceNumber", u32, MANDATORY),
+            get: (sequence_number, v => { v.to_owned() }),
+        );
+    }
+);
@@ -1,38 +1,293 @@
 //! Implement Provisioner bluetooth mesh interface
 
 use crate::{method_call, SessionInner};
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
 
 use dbus::{
+    arg::PropMap,
     nonblock::{Proxy, SyncConnection},
 };
 use dbus_crossroads::{Crossroads, IfaceBuilder, IfaceToken};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
 
-use crate::mesh::{PATH, SERVICE_NAME, TIMEOUT};
+use crate::mesh::{ProvisioningError, ReqError, ReqResult, PATH, SERVICE_NAME, TIMEOUT};
 
-use super::application::RegisteredApplication;
+use super::{agent::OobInfo, application::RegisteredApplication};
 
 pub(crate) const INTERFACE: &str = "org.bluez.mesh.Provisioner1";
 
+/// Lowest valid unicast address.
+pub const UNICAST_MIN: u16 = 0x0001;
+/// Highest valid unicast address.
+pub const UNICAST_MAX: u16 = 0x7fff;
+
 /// Definition of Provisioner interface
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Provisioner {
     /// Control handle for provisioner once it has been registered.
     pub control_handle: ProvisionerControlHandle,
+    /// Allocator of unicast addresses for nodes added by this provisioner.
+    pub address_allocator: Arc<dyn AddressAllocator>,
+}
+
+/// Allocator of unicast addresses for nodes being provisioned.
+///
+/// It is called when the mesh daemon requests provisioning data
+/// for a node that is being added to the network.
+pub trait AddressAllocator: Send + Sync + fmt::Debug {
+    /// Allocates a range of `count` consecutive unicast addresses,
+    /// one for each element of the node being provisioned.
+    ///
+    /// Returns the index of the network key the node will be provisioned into
+    /// and the unicast address of the primary element of the node.
+    fn allocate(&self, count: u8) -> ReqResult<(u16, u16)>;
+
+    /// Releases the range of `count` unicast addresses starting at `unicast`
+    /// that has been allocated for a node whose provisioning failed.
+    fn release(&self, unicast: u16, count: u8);
+}
+
+/// Default [AddressAllocator] handing out non-overlapping ranges of unicast addresses.
+///
+/// Addresses of nodes that already belong to the network, including the
+/// provisioner itself, must be [reserved](Self::reserve) before provisioning
+/// new nodes.
+pub struct UnicastAllocator {
+    net_index: u16,
+    range: RangeInclusive<u16>,
+    /// Allocated address ranges as first address mapped to last address.
+    allocated: Mutex<BTreeMap<u16, u16>>,
+}
+
+impl fmt::Debug for UnicastAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnicastAllocator")
+            .field("net_index", &self.net_index)
+            .field("range", &self.range)
+            .field("allocated", &*self.allocated.lock().unwrap())
+            .finish()
+    }
+}
+
+impl UnicastAllocator {
+    /// Creates an allocator using the whole unicast address space
+    /// for nodes provisioned into the network with key index `net_index`.
+    pub fn new(net_index: u16) -> Self {
+        Self::with_range(net_index, UNICAST_MIN..=UNICAST_MAX)
+    }
+
+    /// Creates an allocator handing out addresses from the specified range only.
+    ///
+    /// The range is clamped to valid unicast addresses.
+    pub fn with_range(net_index: u16, range: RangeInclusive<u16>) -> Self {
+        let range = (*range.start()).max(UNICAST_MIN)..=(*range.end()).min(UNICAST_MAX);
+        Self { net_index, range, allocated: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Creates an allocator seeded with already provisioned nodes.
+    ///
+    /// Each node is given by the address of its primary element and its element count.
+    pub fn with_nodes(net_index: u16, nodes: impl IntoIterator<Item = (u16, u8)>) -> Self {
+        let this = Self::new(net_index);
+        for (unicast, count) in nodes {
+            this.reserve(unicast, count);
+        }
+        this
+    }
+
+    /// Marks the addresses of an already provisioned node as used.
+    ///
+    /// `unicast` is the address of the primary element and `count` the number of elements.
+    pub fn reserve(&self, unicast: u16, count: u8) {
+        if count == 0 {
+            return;
+        }
+        let last = unicast.saturating_add(u16::from(count) - 1);
+        self.allocated.lock().unwrap().insert(unicast, last);
+    }
+
+    /// Allocated address ranges.
+    pub fn allocated(&self) -> Vec<RangeInclusive<u16>> {
+        self.allocated.lock().unwrap().iter().map(|(first, last)| *first..=*last).collect()
+    }
 }
 
+impl AddressAllocator for UnicastAllocator {
+    fn allocate(&self, count: u8) -> ReqResult<(u16, u16)> {
+        if count == 0 {
+            return Err(ReqError::Failed);
+        }
+        let count = u16::from(count);
+        let mut allocated = self.allocated.lock().unwrap();
+
+        // Find the first gap between allocated ranges that is large enough.
+        let mut candidate = *self.range.start();
+        for (&first, &last) in allocated.iter() {
+            if last < candidate {
+                continue;
+            }
+            if first >= candidate && first - candidate >= count {
+                break;
+            }
+            candidate = match last.checked_add(1) {
+                Some(next) => next,
+                None => return Err(ReqError::Failed),
+            };
+        }
+
+        match candidate.checked_add(count - 1) {
+            Some(last) if last <= *self.range.end() => {
+                allocated.insert(candidate, last);
+                Ok((self.net_index, candidate))
+            }
+            _ => {
+                log::warn!("No {} consecutive unicast addresses left in {:?}", count, &self.range);
+                Err(ReqError::Failed)
+            }
+        }
+    }
+
+    fn release(&self, unicast: u16, count: u8) {
+        if count == 0 {
+            return;
+        }
+        let last = unicast.saturating_add(u16::from(count) - 1);
+        let mut allocated = self.allocated.lock().unwrap();
+        if allocated.get(&unicast) == Some(&last) {
+            allocated.remove(&unicast);
+        } else {
+            log::warn!("Cannot release unallocated unicast addresses {:04x}..={:04x}", unicast, last);
+        }
+    }
+}
+
+/// A node that has been added to the network by a provisioner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddedNode {
+    /// Device UUID of the node.
+    pub uuid: Uuid,
+    /// Unicast address of the primary element of the node.
+    pub unicast: u16,
+    /// Number of elements of the node.
+    pub count: u8,
+}
+
+/// An unprovisioned device discovered while scanning.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanResult {
+    /// Received signal strength in dBm.
+    pub rssi: i16,
+    /// Device UUID.
+    pub uuid: Uuid,
+    /// Availability of out-of-band information.
+    pub oob_info: OobInfo,
+    /// Hash of the URI of the out-of-band information, if advertised.
+    pub uri_hash: Option<[u8; 4]>,
+}
+
+impl ScanResult {
+    /// Decodes the data of an unprovisioned device beacon,
+    /// consisting of the device UUID, the OOB information and an optional URI hash.
+    fn parse(rssi: i16, data: &[u8]) -> Option<Self> {
+        let uuid = Uuid::from_slice(data.get(..16)?).ok()?;
+        let oob_info = match data.get(16..18) {
+            Some(oob) => OobInfo::from_bits(u16::from_be_bytes([oob[0], oob[1]])),
+            None => OobInfo::default(),
+        };
+        let uri_hash = data.get(18..22).map(|hash| [hash[0], hash[1], hash[2], hash[3]]);
+        Some(Self { rssi, uuid, oob_info, uri_hash })
+    }
+}
+
+type AddNodeResult = std::result::Result<AddedNode, ProvisioningError>;
+
 /// A provisioner exposed over D-Bus to bluez.
 #[derive(Clone)]
 pub struct RegisteredProvisioner {
     inner: Arc<SessionInner>,
     provisioner: Provisioner,
+    add_node_txs: Arc<Mutex<HashMap<Uuid, oneshot::Sender<AddNodeResult>>>>,
+    /// Addresses allocated for the node that is being provisioned.
+    allocated: Arc<Mutex<Option<(u16, u8)>>>,
+    scan_tx: Arc<Mutex<Option<mpsc::UnboundedSender<ScanResult>>>>,
 }
 
 impl RegisteredProvisioner {
-
     pub(crate) fn new(inner: Arc<SessionInner>, provisioner: Provisioner) -> Self {
-        Self { inner, provisioner }
+        Self {
+            inner,
+            provisioner,
+            add_node_txs: Arc::new(Mutex::new(HashMap::new())),
+            allocated: Arc::new(Mutex::new(None)),
+            scan_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Registers interest in scan results, ending the stream of a previous scan.
+    pub(crate) fn start_scan(&self) -> mpsc::UnboundedReceiver<ScanResult> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.scan_tx.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Ends the stream of scan results.
+    pub(crate) fn stop_scan(&self) {
+        self.scan_tx.lock().unwrap().take();
+    }
+
+    /// Registers interest in the outcome of adding the device with the specified UUID.
+    pub(crate) fn expect_node(&self, uuid: Uuid) -> oneshot::Receiver<AddNodeResult> {
+        let (tx, rx) = oneshot::channel();
+        self.add_node_txs.lock().unwrap().insert(uuid, tx);
+        rx
+    }
+
+    /// Removes interest in the outcome of adding the device with the specified UUID.
+    pub(crate) fn forget_node(&self, uuid: &Uuid) {
+        self.add_node_txs.lock().unwrap().remove(uuid);
+    }
+
+    /// Notifies the waiting [add_node](super::management::Management::add_node) call
+    /// and the application of the outcome of adding a node.
+    ///
+    /// The addresses allocated for the node are released if adding it failed.
+    /// If the message channel of the application is full, the message is dropped,
+    /// so that the D-Bus method call is not blocked.
+    fn add_node_result(&self, uuid: Uuid, result: AddNodeResult) {
+        let allocated = self.allocated.lock().unwrap().take();
+        if let (Err(_), Some((unicast, count))) = (&result, allocated) {
+            log::trace!("Releasing {} unicast addresses starting at {:04x}", count, unicast);
+            self.provisioner.address_allocator.release(unicast, count);
+        }
+
+        if let Some(tx) = self.add_node_txs.lock().unwrap().remove(&uuid) {
+            let _ = tx.send(result.clone());
+        }
+
+        let msg = match result {
+            Ok(AddedNode { uuid, unicast, count }) => {
+                ProvisionerMessage::AddNodeComplete { uuid, unicast, count }
+            }
+            Err(reason) => ProvisionerMessage::AddNodeFailed { uuid, reason },
+        };
+        if let Err(err) = self.provisioner.control_handle.messages_tx.try_send(msg) {
+            log::warn!("Cannot deliver provisioner message: {}", err);
+        }
+    }
+
+    fn parse_uuid(uuid: &[u8]) -> ReqResult<Uuid> {
+        Uuid::from_slice(uuid).map_err(|_| {
+            log::error!("Invalid device UUID: {:x?}", uuid);
+            ReqError::Failed
+        })
     }
 
     fn proxy(&self) -> Proxy<'_, &SyncConnection> {
@@ -44,34 +299,82 @@ impl RegisteredProvisioner {
 
     pub(crate) fn register_interface(cr: &mut Crossroads) -> IfaceToken<Arc<RegisteredApplication>> {
         cr.register(INTERFACE, |ib: &mut IfaceBuilder<Arc<RegisteredApplication>>| {
-            ib.method_with_cr_async("AddNodeComplete", ("uuid", "unicast", "count"), (), |ctx, cr, (_uuid, _unicast, _count,): (Vec<u16>, u16, u8)| {
-                method_call(ctx, cr, move |_reg: Arc<RegisteredApplication>| async move {
-                    println!("AddNodeComplete");
-                    Ok(())
-                })
-            });
-            ib.method_with_cr_async("AddNodeFailed", ("uuid", "reason",), (), |ctx, cr, (_uuid, _reason,): (Vec<u16>, String,)| {
-                method_call(ctx, cr, move |_reg: Arc<RegisteredApplication>| async move {
-                    println!("AddNodeFailed");
-                    Ok(())
-                })
-            });
-            ib.method_with_cr_async("RequestProvData", ("count",), ("net_index", "unicast"), |ctx, cr, (_count,): (u8,)| {
-                method_call(ctx, cr, move |_reg: Arc<RegisteredApplication>| async move {
-                    println!("RequestProvData");
+            ib.method_with_cr_async(
+                "AddNodeComplete",
+                ("uuid", "unicast", "count"),
+                (),
+                |ctx, cr, (uuid, unicast, count): (Vec<u8>, u16, u8)| {
+                    method_call(ctx, cr, move |reg: Arc<RegisteredApplication>| async move {
+                        let prov = reg.provisioner.as_ref().ok_or(ReqError::NotSupported)?;
+                        let uuid = Self::parse_uuid(&uuid)?;
+                        prov.add_node_result(uuid, Ok(AddedNode { uuid, unicast, count }));
+                        Ok(())
+                    })
+                },
+            );
+            ib.method_with_cr_async(
+                "AddNodeFailed",
+                ("uuid", "reason"),
+                (),
+                |ctx, cr, (uuid, reason): (Vec<u8>, String)| {
+                    method_call(ctx, cr, move |reg: Arc<RegisteredApplication>| async move {
+                        let prov = reg.provisioner.as_ref().ok_or(ReqError::NotSupported)?;
+                        let uuid = Self::parse_uuid(&uuid)?;
+                        prov.add_node_result(uuid, Err(ProvisioningError::from_reason(reason)));
+                        Ok(())
+                    })
+                },
+            );
+            ib.method_with_cr_async(
+                "RequestProvData",
+                ("count",),
+                ("net_index", "unicast"),
+                |ctx, cr, (count,): (u8,)| {
+                    method_call(ctx, cr, move |reg: Arc<RegisteredApplication>| async move {
+                        let prov = reg.provisioner.as_ref().ok_or(ReqError::NotSupported)?;
+                        let (net_index, unicast) = prov.provisioner.address_allocator.allocate(count)?;
+                        log::trace!(
+                            "Allocated {} unicast addresses starting at {:04x} in network {}",
+                            count,
+                            unicast,
+                            net_index
+                        );
+                        *prov.allocated.lock().unwrap() = Some((unicast, count));
+                        Ok((net_index, unicast))
+                    })
+                },
+            );
+            ib.method_with_cr_async(
+                "ScanResult",
+                ("rssi", "data", "options"),
+                (),
+                |ctx, cr, (rssi, data, _options): (i16, Vec<u8>, PropMap)| {
+                    method_call(ctx, cr, move |reg: Arc<RegisteredApplication>| async move {
+                        let prov = reg.provisioner.as_ref().ok_or(ReqError::NotSupported)?;
+                        let result = ScanResult::parse(rssi, &data).ok_or_else(|| {
+                            log::error!("Invalid unprovisioned device beacon: {:x?}", &data);
+                            ReqError::Failed
+                        })?;
+                        log::trace!("Scan result: {:?}", &result);
 
-                    Ok((0x000 as u16, 0x0bd as u16))
-                })
-            });
+                        let mut scan_tx = prov.scan_tx.lock().unwrap();
+                        if let Some(tx) = scan_tx.as_ref() {
+                            if tx.send(result).is_err() {
+                                scan_tx.take();
+                            }
+                        }
+                        Ok(())
+                    })
+                },
+            );
             cr_property!(ib, "VersionID", _reg => {
                 Some(0x0001 as u16)
             });
         })
     }
-
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 /// A handle to store inside a provisioner definition to make it controllable
 /// once it has been registered.
 pub struct ProvisionerControlHandle {
@@ -82,5 +385,93 @@ pub struct ProvisionerControlHandle {
 #[derive(Clone, Debug)]
 ///Messages sent by provisioner
 pub enum ProvisionerMessage {
+    /// A node has been added to the network.
+    AddNodeComplete {
+        /// Device UUID of the node.
+        uuid: Uuid,
+        /// Unicast address of the primary element of the node.
+        unicast: u16,
+        /// Number of elements of the node.
+        count: u8,
+    },
+    /// Adding a node to the network failed.
+    AddNodeFailed {
+        /// Device UUID of the node.
+        uuid: Uuid,
+        /// Reason for the failure.
+        reason: ProvisioningError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-}
\ No newline at end of file
+    #[test]
+    fn allocate_fills_gaps() {
+        let allocator = UnicastAllocator::with_range(0, 0x0001..=0x0010);
+        allocator.reserve(0x0001, 2);
+        allocator.reserve(0x0005, 1);
+        allocator.reserve(0x0009, 4);
+
+        assert_eq!(allocator.allocate(2).unwrap(), (0, 0x0003));
+        assert_eq!(allocator.allocate(3).unwrap(), (0, 0x0006));
+        assert_eq!(allocator.allocate(1).unwrap(), (0, 0x000d));
+        assert_eq!(allocator.allocate(3).unwrap(), (0, 0x000e));
+        assert!(allocator.allocate(1).is_err());
+        assert_eq!(
+            allocator.allocated(),
+            [
+                0x0001..=0x0002,
+                0x0003..=0x0004,
+                0x0005..=0x0005,
+                0x0006..=0x0008,
+                0x0009..=0x000c,
+                0x000d..=0x000d,
+                0x000e..=0x0010
+            ]
+        );
+    }
+
+    #[test]
+    fn allocate_skips_too_small_gaps() {
+        let allocator = UnicastAllocator::with_range(1, 0x0100..=0x01ff);
+        allocator.reserve(0x0100, 1);
+        allocator.reserve(0x0103, 1);
+
+        assert_eq!(allocator.allocate(3).unwrap(), (1, 0x0104));
+        assert_eq!(allocator.allocate(2).unwrap(), (1, 0x0101));
+        assert!(allocator.allocate(0).is_err());
+    }
+
+    #[test]
+    fn allocate_respects_range() {
+        let allocator = UnicastAllocator::with_range(0, 0x0000..=0x0003);
+        assert_eq!(allocator.allocate(3).unwrap(), (0, UNICAST_MIN));
+        assert!(allocator.allocate(1).is_err());
+
+        let allocator = UnicastAllocator::with_range(0, 0x7ffe..=0xffff);
+        assert!(allocator.allocate(3).is_err());
+        assert_eq!(allocator.allocate(2).unwrap(), (0, 0x7ffe));
+    }
+
+    #[test]
+    fn with_nodes_reserves_addresses() {
+        let allocator = UnicastAllocator::with_nodes(0, [(0x0001, 1), (0x0002, 3), (0x0010, 0)]);
+        assert_eq!(allocator.allocated(), [0x0001..=0x0001, 0x0002..=0x0004]);
+        assert_eq!(allocator.allocate(1).unwrap(), (0, 0x0005));
+    }
+
+    #[test]
+    fn release_frees_addresses() {
+        let allocator = UnicastAllocator::with_nodes(0, [(0x0001, 1)]);
+        let (_, unicast) = allocator.allocate(2).unwrap();
+        assert_eq!(unicast, 0x0002);
+
+        allocator.release(unicast, 1);
+        assert_eq!(allocator.allocated(), [0x0001..=0x0001, 0x0002..=0x0003]);
+        allocator.release(unicast, 2);
+        assert_eq!(allocator.allocated(), [0x0001..=0x0001]);
+        assert_eq!(allocator.allocate(2).unwrap(), (0, 0x0002));
+    }
+}
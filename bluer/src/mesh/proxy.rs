@@ -0,0 +1,1209 @@
+//! Bluetooth mesh proxy client.
+//!
+//! The proxy client connects to a mesh network through the Mesh Proxy Service
+//! of a proxy node using the [remote GATT API](crate::gatt::remote).
+//! It does not require the Bluetooth mesh daemon; instead it is supplied with
+//! the network, application and device keys and acts as a node with a single
+//! element at the configured unicast address.
+//!
+//! Received access messages are provided as [ElementEvent]s, like the events
+//! of an [ElementControl](super::ElementControl).
+//! Since the proxy node only forwards messages whose destination is in its
+//! proxy filter, the client must [add](ProxyClient::add_filter_addresses) its
+//! own address and the group addresses it is interested in.
+//!
+//! The IV update and key refresh procedures are not supported.
+//! The sequence number must be [saved](ProxyClient::seq) by the application
+//! and used as the starting [sequence number](ProxyConfig::seq) of the next
+//! connection to avoid the replay protection of other nodes.
+
+use futures::{channel::oneshot, Stream};
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    time::timeout,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::{
+    gatt::{remote::Characteristic, CharacteristicReader, CharacteristicWriter},
+    mesh::{
+        crypto::{self, NONCE_LEN},
+        management::Key,
+        AccessPayload, Destination, DevKeyMessage, ElementEvent, ElementMessage, Message,
+    },
+    Device, Error, ErrorKind, Result,
+};
+
+/// Mesh Proxy Service UUID.
+pub const SERVICE_UUID: Uuid = Uuid::from_u128(0x00001828_0000_1000_8000_00805f9b34fb);
+/// Mesh Proxy Data In characteristic UUID.
+pub const DATA_IN_UUID: Uuid = Uuid::from_u128(0x00002add_0000_1000_8000_00805f9b34fb);
+/// Mesh Proxy Data Out characteristic UUID.
+pub const DATA_OUT_UUID: Uuid = Uuid::from_u128(0x00002ade_0000_1000_8000_00805f9b34fb);
+
+/// Proxy PDU message type of network PDUs.
+const PDU_NETWORK: u8 = 0x00;
+/// Proxy PDU message type of mesh beacons.
+const PDU_BEACON: u8 = 0x01;
+/// Proxy PDU message type of proxy configuration messages.
+const PDU_PROXY_CONFIGURATION: u8 = 0x02;
+
+/// Segmentation and reassembly field of a proxy PDU.
+const SAR_COMPLETE: u8 = 0b00;
+const SAR_FIRST: u8 = 0b01;
+const SAR_CONTINUATION: u8 = 0b10;
+const SAR_LAST: u8 = 0b11;
+
+const NONCE_NETWORK: u8 = 0x00;
+const NONCE_APPLICATION: u8 = 0x01;
+const NONCE_DEVICE: u8 = 0x02;
+const NONCE_PROXY: u8 = 0x03;
+
+const FILTER_SET_TYPE: u8 = 0x00;
+const FILTER_ADD_ADDRESSES: u8 = 0x01;
+const FILTER_REMOVE_ADDRESSES: u8 = 0x02;
+const FILTER_STATUS: u8 = 0x03;
+
+/// Opcode of the segment acknowledgment control message.
+const SEGMENT_ACK: u8 = 0x00;
+
+/// Largest sequence number.
+const SEQ_MAX: u32 = 0x00ff_ffff;
+
+/// Maximum size of an unsegmented upper transport access PDU.
+const MAX_UNSEGMENTED: usize = 15;
+/// Size of a segment of an upper transport access PDU.
+const SEGMENT_SIZE: usize = 12;
+/// Maximum number of segments.
+const MAX_SEGMENTS: usize = 32;
+
+/// Number of times unacknowledged segments are sent.
+const SEGMENT_ATTEMPTS: usize = 4;
+/// Time after which an incomplete segmented message is discarded.
+const INCOMPLETE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time to wait for the status of a proxy configuration message.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration of a [ProxyClient].
+#[derive(Clone)]
+pub struct ProxyConfig {
+    /// Unicast address of the client.
+    ///
+    /// It must be assigned to the client and not be used by any other node.
+    pub address: u16,
+    /// Network key.
+    pub net_key: Key,
+    /// Index of the network key, reported in received device key messages.
+    pub net_index: u16,
+    /// Current IV index of the network.
+    pub iv_index: u32,
+    /// Sequence number of the first message sent.
+    pub seq: u32,
+    /// TTL of sent messages.
+    pub ttl: u8,
+    /// Application keys by index.
+    pub app_keys: HashMap<u16, Key>,
+    /// Device keys of remote nodes by unicast address of their primary element.
+    pub dev_keys: HashMap<u16, Key>,
+    /// Label UUIDs of the virtual addresses messages are received from.
+    pub labels: Vec<Uuid>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            address: 0,
+            net_key: [0; 16],
+            net_index: 0,
+            iv_index: 0,
+            seq: 0,
+            ttl: 7,
+            app_keys: HashMap::new(),
+            dev_keys: HashMap::new(),
+            labels: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("address", &self.address)
+            .field("net_index", &self.net_index)
+            .field("iv_index", &self.iv_index)
+            .field("seq", &self.seq)
+            .field("ttl", &self.ttl)
+            .field("app_keys", &self.app_keys.keys())
+            .field("dev_keys", &self.dev_keys.keys())
+            .field("labels", &self.labels)
+            .finish()
+    }
+}
+
+/// Type of the proxy filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterType {
+    /// Only messages to addresses in the filter list are forwarded.
+    Accept,
+    /// Messages to addresses in the filter list are not forwarded.
+    Reject,
+}
+
+/// Status of the proxy filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterStatus {
+    /// Filter type.
+    pub filter_type: FilterType,
+    /// Number of addresses in the filter list.
+    pub list_size: u16,
+}
+
+/// Keys derived from the network key.
+struct NetworkKeys {
+    nid: u8,
+    encryption: Key,
+    privacy: Key,
+}
+
+impl NetworkKeys {
+    fn new(net_key: &Key) -> Self {
+        let (nid, encryption, privacy) = crypto::k2(net_key);
+        Self { nid, encryption, privacy }
+    }
+
+    /// Encrypts and obfuscates a network PDU.
+    fn encrypt_network(&self, kind: u8, pdu: &NetworkPdu) -> Vec<u8> {
+        let ctl_ttl = if pdu.ctl { 0x80 } else { 0x00 } | (pdu.ttl & 0x7f);
+        let second = if kind == NONCE_PROXY { 0x00 } else { ctl_ttl };
+        let nonce = nonce(kind, second, pdu.seq, pdu.src, 0, pdu.iv_index);
+
+        let mut plain = pdu.dst.to_be_bytes().to_vec();
+        plain.extend_from_slice(&pdu.transport);
+        let encrypted = crypto::aes_ccm_encrypt(&self.encryption, &nonce, &[], &plain, net_mic_len(pdu.ctl));
+
+        let mut header = [0; 6];
+        header[0] = ctl_ttl;
+        header[1..4].copy_from_slice(&pdu.seq.to_be_bytes()[1..]);
+        header[4..6].copy_from_slice(&pdu.src.to_be_bytes());
+        let pecb = self.pecb(pdu.iv_index, &encrypted);
+
+        let mut data = vec![((pdu.iv_index & 1) as u8) << 7 | self.nid];
+        data.extend(header.iter().zip(pecb).map(|(h, p)| h ^ p));
+        data.extend_from_slice(&encrypted);
+        data
+    }
+
+    /// Deobfuscates and decrypts a network PDU.
+    fn decrypt_network(&self, kind: u8, current_iv_index: u32, data: &[u8]) -> Option<NetworkPdu> {
+        if data.len() < 14 || data[0] & 0x7f != self.nid {
+            return None;
+        }
+        let ivi = u32::from(data[0] >> 7);
+        let iv_index =
+            if current_iv_index & 1 == ivi { current_iv_index } else { current_iv_index.checked_sub(1)? };
+
+        let encrypted = &data[7..];
+        let pecb = self.pecb(iv_index, encrypted);
+        let header: Vec<u8> = data[1..7].iter().zip(pecb).map(|(h, p)| h ^ p).collect();
+        let ctl = header[0] & 0x80 != 0;
+        let ttl = header[0] & 0x7f;
+        let seq = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        let src = u16::from_be_bytes([header[4], header[5]]);
+
+        let second = if kind == NONCE_PROXY { 0x00 } else { header[0] };
+        let nonce = nonce(kind, second, seq, src, 0, iv_index);
+        let plain = crypto::aes_ccm_decrypt(&self.encryption, &nonce, &[], encrypted, net_mic_len(ctl))?;
+        if plain.len() < 2 {
+            return None;
+        }
+        let dst = u16::from_be_bytes([plain[0], plain[1]]);
+
+        Some(NetworkPdu { ctl, ttl, seq, src, dst, iv_index, transport: plain[2..].to_vec() })
+    }
+
+    /// Privacy key stream used for obfuscating the network header.
+    fn pecb(&self, iv_index: u32, encrypted: &[u8]) -> [u8; 16] {
+        let mut privacy_plain = [0; 16];
+        privacy_plain[5..9].copy_from_slice(&iv_index.to_be_bytes());
+        let random_len = encrypted.len().min(7);
+        privacy_plain[9..9 + random_len].copy_from_slice(&encrypted[..random_len]);
+        crypto::e(&self.privacy, &privacy_plain)
+    }
+}
+
+/// Decrypted network PDU.
+struct NetworkPdu {
+    ctl: bool,
+    ttl: u8,
+    seq: u32,
+    src: u16,
+    dst: u16,
+    iv_index: u32,
+    transport: Vec<u8>,
+}
+
+/// State shared between the client and its receive task.
+struct Shared {
+    config: ProxyConfig,
+    keys: NetworkKeys,
+    /// Application key indices by application key identifier.
+    aids: HashMap<u8, Vec<u16>>,
+    writer: CharacteristicWriter,
+    /// Serializes the segments of proxy PDUs sent through the writer.
+    send_lock: tokio::sync::Mutex<()>,
+    seq: AtomicU32,
+    filter_waiters: Mutex<Vec<oneshot::Sender<FilterStatus>>>,
+    /// Block acknowledgments of outgoing segmented messages by SeqZero.
+    acks: Mutex<HashMap<u16, watch::Sender<u32>>>,
+}
+
+impl Shared {
+    fn next_seq(&self) -> Result<u32> {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        if seq > SEQ_MAX {
+            return Err(Error::new(ErrorKind::NotPermitted));
+        }
+        Ok(seq)
+    }
+
+    /// Sends a proxy PDU, segmenting it according to the MTU.
+    ///
+    /// The segments of concurrently sent proxy PDUs are not interleaved.
+    async fn send_proxy_pdu(&self, pdu_type: u8, data: &[u8]) -> Result<()> {
+        let _guard = self.send_lock.lock().await;
+        for pdu in segment_proxy_pdu(pdu_type, data, self.writer.mtu()) {
+            self.writer.send(&pdu).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a lower transport PDU in a network PDU.
+    async fn send_transport(&self, ctl: bool, dst: u16, seq: u32, transport: Vec<u8>) -> Result<()> {
+        let pdu = NetworkPdu {
+            ctl,
+            ttl: self.config.ttl,
+            seq,
+            src: self.config.address,
+            dst,
+            iv_index: self.config.iv_index,
+            transport,
+        };
+        let data = self.keys.encrypt_network(NONCE_NETWORK, &pdu);
+        self.send_proxy_pdu(PDU_NETWORK, &data).await
+    }
+
+    /// Sends a proxy configuration message and waits for the filter status.
+    async fn send_proxy_configuration(&self, opcode: u8, parameters: &[u8]) -> Result<FilterStatus> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut waiters = self.filter_waiters.lock().unwrap();
+            waiters.retain(|tx| !tx.is_canceled());
+            waiters.push(tx);
+        }
+
+        let sent = async {
+            let mut transport = vec![opcode];
+            transport.extend_from_slice(parameters);
+            let pdu = NetworkPdu {
+                ctl: true,
+                ttl: 0,
+                seq: self.next_seq()?,
+                src: self.config.address,
+                dst: 0,
+                iv_index: self.config.iv_index,
+                transport,
+            };
+            let data = self.keys.encrypt_network(NONCE_PROXY, &pdu);
+            self.send_proxy_pdu(PDU_PROXY_CONFIGURATION, &data).await
+        }
+        .await;
+        if let Err(err) = sent {
+            drop(rx);
+            self.filter_waiters.lock().unwrap().retain(|tx| !tx.is_canceled());
+            return Err(err);
+        }
+
+        match timeout(FILTER_TIMEOUT, rx).await {
+            Ok(Ok(status)) => Ok(status),
+            _ => Err(Error::new(ErrorKind::NoResponse)),
+        }
+    }
+
+    /// Encrypts an access message and sends it using the lower transport layer.
+    async fn send_access(&self, dst: Destination, key: &Key, aid: Option<u8>, access: &[u8]) -> Result<()> {
+        let seq = self.next_seq()?;
+        let dst_addr = dst.address();
+        let kind = if aid.is_some() { NONCE_APPLICATION } else { NONCE_DEVICE };
+        let nonce = nonce(kind, 0x00, seq, self.config.address, dst_addr, self.config.iv_index);
+        let aad = match &dst {
+            Destination::Virtual(label) => label.as_bytes().to_vec(),
+            _ => Vec::new(),
+        };
+        let encrypted = crypto::aes_ccm_encrypt(key, &nonce, &aad, access, 4);
+        let header = match aid {
+            Some(aid) => 0x40 | aid,
+            None => 0x00,
+        };
+
+        if encrypted.len() <= MAX_UNSEGMENTED {
+            let mut transport = vec![header];
+            transport.extend_from_slice(&encrypted);
+            return self.send_transport(false, dst_addr, seq, transport).await;
+        }
+
+        let segments: Vec<_> = encrypted.chunks(SEGMENT_SIZE).collect();
+        if segments.len() > MAX_SEGMENTS {
+            return Err(Error::new(ErrorKind::InvalidLength));
+        }
+        let seg_n = (segments.len() - 1) as u8;
+        let seq_zero = (seq & 0x1fff) as u16;
+        let segment_pdu = |seg_o: u8| {
+            let header = SegmentHeader {
+                akf: aid.is_some(),
+                aid: aid.unwrap_or_default(),
+                szmic: false,
+                seq_zero,
+                seg_o,
+                seg_n,
+            };
+            let mut transport = header.emit().to_vec();
+            transport.extend_from_slice(segments[usize::from(seg_o)]);
+            transport
+        };
+
+        if !matches!(dst, Destination::Unicast(_)) {
+            for seg_o in 0..=seg_n {
+                let seg_seq = if seg_o == 0 { seq } else { self.next_seq()? };
+                self.send_transport(false, dst_addr, seg_seq, segment_pdu(seg_o)).await?;
+            }
+            return Ok(());
+        }
+
+        let (ack_tx, mut ack_rx) = watch::channel(0u32);
+        self.acks.lock().unwrap().insert(seq_zero, ack_tx);
+        let all = block_ack_all(seg_n);
+        let ack_timeout = Duration::from_millis(200 + 50 * u64::from(self.config.ttl));
+
+        let res = async {
+            let mut first = true;
+            for _ in 0..SEGMENT_ATTEMPTS {
+                let acked = *ack_rx.borrow();
+                for seg_o in (0..=seg_n).filter(|seg_o| acked & (1 << seg_o) == 0) {
+                    let seg_seq = if first && seg_o == 0 { seq } else { self.next_seq()? };
+                    self.send_transport(false, dst_addr, seg_seq, segment_pdu(seg_o)).await?;
+                }
+                first = false;
+
+                let deadline = Instant::now() + ack_timeout;
+                while let Ok(Ok(())) =
+                    timeout(deadline.saturating_duration_since(Instant::now()), ack_rx.changed()).await
+                {
+                    match *ack_rx.borrow() {
+                        acked if acked & all == all => return Ok(()),
+                        0 => return Err(Error::new(ErrorKind::Failed)),
+                        _ => (),
+                    }
+                }
+            }
+            Err(Error::new(ErrorKind::NoResponse))
+        }
+        .await;
+
+        self.acks.lock().unwrap().remove(&seq_zero);
+        res
+    }
+}
+
+/// Splits a proxy PDU into proxy PDU segments fitting into the MTU.
+fn segment_proxy_pdu(pdu_type: u8, data: &[u8], mtu: usize) -> Vec<Vec<u8>> {
+    let chunk_len = mtu.saturating_sub(1).max(1);
+    if data.len() <= chunk_len {
+        let mut pdu = vec![SAR_COMPLETE << 6 | pdu_type];
+        pdu.extend_from_slice(data);
+        return vec![pdu];
+    }
+
+    let chunks: Vec<_> = data.chunks(chunk_len).collect();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(n, chunk)| {
+            let sar = match n {
+                0 => SAR_FIRST,
+                n if n == chunks.len() - 1 => SAR_LAST,
+                _ => SAR_CONTINUATION,
+            };
+            let mut pdu = vec![sar << 6 | pdu_type];
+            pdu.extend_from_slice(chunk);
+            pdu
+        })
+        .collect()
+}
+
+/// Reassembles proxy PDUs from their segments.
+#[derive(Default)]
+struct ProxyReassembler {
+    buf: Option<(u8, Vec<u8>)>,
+}
+
+impl ProxyReassembler {
+    /// Processes a received proxy PDU segment.
+    ///
+    /// Returns the message type and data of the proxy PDU once it is complete.
+    fn push(&mut self, data: &[u8]) -> Option<(u8, Vec<u8>)> {
+        let (sar, pdu_type, payload) = (data.first()? >> 6, data[0] & 0x3f, &data[1..]);
+        match sar {
+            SAR_COMPLETE => Some((pdu_type, payload.to_vec())),
+            SAR_FIRST => {
+                self.buf = Some((pdu_type, payload.to_vec()));
+                None
+            }
+            _ => match &mut self.buf {
+                Some((buf_type, buf)) if *buf_type == pdu_type => {
+                    buf.extend_from_slice(payload);
+                    if sar == SAR_LAST {
+                        self.buf.take()
+                    } else {
+                        None
+                    }
+                }
+                _ => {
+                    log::debug!("Discarding unexpected mesh proxy PDU segment");
+                    self.buf = None;
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Header of a segmented lower transport access PDU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SegmentHeader {
+    akf: bool,
+    aid: u8,
+    szmic: bool,
+    seq_zero: u16,
+    seg_o: u8,
+    seg_n: u8,
+}
+
+impl SegmentHeader {
+    fn parse(transport: &[u8]) -> Option<Self> {
+        match *transport {
+            [h0, h1, h2, h3, ..] if h0 & 0x80 != 0 => Some(Self {
+                akf: h0 & 0x40 != 0,
+                aid: h0 & 0x3f,
+                szmic: h1 & 0x80 != 0,
+                seq_zero: u16::from_be_bytes([h1, h2]) >> 2 & 0x1fff,
+                seg_o: (h2 & 0x03) << 3 | h3 >> 5,
+                seg_n: h3 & 0x1f,
+            }),
+            _ => None,
+        }
+    }
+
+    fn emit(&self) -> [u8; 4] {
+        [
+            0x80 | if self.akf { 0x40 } else { 0x00 } | (self.aid & 0x3f),
+            if self.szmic { 0x80 } else { 0x00 } | (self.seq_zero >> 6 & 0x7f) as u8,
+            ((self.seq_zero & 0x3f) as u8) << 2 | (self.seg_o >> 3 & 0x03),
+            (self.seg_o & 0x07) << 5 | (self.seg_n & 0x1f),
+        ]
+    }
+}
+
+/// Reconstructs the SeqAuth of a segmented message from the sequence number
+/// of one of its segments and the SeqZero field.
+fn seq_auth(seq: u32, seq_zero: u16) -> u32 {
+    seq.wrapping_sub(seq.wrapping_sub(u32::from(seq_zero)) & 0x1fff)
+}
+
+/// Block acknowledgment of all segments of a message with the specified SegN.
+fn block_ack_all(seg_n: u8) -> u32 {
+    if seg_n >= 31 {
+        u32::MAX
+    } else {
+        (1 << (u32::from(seg_n) + 1)) - 1
+    }
+}
+
+/// Encodes a segment acknowledgment control message.
+fn segment_ack(seq_zero: u16, block_ack: u32) -> Vec<u8> {
+    let mut transport = vec![SEGMENT_ACK];
+    transport.extend_from_slice(&(seq_zero << 2).to_be_bytes());
+    transport.extend_from_slice(&block_ack.to_be_bytes());
+    transport
+}
+
+/// Parses a segment acknowledgment control message into SeqZero and block acknowledgment.
+fn parse_segment_ack(transport: &[u8]) -> Option<(u16, u32)> {
+    match *transport {
+        [SEGMENT_ACK, s0, s1, b0, b1, b2, b3] => {
+            Some((u16::from_be_bytes([s0, s1]) >> 2 & 0x1fff, u32::from_be_bytes([b0, b1, b2, b3])))
+        }
+        _ => None,
+    }
+}
+
+/// Nonce of the network, application, device or proxy kind.
+fn nonce(kind: u8, second: u8, seq: u32, src: u16, dst: u16, iv_index: u32) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[0] = kind;
+    nonce[1] = second;
+    nonce[2..5].copy_from_slice(&seq.to_be_bytes()[1..]);
+    nonce[5..7].copy_from_slice(&src.to_be_bytes());
+    nonce[7..9].copy_from_slice(&dst.to_be_bytes());
+    nonce[9..].copy_from_slice(&iv_index.to_be_bytes());
+    nonce
+}
+
+fn net_mic_len(ctl: bool) -> usize {
+    if ctl {
+        8
+    } else {
+        4
+    }
+}
+
+/// Client connected to a mesh network through a proxy node.
+///
+/// Use [ProxyClient::connect] to connect to a proxy node.
+/// The client can be cloned to send messages from multiple tasks.
+#[derive(Clone)]
+pub struct ProxyClient {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for ProxyClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ProxyClient {{ address: {:04x}, seq: {} }}", self.shared.config.address, self.seq())
+    }
+}
+
+impl ProxyClient {
+    /// Connects to the Mesh Proxy Service of a proxy node.
+    ///
+    /// The device must be connected and its services resolved.
+    /// Returns the client and the stream of received messages.
+    pub async fn connect(device: &Device, config: ProxyConfig) -> Result<(Self, ProxyEvents)> {
+        for service in device.services().await? {
+            if service.uuid().await? != SERVICE_UUID {
+                continue;
+            }
+
+            let (mut data_in, mut data_out) = (None, None);
+            for characteristic in service.characteristics().await? {
+                match characteristic.uuid().await? {
+                    DATA_IN_UUID => data_in = Some(characteristic),
+                    DATA_OUT_UUID => data_out = Some(characteristic),
+                    _ => (),
+                }
+            }
+            if let (Some(data_in), Some(data_out)) = (data_in, data_out) {
+                return Self::new(&data_in, &data_out, config).await;
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound))
+    }
+
+    /// Creates a client using the Mesh Proxy Data In and Data Out characteristics of a proxy node.
+    ///
+    /// Returns the client and the stream of received messages.
+    pub async fn new(
+        data_in: &Characteristic, data_out: &Characteristic, config: ProxyConfig,
+    ) -> Result<(Self, ProxyEvents)> {
+        let writer = data_in.write_io().await?;
+        let reader = data_out.notify_io().await?;
+
+        let mut aids: HashMap<u8, Vec<u16>> = HashMap::new();
+        for (index, key) in &config.app_keys {
+            aids.entry(crypto::k4(key)).or_default().push(*index);
+        }
+
+        let shared = Arc::new(Shared {
+            seq: AtomicU32::new(config.seq),
+            keys: NetworkKeys::new(&config.net_key),
+            config,
+            aids,
+            writer,
+            send_lock: tokio::sync::Mutex::new(()),
+            filter_waiters: Mutex::new(Vec::new()),
+            acks: Mutex::new(HashMap::new()),
+        });
+
+        let (events_tx, events_rx) = mpsc::channel(32);
+        let receiver = Receiver {
+            shared: shared.clone(),
+            events_tx,
+            reassembly: HashMap::new(),
+            completed: HashMap::new(),
+            replay: HashMap::new(),
+        };
+        tokio::spawn(receiver.run(reader));
+
+        Ok((Self { shared }, ProxyEvents { events_rx: ReceiverStream::new(events_rx) }))
+    }
+
+    /// Unicast address of the client.
+    pub fn address(&self) -> u16 {
+        self.shared.config.address
+    }
+
+    /// Sequence number of the next message sent.
+    ///
+    /// Save it and use it as [ProxyConfig::seq] when connecting again.
+    pub fn seq(&self) -> u32 {
+        self.shared.seq.load(Ordering::SeqCst)
+    }
+
+    /// Sets the type of the proxy filter, clearing its list of addresses.
+    pub async fn set_filter_type(&self, filter_type: FilterType) -> Result<FilterStatus> {
+        let filter_type = match filter_type {
+            FilterType::Accept => 0x00,
+            FilterType::Reject => 0x01,
+        };
+        self.shared.send_proxy_configuration(FILTER_SET_TYPE, &[filter_type]).await
+    }
+
+    /// Adds addresses to the list of the proxy filter.
+    pub async fn add_filter_addresses(&self, addresses: &[u16]) -> Result<FilterStatus> {
+        let parameters: Vec<u8> = addresses.iter().flat_map(|address| address.to_be_bytes()).collect();
+        self.shared.send_proxy_configuration(FILTER_ADD_ADDRESSES, &parameters).await
+    }
+
+    /// Removes addresses from the list of the proxy filter.
+    pub async fn remove_filter_addresses(&self, addresses: &[u16]) -> Result<FilterStatus> {
+        let parameters: Vec<u8> = addresses.iter().flat_map(|address| address.to_be_bytes()).collect();
+        self.shared.send_proxy_configuration(FILTER_REMOVE_ADDRESSES, &parameters).await
+    }
+
+    /// Sends a message to a destination, encrypted with the application key with index `app_key`.
+    ///
+    /// Messages to a unicast address that need segmentation are retransmitted until
+    /// all segments are acknowledged.
+    pub async fn send(&self, destination: Destination, app_key: u16, message: &dyn Message) -> Result<()> {
+        let key =
+            *self.shared.config.app_keys.get(&app_key).ok_or_else(|| Error::new(ErrorKind::InvalidArguments))?;
+        let access = encode(message);
+        self.shared.send_access(destination, &key, Some(crypto::k4(&key)), &access).await
+    }
+
+    /// Sends a message to the node at `destination`, encrypted with its device key.
+    pub async fn dev_key_send(&self, destination: u16, message: &dyn Message) -> Result<()> {
+        let key = *self
+            .shared
+            .config
+            .dev_keys
+            .get(&destination)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidArguments))?;
+        let access = encode(message);
+        self.shared.send_access(Destination::Unicast(destination), &key, None, &access).await
+    }
+}
+
+fn encode(message: &dyn Message) -> Vec<u8> {
+    let mut data = Vec::new();
+    message.opcode().emit(&mut data);
+    message.emit_parameters(&mut data);
+    data
+}
+
+/// Stream of messages received by a [ProxyClient].
+///
+/// The stream ends when the connection to the proxy node is lost.
+#[pin_project]
+pub struct ProxyEvents {
+    #[pin]
+    events_rx: ReceiverStream<ElementEvent>,
+}
+
+impl fmt::Debug for ProxyEvents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ProxyEvents")
+    }
+}
+
+impl Stream for ProxyEvents {
+    type Item = ElementEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context) -> Poll<Option<Self::Item>> {
+        self.project().events_rx.poll_next(cx)
+    }
+}
+
+/// Segmented access message being reassembled.
+struct Reassembly {
+    akf: bool,
+    aid: u8,
+    szmic: bool,
+    dst: u16,
+    iv_index: u32,
+    segments: Vec<Option<Vec<u8>>>,
+    started: Instant,
+}
+
+/// Receive task of a proxy client.
+struct Receiver {
+    shared: Arc<Shared>,
+    events_tx: mpsc::Sender<ElementEvent>,
+    /// Incomplete segmented messages by source and SeqAuth.
+    reassembly: HashMap<(u16, u32), Reassembly>,
+    /// SeqAuth of the last completed segmented message by source.
+    completed: HashMap<u16, u32>,
+    /// Last sequence number by source.
+    replay: HashMap<u16, (u32, u32)>,
+}
+
+impl Receiver {
+    async fn run(mut self, reader: CharacteristicReader) {
+        let mut reassembler = ProxyReassembler::default();
+        loop {
+            let data = match reader.recv().await {
+                Ok(data) if !data.is_empty() => data,
+                Ok(_) => break,
+                Err(err) => {
+                    log::debug!("Mesh proxy connection lost: {}", err);
+                    break;
+                }
+            };
+
+            if let Some((pdu_type, pdu)) = reassembler.push(&data) {
+                if let Err(err) = self.handle_proxy_pdu(pdu_type, &pdu).await {
+                    log::debug!("Handling mesh proxy PDU failed: {}", err);
+                }
+            }
+        }
+    }
+
+    async fn handle_proxy_pdu(&mut self, pdu_type: u8, data: &[u8]) -> Result<()> {
+        match pdu_type {
+            PDU_NETWORK => {
+                if let Some(pdu) =
+                    self.shared.keys.decrypt_network(NONCE_NETWORK, self.shared.config.iv_index, data)
+                {
+                    self.handle_network_pdu(pdu).await?;
+                }
+            }
+            PDU_PROXY_CONFIGURATION => {
+                if let Some(pdu) =
+                    self.shared.keys.decrypt_network(NONCE_PROXY, self.shared.config.iv_index, data)
+                {
+                    if let [FILTER_STATUS, filter_type, s0, s1] = pdu.transport[..] {
+                        let status = FilterStatus {
+                            filter_type: if filter_type == 0 { FilterType::Accept } else { FilterType::Reject },
+                            list_size: u16::from_be_bytes([s0, s1]),
+                        };
+                        let mut waiters = self.shared.filter_waiters.lock().unwrap();
+                        waiters.retain(|tx| !tx.is_canceled());
+                        if !waiters.is_empty() {
+                            let _ = waiters.remove(0).send(status);
+                        }
+                    }
+                }
+            }
+            PDU_BEACON => log::trace!("Ignoring mesh beacon {:x?}", data),
+            other => log::trace!("Ignoring mesh proxy PDU of type {}", other),
+        }
+        Ok(())
+    }
+
+    async fn handle_network_pdu(&mut self, pdu: NetworkPdu) -> Result<()> {
+        let own = self.shared.config.address;
+        if pdu.src == own || pdu.dst == 0 || (pdu.dst < 0x8000 && pdu.dst != own) {
+            return Ok(());
+        }
+
+        match self.replay.get(&pdu.src) {
+            Some((iv_index, seq)) if (pdu.iv_index, pdu.seq) <= (*iv_index, *seq) => return Ok(()),
+            _ => {
+                self.replay.insert(pdu.src, (pdu.iv_index, pdu.seq));
+            }
+        }
+
+        let transport = &pdu.transport;
+        if transport.is_empty() {
+            return Ok(());
+        }
+        let seg = transport[0] & 0x80 != 0;
+
+        if pdu.ctl {
+            if let Some((seq_zero, block_ack)) = parse_segment_ack(transport) {
+                if let Some(tx) = self.shared.acks.lock().unwrap().get(&seq_zero) {
+                    tx.send_if_modified(|acked| {
+                        *acked |= block_ack;
+                        true
+                    });
+                }
+            }
+            return Ok(());
+        }
+
+        let akf = transport[0] & 0x40 != 0;
+        let aid = transport[0] & 0x3f;
+        if !seg {
+            return self.deliver(&pdu, akf, aid, false, pdu.seq, &transport[1..]).await;
+        }
+
+        let SegmentHeader { szmic, seq_zero, seg_o, seg_n, .. } = match SegmentHeader::parse(transport) {
+            Some(header) if transport.len() > 4 && header.seg_o <= header.seg_n => header,
+            _ => return Ok(()),
+        };
+        let seq_auth = seq_auth(pdu.seq, seq_zero);
+
+        if self.completed.get(&pdu.src) == Some(&seq_auth) {
+            return self.ack(&pdu, seq_zero, seg_n).await;
+        }
+
+        let now = Instant::now();
+        self.reassembly.retain(|_, r| now.duration_since(r.started) < INCOMPLETE_TIMEOUT);
+        let reassembly = self.reassembly.entry((pdu.src, seq_auth)).or_insert_with(|| Reassembly {
+            akf,
+            aid,
+            szmic,
+            dst: pdu.dst,
+            iv_index: pdu.iv_index,
+            segments: vec![None; usize::from(seg_n) + 1],
+            started: now,
+        });
+        if reassembly.segments.len() != usize::from(seg_n) + 1 {
+            return Ok(());
+        }
+        reassembly.segments[usize::from(seg_o)] = Some(transport[4..].to_vec());
+        if reassembly.segments.iter().any(Option::is_none) {
+            return Ok(());
+        }
+
+        let reassembly = self.reassembly.remove(&(pdu.src, seq_auth)).unwrap();
+        self.completed.insert(pdu.src, seq_auth);
+        let upper: Vec<u8> = reassembly.segments.into_iter().flatten().flatten().collect();
+        let whole = NetworkPdu { dst: reassembly.dst, iv_index: reassembly.iv_index, ..pdu };
+        self.ack(&whole, seq_zero, seg_n).await?;
+        self.deliver(&whole, reassembly.akf, reassembly.aid, reassembly.szmic, seq_auth, &upper).await
+    }
+
+    /// Acknowledges all segments of a segmented message sent to the client's unicast address.
+    async fn ack(&self, pdu: &NetworkPdu, seq_zero: u16, seg_n: u8) -> Result<()> {
+        if pdu.dst != self.shared.config.address {
+            return Ok(());
+        }
+        let transport = segment_ack(seq_zero, block_ack_all(seg_n));
+        let seq = self.shared.next_seq()?;
+        self.shared.send_transport(true, pdu.src, seq, transport).await
+    }
+
+    /// Decrypts an upper transport access PDU and delivers the access message.
+    async fn deliver(
+        &self, pdu: &NetworkPdu, akf: bool, aid: u8, szmic: bool, seq_auth: u32, upper: &[u8],
+    ) -> Result<()> {
+        let config = &self.shared.config;
+        let mic_len = if szmic { 8 } else { 4 };
+        let second = if szmic { 0x80 } else { 0x00 };
+
+        let event = if akf {
+            let labels: Vec<Option<&Uuid>> = if pdu.dst & 0xc000 == 0x8000 {
+                config
+                    .labels
+                    .iter()
+                    .filter(|label| Destination::Virtual(**label).address() == pdu.dst)
+                    .map(Some)
+                    .collect()
+            } else {
+                vec![None]
+            };
+            let nonce = nonce(NONCE_APPLICATION, second, seq_auth, pdu.src, pdu.dst, pdu.iv_index);
+
+            let mut decrypted = None;
+            'keys: for index in self.shared.aids.get(&aid).into_iter().flatten() {
+                for label in &labels {
+                    let aad = label.map(|label| label.as_bytes().as_slice()).unwrap_or_default();
+                    if let Some(access) =
+                        crypto::aes_ccm_decrypt(&config.app_keys[index], &nonce, aad, upper, mic_len)
+                    {
+                        let dest = match label {
+                            Some(label) => Destination::Virtual(**label),
+                            None => Destination::from_address(pdu.dst).unwrap_or(Destination::Unicast(pdu.dst)),
+                        };
+                        decrypted = Some((*index, dest, access));
+                        break 'keys;
+                    }
+                }
+            }
+            let (key, dest, access) = match decrypted {
+                Some(decrypted) => decrypted,
+                None => {
+                    log::trace!("Cannot decrypt access message from {:04x}", pdu.src);
+                    return Ok(());
+                }
+            };
+            let payload =
+                AccessPayload::parse(&access).map_err(|err| Error::new(ErrorKind::InvalidMessage(err)))?;
+            ElementEvent::Message(ElementMessage { key, src: pdu.src, dest, payload })
+        } else {
+            let key = match config.dev_keys.get(&pdu.src) {
+                Some(key) => key,
+                None => {
+                    log::trace!("No device key for message from {:04x}", pdu.src);
+                    return Ok(());
+                }
+            };
+            let nonce = nonce(NONCE_DEVICE, second, seq_auth, pdu.src, pdu.dst, pdu.iv_index);
+            let access = match crypto::aes_ccm_decrypt(key, &nonce, &[], upper, mic_len) {
+                Some(access) => access,
+                None => {
+                    log::trace!("Cannot decrypt device key message from {:04x}", pdu.src);
+                    return Ok(());
+                }
+            };
+            let payload =
+                AccessPayload::parse(&access).map_err(|err| Error::new(ErrorKind::InvalidMessage(err)))?;
+            ElementEvent::DevKeyMessage(DevKeyMessage {
+                src: pdu.src,
+                remote: true,
+                net_index: config.net_index,
+                payload,
+            })
+        };
+
+        let _ = self.events_tx.send(event).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network_keys() -> NetworkKeys {
+        NetworkKeys::new(&hex::decode("7dd7364cd842ad18c17c2b820c84c3d6").unwrap().try_into().unwrap())
+    }
+
+    /// Message #1 of the sample data of the Mesh Profile specification.
+    #[test]
+    fn network_pdu_sample() {
+        let keys = network_keys();
+        let pdu = NetworkPdu {
+            ctl: true,
+            ttl: 0,
+            seq: 1,
+            src: 0x1201,
+            dst: 0xfffd,
+            iv_index: 0x12345678,
+            transport: hex::decode("034b50057e400000010000").unwrap(),
+        };
+        let data = hex::decode("68eca487516765b5e5bfdacbaf6cb7fb6bff871f035444ce83a670df").unwrap();
+        assert_eq!(keys.encrypt_network(NONCE_NETWORK, &pdu), data);
+
+        for current_iv_index in [0x12345678, 0x12345679] {
+            let decrypted = keys.decrypt_network(NONCE_NETWORK, current_iv_index, &data).unwrap();
+            assert!(decrypted.ctl);
+            assert_eq!(decrypted.ttl, 0);
+            assert_eq!(decrypted.seq, 1);
+            assert_eq!(decrypted.src, 0x1201);
+            assert_eq!(decrypted.dst, 0xfffd);
+            assert_eq!(decrypted.iv_index, 0x12345678);
+            assert_eq!(decrypted.transport, pdu.transport);
+        }
+
+        assert!(keys.decrypt_network(NONCE_PROXY, 0x12345678, &data).is_none());
+        let mut corrupted = data.clone();
+        corrupted[10] ^= 0x01;
+        assert!(keys.decrypt_network(NONCE_NETWORK, 0x12345678, &corrupted).is_none());
+    }
+
+    #[test]
+    fn proxy_pdu_unsegmented() {
+        let pdus = segment_proxy_pdu(PDU_NETWORK, &[1, 2, 3], 20);
+        assert_eq!(pdus, vec![vec![0x00, 1, 2, 3]]);
+
+        let mut reassembler = ProxyReassembler::default();
+        assert_eq!(reassembler.push(&pdus[0]), Some((PDU_NETWORK, vec![1, 2, 3])));
+        assert_eq!(reassembler.push(&[]), None);
+    }
+
+    #[test]
+    fn proxy_pdu_segmented() {
+        let data: Vec<u8> = (0..50).collect();
+        let pdus = segment_proxy_pdu(PDU_PROXY_CONFIGURATION, &data, 20);
+        assert_eq!(pdus.len(), 3);
+        assert_eq!(pdus[0][0], 0x42);
+        assert_eq!(pdus[1][0], 0x82);
+        assert_eq!(pdus[2][0], 0xc2);
+        assert_eq!(pdus.iter().map(Vec::len).collect::<Vec<_>>(), vec![20, 20, 13]);
+
+        let mut reassembler = ProxyReassembler::default();
+        assert_eq!(reassembler.push(&pdus[0]), None);
+        assert_eq!(reassembler.push(&pdus[1]), None);
+        assert_eq!(reassembler.push(&pdus[2]), Some((PDU_PROXY_CONFIGURATION, data.clone())));
+
+        // First and last segment only.
+        let pdus = segment_proxy_pdu(PDU_NETWORK, &data, 40);
+        assert_eq!(pdus.iter().map(|pdu| pdu[0]).collect::<Vec<_>>(), vec![0x40, 0xc0]);
+        assert_eq!(reassembler.push(&pdus[0]), None);
+        assert_eq!(reassembler.push(&pdus[1]), Some((PDU_NETWORK, data)));
+    }
+
+    #[test]
+    fn proxy_pdu_unexpected_segments() {
+        let data: Vec<u8> = (0..50).collect();
+        let pdus = segment_proxy_pdu(PDU_NETWORK, &data, 20);
+        let mut reassembler = ProxyReassembler::default();
+
+        // Continuation without first segment.
+        assert_eq!(reassembler.push(&pdus[1]), None);
+        assert_eq!(reassembler.push(&pdus[2]), None);
+
+        // Segment of different message type discards the message.
+        assert_eq!(reassembler.push(&pdus[0]), None);
+        let mut other = pdus[1].clone();
+        other[0] = SAR_CONTINUATION << 6 | PDU_BEACON;
+        assert_eq!(reassembler.push(&other), None);
+        assert_eq!(reassembler.push(&pdus[2]), None);
+
+        // A new first segment restarts reassembly.
+        assert_eq!(reassembler.push(&pdus[0]), None);
+        assert_eq!(reassembler.push(&pdus[0]), None);
+        assert_eq!(reassembler.push(&pdus[1]), None);
+        assert_eq!(reassembler.push(&pdus[2]), Some((PDU_NETWORK, data)));
+    }
+
+    /// Lower transport PDUs of message #6 of the sample data of the Mesh Profile specification.
+    #[test]
+    fn segment_header_sample() {
+        let header = SegmentHeader { akf: false, aid: 0, szmic: false, seq_zero: 0x09ab, seg_o: 0, seg_n: 1 };
+        assert_eq!(header.emit(), [0x80, 0x26, 0xac, 0x01]);
+        assert_eq!(SegmentHeader::parse(&[0x80, 0x26, 0xac, 0x01, 0xee]), Some(header));
+
+        let header = SegmentHeader { seg_o: 1, ..header };
+        assert_eq!(header.emit(), [0x80, 0x26, 0xac, 0x21]);
+        assert_eq!(SegmentHeader::parse(&[0x80, 0x26, 0xac, 0x21]), Some(header));
+    }
+
+    #[test]
+    fn segment_header_fields() {
+        let header = SegmentHeader { akf: true, aid: 0x26, szmic: true, seq_zero: 0x1fff, seg_o: 31, seg_n: 31 };
+        assert_eq!(header.emit(), [0xe6, 0xff, 0xff, 0xff]);
+        assert_eq!(SegmentHeader::parse(&header.emit()), Some(header));
+
+        let header = SegmentHeader { akf: true, aid: 0x01, szmic: false, seq_zero: 0x0040, seg_o: 8, seg_n: 9 };
+        assert_eq!(header.emit(), [0xc1, 0x01, 0x01, 0x09]);
+        assert_eq!(SegmentHeader::parse(&header.emit()), Some(header));
+
+        assert_eq!(SegmentHeader::parse(&[0x40, 0x26, 0xac, 0x01]), None);
+        assert_eq!(SegmentHeader::parse(&[0x80, 0x26, 0xac]), None);
+    }
+
+    #[test]
+    fn seq_auth_reconstruction() {
+        assert_eq!(seq_auth(0x3129ab, 0x09ab), 0x3129ab);
+        assert_eq!(seq_auth(0x3129ad, 0x09ab), 0x3129ab);
+        assert_eq!(seq_auth(0x002001, 0x1fff), 0x001fff);
+        assert_eq!(seq_auth(0x003ffe, 0x1ffe), 0x003ffe);
+        assert_eq!(seq_auth(0x000005, 0x0003), 0x000003);
+    }
+
+    #[test]
+    fn block_ack() {
+        assert_eq!(block_ack_all(0), 0x0000_0001);
+        assert_eq!(block_ack_all(1), 0x0000_0003);
+        assert_eq!(block_ack_all(30), 0x7fff_ffff);
+        assert_eq!(block_ack_all(31), 0xffff_ffff);
+
+        let ack = segment_ack(0x09ab, 0x0000_0003);
+        assert_eq!(ack, vec![0x00, 0x26, 0xac, 0x00, 0x00, 0x00, 0x03]);
+        assert_eq!(parse_segment_ack(&ack), Some((0x09ab, 0x0000_0003)));
+
+        // The OBO flag is ignored.
+        assert_eq!(parse_segment_ack(&[0x00, 0xa6, 0xac, 0x00, 0x00, 0x00, 0x02]), Some((0x09ab, 0x0000_0002)));
+        assert_eq!(parse_segment_ack(&[0x01, 0x26, 0xac, 0x00, 0x00, 0x00, 0x03]), None);
+        assert_eq!(parse_segment_ack(&ack[..6]), None);
+    }
+
+    /// Creates a writer with the given MTU and a small send buffer, and the receiving end of its socket.
+    fn writer_pair(mtu: usize) -> (CharacteristicWriter, tokio::net::UnixStream) {
+        use std::os::unix::io::FromRawFd;
+
+        let mut fds = [0; 2];
+        let ty = libc::SOCK_SEQPACKET | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+        assert_eq!(unsafe { libc::socketpair(libc::AF_UNIX, ty, 0, fds.as_mut_ptr()) }, 0);
+        let size: libc::c_int = 1;
+        let res = unsafe {
+            libc::setsockopt(
+                fds[0],
+                libc::SOL_SOCKET,
+                libc::SO_SNDBUF,
+                &size as *const _ as *const libc::c_void,
+                std::mem::size_of_val(&size) as _,
+            )
+        };
+        assert_eq!(res, 0);
+        let [tx, rx] = fds.map(|fd| {
+            let stream = unsafe { std::os::unix::net::UnixStream::from_raw_fd(fd) };
+            tokio::net::UnixStream::from_std(stream).unwrap()
+        });
+        (CharacteristicWriter::new(mtu, tx), rx)
+    }
+
+    #[tokio::test]
+    async fn proxy_pdus_sent_concurrently() {
+        let (writer, reader) = writer_pair(8);
+        let shared = Arc::new(Shared {
+            config: ProxyConfig::default(),
+            keys: network_keys(),
+            aids: HashMap::new(),
+            writer,
+            send_lock: tokio::sync::Mutex::new(()),
+            seq: AtomicU32::new(0),
+            filter_waiters: Mutex::new(Vec::new()),
+            acks: Mutex::new(HashMap::new()),
+        });
+
+        let received = tokio::spawn(async move {
+            let mut reassembler = ProxyReassembler::default();
+            let mut received = Vec::new();
+            let mut buf = [0; 8];
+            loop {
+                reader.readable().await.unwrap();
+                match reader.try_read(&mut buf) {
+                    Ok(0) => break received,
+                    Ok(n) => received.extend(reassembler.push(&buf[..n])),
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => (),
+                    Err(err) => panic!("{err}"),
+                }
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let messages: Vec<Vec<u8>> = (0..8).map(|n| vec![n; 30]).collect();
+        let sends: Vec<_> = messages
+            .iter()
+            .map(|data| {
+                let (shared, data) = (shared.clone(), data.clone());
+                tokio::spawn(async move { shared.send_proxy_pdu(PDU_NETWORK, &data).await })
+            })
+            .collect();
+        for send in sends {
+            send.await.unwrap().unwrap();
+        }
+        drop(shared);
+
+        let mut received = received.await.unwrap();
+        received.sort();
+        assert_eq!(received, messages.into_iter().map(|data| (PDU_NETWORK, data)).collect::<Vec<_>>());
+    }
+}
@@ -1,15 +1,152 @@
 use core::fmt::Debug;
-use drogue_device::drivers::ble::mesh::model::Model as ConcreteModel;
-pub use drogue_device::drivers::ble::mesh::{
-    address::{Address, UnicastAddress},
-    app::ApplicationKeyIdentifier,
-    model::{Message as ConcreteMessage, ModelIdentifier},
-    pdu::{
-        access::{AccessPayload, Opcode},
-        ParseError,
-    },
-    InsufficientBuffer,
-};
+use dbus::arg::{cast, RefArg, Variant};
+use uuid::Uuid;
+
+use super::crypto;
+
+/// Reserved one-octet opcode.
+const RESERVED_OPCODE: u8 = 0x7f;
+
+/// Error parsing a mesh message.
+#[derive(Clone, Copy, Debug, displaydoc::Display, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum ParseError {
+    /// invalid message length
+    InvalidLength,
+    /// invalid field value
+    InvalidValue,
+}
+
+impl std::error::Error for ParseError {}
+
+/// Company identifier assigned by the Bluetooth SIG.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompanyIdentifier(pub u16);
+
+/// Identifier of a mesh model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModelIdentifier {
+    /// Model defined by the Bluetooth SIG.
+    SIG(u16),
+    /// Vendor model of the specified company.
+    Vendor(CompanyIdentifier, u16),
+}
+
+/// Opcode of an access layer message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Opcode {
+    /// One-octet opcode of a SIG model.
+    OneOctet(u8),
+    /// Two-octet opcode of a SIG model.
+    TwoOctet(u8, u8),
+    /// Three-octet opcode of a vendor model.
+    ///
+    /// The last two octets are the company identifier in little-endian byte order.
+    ThreeOctet(u8, u8, u8),
+}
+
+impl Opcode {
+    /// Splits an access layer PDU into its opcode and parameters.
+    ///
+    /// The two most significant bits of the first octet determine the opcode length:
+    /// `0x` denotes a one-octet, `10` a two-octet and `11` a three-octet (vendor) opcode.
+    pub fn split(data: &[u8]) -> Result<(Self, &[u8]), ParseError> {
+        match data {
+            [] => Err(ParseError::InvalidLength),
+            [RESERVED_OPCODE, ..] => Err(ParseError::InvalidValue),
+            [o0, rest @ ..] if o0 & 0x80 == 0 => Ok((Self::OneOctet(*o0), rest)),
+            [o0, o1, rest @ ..] if o0 & 0xc0 == 0x80 => Ok((Self::TwoOctet(*o0, *o1), rest)),
+            [o0, o1, o2, rest @ ..] if o0 & 0xc0 == 0xc0 => Ok((Self::ThreeOctet(*o0, *o1, *o2), rest)),
+            _ => Err(ParseError::InvalidLength),
+        }
+    }
+
+    /// Appends the encoded opcode to `xmit`.
+    pub fn emit(&self, xmit: &mut Vec<u8>) {
+        match *self {
+            Self::OneOctet(o0) => xmit.push(o0),
+            Self::TwoOctet(o0, o1) => xmit.extend_from_slice(&[o0, o1]),
+            Self::ThreeOctet(o0, o1, o2) => xmit.extend_from_slice(&[o0, o1, o2]),
+        }
+    }
+}
+
+/// Access layer message consisting of opcode and parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccessPayload {
+    /// Message opcode.
+    pub opcode: Opcode,
+    /// Message parameters.
+    pub parameters: Vec<u8>,
+}
+
+impl AccessPayload {
+    /// Parses an access layer PDU.
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let (opcode, parameters) = Opcode::split(data)?;
+        Ok(Self { opcode, parameters: parameters.to_vec() })
+    }
+
+    /// Encodes the message into an access layer PDU.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.opcode.emit(&mut data);
+        data.extend_from_slice(&self.parameters);
+        data
+    }
+}
+
+/// Destination of a mesh message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Destination {
+    /// Unicast address of an element.
+    Unicast(u16),
+    /// Group address, including the fixed group addresses.
+    Group(u16),
+    /// Virtual address given by its label UUID.
+    Virtual(Uuid),
+}
+
+impl Destination {
+    /// Classifies a 16-bit unicast or group address.
+    ///
+    /// Returns [None] for the unassigned address and virtual addresses,
+    /// since the label UUID of the latter cannot be recovered.
+    pub fn from_address(address: u16) -> Option<Self> {
+        match address {
+            0x0001..=0x7fff => Some(Self::Unicast(address)),
+            0xc000..=0xffff => Some(Self::Group(address)),
+            _ => None,
+        }
+    }
+
+    /// 16-bit destination address.
+    ///
+    /// For a virtual destination this is the virtual address derived from its label UUID.
+    pub fn address(&self) -> u16 {
+        match self {
+            Self::Unicast(address) | Self::Group(address) => *address,
+            Self::Virtual(label) => crypto::virtual_address(label),
+        }
+    }
+
+    /// Parses the destination argument of a received message,
+    /// which is either a 16-bit address or a 16-byte label UUID.
+    pub(crate) fn from_variant(value: &Variant<Box<dyn RefArg + 'static>>) -> Option<Self> {
+        if let Some(address) = cast::<u16>(&value.0) {
+            Self::from_address(*address)
+        } else {
+            let label = cast::<Vec<u8>>(&value.0)?;
+            Uuid::from_slice(label).ok().map(Self::Virtual)
+        }
+    }
+}
 
 /// Bluetooth Mesh Message
 pub trait Message {
@@ -34,83 +171,74 @@ pub trait Model: Sync + Send + Debug {
         Self: Sized + 'm;
 }
 
-/// Bluetooth Mesh Model Message
-pub struct ModelMessage<M> {
-    m: M,
-}
-
-impl<M> Message for ModelMessage<M>
-where
-    M: ConcreteMessage,
-{
-    fn opcode(&self) -> Opcode {
-        self.m.opcode()
-    }
-
-    fn emit_parameters(&self, xmit: &mut Vec<u8>) {
-        let mut v: heapless::Vec<u8, 512> = heapless::Vec::new();
-        self.m.emit_parameters(&mut v).unwrap();
-        xmit.extend_from_slice(&v[..]);
-    }
+/// Element message received from dbus
+#[derive(Clone, Debug)]
+pub struct ElementMessage {
+    /// Index of the application key the message was encrypted with
+    pub key: u16,
+    /// Unicast address of the message source
+    pub src: u16,
+    /// Message destination
+    pub dest: Destination,
+    /// Message payload
+    pub payload: AccessPayload,
 }
 
-/// Converting Drogue to local types
-#[derive(Debug)]
-pub struct FromDrogue<M>
-where
-    M: Debug,
-{
-    _m: core::marker::PhantomData<M>,
+/// Element message encrypted with a device key received from dbus
+#[derive(Clone, Debug)]
+pub struct DevKeyMessage {
+    /// Unicast address of the message source
+    pub src: u16,
+    /// Whether the message was encrypted with the device key of the remote node
+    /// instead of the device key of the local node.
+    pub remote: bool,
+    /// Index of the network key the message was received on
+    pub net_index: u16,
+    /// Message payload
+    pub payload: AccessPayload,
 }
 
-impl<M: Debug> FromDrogue<M> {
-    /// New model
-    pub fn new(_m: M) -> Self {
-        Self { _m: core::marker::PhantomData }
-    }
+/// Event received by an element.
+#[derive(Clone, Debug)]
+pub enum ElementEvent {
+    /// Message encrypted with an application key.
+    Message(ElementMessage),
+    /// Message encrypted with a device key.
+    DevKeyMessage(DevKeyMessage),
 }
 
-unsafe impl<M> Sync for FromDrogue<M> where M: Debug {}
-unsafe impl<M> Send for FromDrogue<M> where M: Debug {}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl<M> Model for FromDrogue<M>
-where
-    M: ConcreteModel + Debug,
-{
-    fn identifier(&self) -> ModelIdentifier {
-        M::IDENTIFIER
-    }
-    fn supports_subscription(&self) -> bool {
-        M::SUPPORTS_SUBSCRIPTION
+    #[test]
+    fn opcode_split() {
+        assert_eq!(Opcode::split(&[0x00]), Ok((Opcode::OneOctet(0x00), &[][..])));
+        assert_eq!(Opcode::split(&[0x7e, 0x01, 0x02]), Ok((Opcode::OneOctet(0x7e), &[0x01, 0x02][..])));
+        assert_eq!(Opcode::split(&[0x82, 0x01]), Ok((Opcode::TwoOctet(0x82, 0x01), &[][..])));
+        assert_eq!(Opcode::split(&[0x80, 0x08, 0x01]), Ok((Opcode::TwoOctet(0x80, 0x08), &[0x01][..])));
+        assert_eq!(Opcode::split(&[0xc1, 0xf1, 0x05]), Ok((Opcode::ThreeOctet(0xc1, 0xf1, 0x05), &[][..])));
+        assert_eq!(
+            Opcode::split(&[0xff, 0x34, 0x12, 0xaa, 0xbb]),
+            Ok((Opcode::ThreeOctet(0xff, 0x34, 0x12), &[0xaa, 0xbb][..]))
+        );
     }
 
-    fn supports_publication(&self) -> bool {
-        M::SUPPORTS_PUBLICATION
+    #[test]
+    fn opcode_split_invalid() {
+        assert_eq!(Opcode::split(&[]), Err(ParseError::InvalidLength));
+        assert_eq!(Opcode::split(&[0x7f]), Err(ParseError::InvalidValue));
+        assert_eq!(Opcode::split(&[0x7f, 0x00]), Err(ParseError::InvalidValue));
+        assert_eq!(Opcode::split(&[0x82]), Err(ParseError::InvalidLength));
+        assert_eq!(Opcode::split(&[0xc1]), Err(ParseError::InvalidLength));
+        assert_eq!(Opcode::split(&[0xc1, 0xf1]), Err(ParseError::InvalidLength));
     }
 
-    fn parse<'m>(opcode: Opcode, parameters: &'m [u8]) -> Result<Option<Box<dyn Message + 'm>>, ParseError>
-    where
-        Self: 'm,
-    {
-        let m = M::parse(opcode, parameters)?;
-        if let Some(m) = m {
-            let b: Box<dyn Message + 'm> = Box::new(ModelMessage { m });
-            Ok(Some(b))
-        } else {
-            Ok(None)
+    #[test]
+    fn access_payload_round_trip() {
+        for data in [&[0x04][..], &[0x82, 0x02, 0x01, 0x05], &[0xc0, 0x59, 0x00, 0xde, 0xad]] {
+            let payload = AccessPayload::parse(data).unwrap();
+            assert_eq!(payload.to_vec(), data);
         }
     }
 }
-
-/// Element message received from dbus
-#[derive(Clone, Debug)]
-pub struct ElementMessage {
-    /// Application key
-    pub key: ApplicationKeyIdentifier,
-    /// Message source
-    pub src: UnicastAddress,
-    /// Message destination
-    pub dest: Address,
-    /// Message payload
-    pub payload: AccessPayload,
-}
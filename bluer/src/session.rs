@@ -34,9 +34,13 @@ use crate::{
     adapter,
     adv::Advertisement,
     agent::{Agent, AgentHandle, RegisteredAgent},
-    all_dbus_objects, gatt,
-    mesh::{application::RegisteredApplication, network::Network, RegisteredElement, provisioner::RegisteredProvisioner, agent::ProvisionAgent},
-    parent_path, Adapter, Error, ErrorKind, InternalErrorKind, Result, SERVICE_NAME,
+    all_dbus_objects, gatt, parent_path, Adapter, Error, ErrorKind, InternalErrorKind, Result, SERVICE_NAME,
+};
+
+#[cfg(feature = "mesh")]
+use crate::mesh::{
+    self, agent::RegisteredProvisionAgent, application::RegisteredApplication, network::Network,
+    provisioner::RegisteredProvisioner, RegisteredElement,
 };
 
 #[cfg(feature = "rfcomm")]
@@ -55,10 +59,16 @@ pub(crate) struct SessionInner {
     pub gatt_reg_characteristic_descriptor_token: IfaceToken<Arc<gatt::local::RegisteredDescriptor>>,
     pub gatt_profile_token: IfaceToken<gatt::local::Profile>,
     pub agent_token: IfaceToken<Arc<RegisteredAgent>>,
+    #[cfg(feature = "mesh")]
     pub application_token: IfaceToken<Arc<RegisteredApplication>>,
+    #[cfg(feature = "mesh")]
     pub element_token: IfaceToken<Arc<RegisteredElement>>,
+    #[cfg(feature = "mesh")]
     pub provisioner_token: IfaceToken<Arc<RegisteredApplication>>,
-    pub provision_agent_token: IfaceToken<Arc<ProvisionAgent>>,
+    #[cfg(feature = "mesh")]
+    pub provision_agent_token: IfaceToken<Arc<RegisteredProvisionAgent>>,
+    #[cfg(feature = "mesh")]
+    pub mesh_applications: Mutex<HashMap<dbus::Path<'static>, Arc<RegisteredApplication>>>,
     #[cfg(feature = "rfcomm")]
     pub profile_token: IfaceToken<Arc<RegisteredProfile>>,
     pub single_sessions: Mutex<HashMap<dbus::Path<'static>, SingleSessionTerm>>,
@@ -177,10 +187,14 @@ impl Session {
         let agent_token = RegisteredAgent::register_interface(&mut crossroads);
         #[cfg(feature = "rfcomm")]
         let profile_token = RegisteredProfile::register_interface(&mut crossroads);
+        #[cfg(feature = "mesh")]
         let application_token = RegisteredApplication::register_interface(&mut crossroads);
+        #[cfg(feature = "mesh")]
         let element_token = RegisteredElement::register_interface(&mut crossroads);
+        #[cfg(feature = "mesh")]
         let provisioner_token = RegisteredProvisioner::register_interface(&mut crossroads);
-        let provision_agent_token = ProvisionAgent::register_interface(&mut crossroads);
+        #[cfg(feature = "mesh")]
+        let provision_agent_token = RegisteredProvisionAgent::register_interface(&mut crossroads);
 
         let (event_sub_tx, event_sub_rx) = mpsc::channel(1);
         Event::handle_connection(connection.clone(), event_sub_rx).await?;
@@ -194,10 +208,16 @@ impl Session {
             gatt_reg_characteristic_descriptor_token,
             gatt_profile_token,
             agent_token,
+            #[cfg(feature = "mesh")]
             application_token,
+            #[cfg(feature = "mesh")]
             element_token,
+            #[cfg(feature = "mesh")]
             provisioner_token,
+            #[cfg(feature = "mesh")]
             provision_agent_token,
+            #[cfg(feature = "mesh")]
+            mesh_applications: Mutex::new(HashMap::new()),
             #[cfg(feature = "rfcomm")]
             profile_token,
             single_sessions: Mutex::new(HashMap::new()),
@@ -262,6 +282,8 @@ impl Session {
     }
 
     /// Create an interface for the Bluetooth mesh network
+    #[cfg(feature = "mesh")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "mesh")))]
     pub async fn mesh(&self) -> Result<Network> {
         Network::new(self.inner.clone()).await
     }
@@ -371,6 +393,11 @@ impl Event {
             static ref SERVICE_NAME_BUS: BusName<'static> = BusName::new(SERVICE_NAME).unwrap();
             static ref SERVICE_NAME_REF: Option<&'static BusName<'static>> = Some(&SERVICE_NAME_BUS);
         }
+        #[cfg(feature = "mesh")]
+        lazy_static! {
+            static ref MESH_SERVICE_NAME_BUS: BusName<'static> = BusName::new(mesh::SERVICE_NAME).unwrap();
+            static ref MESH_SERVICE_NAME_REF: Option<&'static BusName<'static>> = Some(&MESH_SERVICE_NAME_BUS);
+        }
 
         let (msg_tx, mut msg_rx) = mpsc::unbounded();
         let handle_msg = move |msg: Message| {
@@ -387,6 +414,11 @@ impl Event {
         let rule_prop = PropertiesPropertiesChanged::match_rule(*SERVICE_NAME_REF, None);
         let msg_match_prop = connection.add_match(rule_prop).await?.msg_cb(handle_msg.clone());
 
+        #[cfg(feature = "mesh")]
+        let rule_mesh_prop = PropertiesPropertiesChanged::match_rule(*MESH_SERVICE_NAME_REF, None);
+        #[cfg(feature = "mesh")]
+        let msg_match_mesh_prop = connection.add_match(rule_mesh_prop).await?.msg_cb(handle_msg.clone());
+
         tokio::spawn(async move {
             log::trace!("Starting event loop for {}", &connection.unique_name());
 
@@ -495,6 +527,8 @@ impl Event {
             let _ = connection.remove_match(msg_match_add.token()).await;
             let _ = connection.remove_match(msg_match_removed.token()).await;
             let _ = connection.remove_match(msg_match_prop.token()).await;
+            #[cfg(feature = "mesh")]
+            let _ = connection.remove_match(msg_match_mesh_prop.token()).await;
             log::trace!("Terminated event loop for {}", &connection.unique_name());
         });
 
@@ -0,0 +1,91 @@
+//! Tests of `#[derive(GattService)]`.
+
+use bluer::{
+    gatt::local::{CharacteristicValue, GattService},
+    Uuid,
+};
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+struct Battery {
+    #[characteristic(uuid = "2a19", read, notify, descriptor(uuid = "2901", value = "Battery level"))]
+    level: CharacteristicValue<u8>,
+    #[characteristic(
+        uuid = "12345678",
+        write,
+        write_without_response,
+        descriptor(uuid = "2904", value = b"\x04")
+    )]
+    control: CharacteristicValue<Vec<u8>>,
+    #[allow(dead_code)]
+    unexposed: u32,
+}
+
+#[derive(GattService)]
+#[gatt(uuid = "00000000-0000-0000-0000-0000feedc0de", secondary)]
+struct Secondary {
+    #[characteristic(uuid = "0000000000000000000f00dc0de00001", read)]
+    value: CharacteristicValue<u16>,
+}
+
+#[test]
+fn derive_expands_short_uuids() {
+    let battery = Battery {
+        level: CharacteristicValue::new(100),
+        control: CharacteristicValue::new(Vec::new()),
+        unexposed: 0,
+    };
+    let service = battery.service();
+    assert_eq!(service.uuid, Uuid::from_u128(0x0000180f_0000_1000_8000_00805f9b34fb));
+    assert!(service.primary);
+    assert_eq!(service.characteristics.len(), 2);
+
+    let level = &service.characteristics[0];
+    assert_eq!(level.uuid, Uuid::from_u128(0x00002a19_0000_1000_8000_00805f9b34fb));
+    assert_eq!(level.descriptors.len(), 1);
+    assert_eq!(level.descriptors[0].uuid, Uuid::from_u128(0x00002901_0000_1000_8000_00805f9b34fb));
+
+    let control = &service.characteristics[1];
+    assert_eq!(control.uuid, Uuid::from_u128(0x12345678_0000_1000_8000_00805f9b34fb));
+    assert_eq!(control.descriptors[0].uuid, Uuid::from_u128(0x00002904_0000_1000_8000_00805f9b34fb));
+}
+
+#[test]
+fn derive_parses_flags() {
+    let battery = Battery {
+        level: CharacteristicValue::new(100),
+        control: CharacteristicValue::new(Vec::new()),
+        unexposed: 0,
+    };
+    let service = battery.service();
+
+    let level = &service.characteristics[0];
+    let read = level.read.as_ref().unwrap();
+    assert!(read.read);
+    assert!(level.write.is_none());
+    assert!(level.notify.as_ref().unwrap().notify);
+
+    let control = &service.characteristics[1];
+    assert!(control.read.is_none());
+    let write = control.write.as_ref().unwrap();
+    assert!(write.write);
+    assert!(write.write_without_response);
+    assert!(control.notify.is_none());
+}
+
+#[test]
+fn derive_parses_full_uuids_and_secondary() {
+    let service = Secondary { value: CharacteristicValue::new(1) }.service();
+    assert_eq!(service.uuid, Uuid::from_u128(0xfeedc0de));
+    assert!(!service.primary);
+    assert_eq!(service.characteristics[0].uuid, Uuid::from_u128(0x000f_00dc_0de0_0001));
+
+    let app = Secondary { value: CharacteristicValue::new(1) }.application();
+    assert_eq!(app.services.len(), 1);
+}
+
+#[test]
+fn derive_reports_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
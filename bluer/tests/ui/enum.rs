@@ -0,0 +1,9 @@
+use bluer::gatt::local::GattService;
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+enum Battery {
+    Level,
+}
+
+fn main() {}
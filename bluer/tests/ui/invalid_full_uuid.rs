@@ -0,0 +1,10 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+struct Battery {
+    #[characteristic(uuid = "00002a19-0000-1000-8000-00805f9b34fg", read)]
+    level: CharacteristicValue<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+#[gatt(uuid = "180")]
+struct Battery {
+    #[characteristic(uuid = "2a19", read)]
+    level: CharacteristicValue<u8>,
+}
+
+fn main() {}
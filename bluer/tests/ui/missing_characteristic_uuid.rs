@@ -0,0 +1,10 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+struct Battery {
+    #[characteristic(read, notify)]
+    level: CharacteristicValue<u8>,
+}
+
+fn main() {}
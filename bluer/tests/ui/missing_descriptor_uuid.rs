@@ -0,0 +1,10 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+struct Battery {
+    #[characteristic(uuid = "2a19", read, descriptor(value = "Battery level"))]
+    level: CharacteristicValue<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+struct Battery {
+    #[characteristic(uuid = "2a19", read)]
+    level: CharacteristicValue<u8>,
+}
+
+fn main() {}
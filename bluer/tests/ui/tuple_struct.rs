@@ -0,0 +1,7 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+struct Battery(CharacteristicValue<u8>);
+
+fn main() {}
@@ -0,0 +1,10 @@
+use bluer::gatt::local::{CharacteristicValue, GattService};
+
+#[derive(GattService)]
+#[gatt(uuid = "180f")]
+struct Battery {
+    #[characteristic(uuid = "2a19", read, descriptor(uuid = "2901", value = "Battery level", writable))]
+    level: CharacteristicValue<u8>,
+}
+
+fn main() {}